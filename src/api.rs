@@ -1,11 +1,22 @@
 pub mod assets;
 pub mod authentication;
 pub mod authorization;
+pub mod device;
+pub mod introspection;
+pub mod jwks;
+pub mod logout;
+pub mod metadata;
+pub mod pushed_authorization;
+pub mod registration;
+pub mod revocation;
 pub mod token;
+pub mod userinfo;
 
 use authorization::authorization_endpoint;
-use axum::routing::post;
+use axum::http::{HeaderMap, header};
+use axum::routing::{delete, post, put};
 use axum::{Router, routing::get};
+use base64::prelude::*;
 use std::io;
 use std::sync::Arc;
 use tera::Tera;
@@ -14,23 +25,146 @@ use tokio::net::TcpListener;
 
 use crate::api::assets::assets_endpoint;
 use crate::api::authentication::{authentication_get_endpoint, authentication_post_endpoint};
-use crate::repository::client::TestClientRepository;
+use crate::api::device::{
+    device_authorization_endpoint, device_get_endpoint, device_post_endpoint,
+};
+use crate::api::introspection::introspection_endpoint;
+use crate::api::jwks::jwks_endpoint;
+use crate::api::logout::{logout_get_endpoint, logout_post_endpoint};
+use crate::api::metadata::{
+    authorization_server_metadata_endpoint, openid_provider_metadata_endpoint,
+};
+use crate::api::pushed_authorization::pushed_authorization_request_endpoint;
+use crate::api::registration::{
+    client_configuration_delete_endpoint, client_configuration_get_endpoint,
+    client_configuration_put_endpoint, registration_endpoint,
+};
+use crate::api::revocation::revocation_endpoint;
+use crate::api::userinfo::{userinfo_get_endpoint, userinfo_post_endpoint};
+use crate::core::token::TokenSettings;
+use crate::repository::authorization::MapAuthorizationRepository;
+use crate::repository::authorization_code::MapAuthorizationCodeRepository;
+use crate::repository::client::MapClientRepository;
+use crate::repository::device_code::MapDeviceCodeRepository;
+use crate::repository::dpop::MapDpopRepository;
+use crate::repository::key::MapKeyRepository;
+use crate::repository::owner::MapOwnerRepository;
+use crate::repository::pushed_request::MapPushedRequestRepository;
+use crate::repository::refresh_token::MapRefreshTokenRepository;
+use crate::repository::session::MapSessionRepository;
 
 #[derive(Debug)]
 pub struct RouterState {
-    pub client_store: TestClientRepository,
+    pub client_store: MapClientRepository,
+    pub authorization_code_store: MapAuthorizationCodeRepository,
+    pub authorization_store: MapAuthorizationRepository,
+    pub refresh_token_store: MapRefreshTokenRepository,
+    pub device_code_store: MapDeviceCodeRepository,
+    pub pushed_request_store: MapPushedRequestRepository,
+    pub dpop_store: MapDpopRepository,
+    pub key_store: MapKeyRepository,
+    pub owner_store: MapOwnerRepository,
+    pub session_store: MapSessionRepository,
+    pub token_settings: TokenSettings,
+    pub server_settings: ServerSettings,
     pub template_engine: Tera,
 }
 
-pub fn create_router(state: RouterState) -> Router {
+/// Settings describing this server instance towards clients
+#[derive(Clone, Debug, Default)]
+pub struct ServerSettings {
+    /// Scopes advertised in the server metadata
+    pub scopes_supported: Vec<String>,
+    /// Bearer token required to register clients, registration is open to anyone without it
+    pub initial_access_token: Option<String>,
+}
+
+#[cfg(test)]
+impl RouterState {
+    /// State with empty stores for tests, which override the fields they need
+    pub fn for_test() -> Self {
+        Self {
+            client_store: MapClientRepository::default(),
+            authorization_code_store: MapAuthorizationCodeRepository::default(),
+            authorization_store: MapAuthorizationRepository::default(),
+            refresh_token_store: MapRefreshTokenRepository::default(),
+            device_code_store: MapDeviceCodeRepository::default(),
+            pushed_request_store: MapPushedRequestRepository::default(),
+            dpop_store: MapDpopRepository::default(),
+            key_store: MapKeyRepository::default(),
+            owner_store: MapOwnerRepository::default(),
+            session_store: MapSessionRepository::default(),
+            token_settings: TokenSettings {
+                issuer: "https://keyper.example.com".to_string(),
+                ..Default::default()
+            },
+            server_settings: ServerSettings::default(),
+            template_engine: create_template_engine().expect("Could not create template engine"),
+        }
+    }
+}
+
+pub const AUTHORIZATION_PATH: &str = "/authorization";
+pub const TOKEN_PATH: &str = "/token";
+pub const DEVICE_AUTHORIZATION_PATH: &str = "/device_authorization";
+pub const DEVICE_PATH: &str = "/device";
+pub const INTROSPECTION_PATH: &str = "/introspect";
+pub const REVOCATION_PATH: &str = "/revoke";
+pub const JWKS_PATH: &str = "/jwks.json";
+pub const USERINFO_PATH: &str = "/userinfo";
+pub const LOGOUT_PATH: &str = "/logout";
+pub const REGISTRATION_PATH: &str = "/register";
+pub const PUSHED_AUTHORIZATION_REQUEST_PATH: &str = "/par";
+pub const AUTHORIZATION_SERVER_METADATA_PATH: &str = "/.well-known/oauth-authorization-server";
+pub const OPENID_PROVIDER_METADATA_PATH: &str = "/.well-known/openid-configuration";
+
+pub fn create_router(state: Arc<RouterState>) -> Router {
     Router::new()
         .route("/", get(index))
         .route("/assets/:filename", get(assets_endpoint))
         .route("/authentication", get(authentication_get_endpoint))
         .route("/authentication", post(authentication_post_endpoint))
-        .route("/authorization", get(authorization_endpoint))
-        .route("/token", get(token_endpoint))
-        .with_state(Arc::new(state))
+        .route(AUTHORIZATION_PATH, get(authorization_endpoint))
+        .route(TOKEN_PATH, post(token_endpoint))
+        .route(
+            PUSHED_AUTHORIZATION_REQUEST_PATH,
+            post(pushed_authorization_request_endpoint),
+        )
+        .route(
+            DEVICE_AUTHORIZATION_PATH,
+            post(device_authorization_endpoint),
+        )
+        .route(DEVICE_PATH, get(device_get_endpoint))
+        .route(DEVICE_PATH, post(device_post_endpoint))
+        .route(INTROSPECTION_PATH, post(introspection_endpoint))
+        .route(REVOCATION_PATH, post(revocation_endpoint))
+        .route(JWKS_PATH, get(jwks_endpoint))
+        .route(USERINFO_PATH, get(userinfo_get_endpoint))
+        .route(USERINFO_PATH, post(userinfo_post_endpoint))
+        .route(LOGOUT_PATH, get(logout_get_endpoint))
+        .route(LOGOUT_PATH, post(logout_post_endpoint))
+        .route(REGISTRATION_PATH, post(registration_endpoint))
+        .route(
+            &format!("{REGISTRATION_PATH}/:client_id"),
+            get(client_configuration_get_endpoint),
+        )
+        .route(
+            &format!("{REGISTRATION_PATH}/:client_id"),
+            put(client_configuration_put_endpoint),
+        )
+        .route(
+            &format!("{REGISTRATION_PATH}/:client_id"),
+            delete(client_configuration_delete_endpoint),
+        )
+        .route(
+            AUTHORIZATION_SERVER_METADATA_PATH,
+            get(authorization_server_metadata_endpoint),
+        )
+        .route(
+            OPENID_PROVIDER_METADATA_PATH,
+            get(openid_provider_metadata_endpoint),
+        )
+        .with_state(state)
 }
 
 pub fn create_template_engine() -> tera::Result<Tera> {
@@ -41,6 +175,10 @@ pub fn create_template_engine() -> tera::Result<Tera> {
             "authenticate",
             include_str!("api/templates/authenticate.html"),
         ),
+        ("device", include_str!("api/templates/device.html")),
+        ("error", include_str!("api/templates/error.html")),
+        ("form_post", include_str!("api/templates/form_post.html")),
+        ("logout", include_str!("api/templates/logout.html")),
     ])?;
 
     Ok(tera)
@@ -57,24 +195,26 @@ async fn index() -> String {
     "Hello, world!".to_string()
 }
 
+fn basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let encoded = value.strip_prefix("Basic ")?;
+    let decoded = String::from_utf8(BASE64_STANDARD.decode(encoded).ok()?).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+
+    Some((username.to_string(), password.to_string()))
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{
-        api::{RouterState, create_router, create_template_engine, index},
-        repository::client::TestClientRepository,
-    };
+    use std::sync::Arc;
+
+    use axum::http::{HeaderMap, header};
+
+    use crate::api::{RouterState, basic_credentials, create_router, index};
 
     #[test]
     fn test_create_router() {
-        let client_store = TestClientRepository {
-            client_ids: vec!["foobar".to_string()],
-        };
-        let template_engine = create_template_engine().expect("Could not create template engine");
-        let router_state = RouterState {
-            client_store,
-            template_engine,
-        };
-        let router = create_router(router_state);
+        let router = create_router(Arc::new(RouterState::for_test()));
 
         assert!(router.has_routes());
     }
@@ -84,4 +224,19 @@ mod tests {
         let response = index().await;
         assert_eq!(response, "Hello, world!");
     }
+
+    #[test]
+    fn test_basic_credentials() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(basic_credentials(&headers), None);
+
+        headers.insert(
+            header::AUTHORIZATION,
+            "Basic czZCaGRSa3F0MzpnWDFmQmF0M2JW".parse().unwrap(),
+        );
+        assert_eq!(
+            basic_credentials(&headers),
+            Some(("s6BhdRkqt3".to_string(), "gX1fBat3bV".to_string()))
+        );
+    }
 }
@@ -1,13 +1,33 @@
 use std::sync::Arc;
 
-use axum::{extract::{Form, State}, response::{Html, IntoResponse, Response}};
+use axum::{
+    extract::{Form, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Response},
+};
 use serde::Deserialize;
 use tera::Context;
+use tracing::error;
 
 use super::RouterState;
+use crate::core::authentication::{self, Session, SessionRepository};
 
-pub async fn authentication_get_endpoint(State(router_state): State<Arc<RouterState>>) -> Html<String> {
-    let context = Context::new();
+const SESSION_COOKIE: &str = "keyper_session";
+
+#[derive(Deserialize, Default, Debug)]
+pub struct AuthenticationQuery {
+    pub return_to: Option<String>,
+    /// Prefills the username, e.g. from the `login_hint` of an authorization request
+    pub login_hint: Option<String>,
+}
+
+pub async fn authentication_get_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    Query(authentication_query): Query<AuthenticationQuery>,
+) -> Html<String> {
+    let mut context = Context::new();
+    context.insert("return_to", &authentication_query.return_to);
+    context.insert("login_hint", &authentication_query.login_hint);
     let html = router_state
         .template_engine
         .render("authenticate", &context)
@@ -19,36 +39,232 @@ pub async fn authentication_get_endpoint(State(router_state): State<Arc<RouterSt
 #[derive(Deserialize, Clone, Debug)]
 pub struct Credentials {
     pub username: String,
-    pub password: String
+    pub password: String,
+    pub return_to: Option<String>,
+}
+
+pub async fn authentication_post_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    Form(credentials): Form<Credentials>,
+) -> Response {
+    let session = authentication::authenticate(
+        &credentials.username,
+        &credentials.password,
+        &router_state.owner_store,
+        &router_state.session_store,
+    );
+
+    match session {
+        Ok(Some(session)) => {
+            let cookie = session_cookie(&router_state, &session.id);
+
+            (
+                StatusCode::SEE_OTHER,
+                [
+                    (
+                        header::LOCATION,
+                        local_path(credentials.return_to.as_deref()),
+                    ),
+                    (header::SET_COOKIE, cookie.as_str()),
+                ],
+            )
+                .into_response()
+        }
+        Ok(None) => {
+            let mut context = Context::new();
+            context.insert("return_to", &credentials.return_to);
+            context.insert("error", "Invalid username or password");
+            match router_state
+                .template_engine
+                .render("authenticate", &context)
+            {
+                Ok(html) => (StatusCode::UNAUTHORIZED, Html(html)).into_response(),
+                Err(_) => StatusCode::UNAUTHORIZED.into_response(),
+            }
+        }
+        Err(error) => {
+            error!("Could not create session: {error}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Returns the active login session referenced by the session cookie, if any
+pub fn current_session(router_state: &RouterState, headers: &HeaderMap) -> Option<Session> {
+    let session_id = headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|cookie| {
+            let (name, value) = cookie.trim().split_once('=')?;
+            (name == SESSION_COOKIE).then_some(value)
+        })?;
+
+    authentication::active_session(session_id, &router_state.session_store)
+}
+
+/// Sends the user agent to the login form, which returns to `return_to` afterwards
+pub fn login_redirect(return_to: &str, login_hint: Option<&str>) -> Response {
+    let mut parameters = vec![("return_to", return_to)];
+    if let Some(login_hint) = login_hint {
+        parameters.push(("login_hint", login_hint));
+    }
+    let query = serde_urlencoded::to_string(parameters).unwrap_or_default();
+
+    (
+        StatusCode::SEE_OTHER,
+        [(header::LOCATION, format!("/authentication?{query}"))],
+    )
+        .into_response()
+}
+
+/// Remembers the client for back-channel logout. Failing to do so must not fail the
+/// authorization, the client merely misses the logout notification.
+pub fn record_session_client(router_state: &RouterState, session: &Session, client_id: &str) {
+    if let Err(error) = router_state
+        .session_store
+        .add_session_client(&session.id, client_id)
+    {
+        error!("Could not add client {client_id} to session: {error}");
+    }
+}
+
+/// Cookie referencing a login session. It is only sent over TLS if keyper is served via HTTPS.
+fn session_cookie(router_state: &RouterState, session_id: &str) -> String {
+    let secure = if router_state.token_settings.issuer.starts_with("https://") {
+        "; Secure"
+    } else {
+        ""
+    };
+
+    format!("{SESSION_COOKIE}={session_id}; Path=/; HttpOnly; SameSite=Lax{secure}")
+}
+
+/// Makes the user agent drop the session cookie
+pub fn expired_session_cookie(router_state: &RouterState) -> String {
+    format!("{}; Max-Age=0", session_cookie(router_state, ""))
 }
 
-pub async fn authentication_post_endpoint(State(_router_state): State<Arc<RouterState>>, Form(credentials): Form<Credentials>) -> Response {
-    format!("{}:{}", credentials.username, credentials.password).into_response()
+/// Only allows redirects to paths on this server after login, so the login form cannot be abused
+/// as an open redirector.
+fn local_path(return_to: Option<&str>) -> &str {
+    match return_to {
+        Some(path) if path.starts_with('/') && !path.starts_with("//") && !path.contains('\\') => {
+            path
+        }
+        _ => "/",
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use axum::{extract::State, response::Html};
-    use core::{assert_eq, assert_ne};
+    use argon2::{
+        Argon2, PasswordHasher,
+        password_hash::{SaltString, rand_core::OsRng},
+    };
+    use axum::{
+        extract::{Form, Query, State},
+        http::{HeaderMap, StatusCode, header},
+        response::Html,
+    };
+    use core::assert_ne;
     use std::sync::Arc;
 
     use crate::{
-        api::{RouterState, authentication::authentication_get_endpoint, create_template_engine},
-        repository::client::TestClientRepository,
+        api::{
+            RouterState,
+            authentication::{
+                AuthenticationQuery, Credentials, authentication_get_endpoint,
+                authentication_post_endpoint, current_session, local_path,
+            },
+        },
+        repository::{client::MapClientRepository, owner::MapOwnerRepository},
     };
 
-    #[tokio::test]
-    async fn test_authentication_endpoint() {
-        let client_store = TestClientRepository {
-            client_ids: vec!["foobar".to_string()],
-        };
-        let template_engine = create_template_engine().expect("Could not create template engine");
-        let router_state = RouterState {
+    fn create_router_state() -> Arc<RouterState> {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(b"password", &salt)
+            .unwrap()
+            .to_string();
+        let owner_store = MapOwnerRepository::try_from_toml(&format!(
+            r#"
+            [alice]
+            email = "alice@example.com"
+            hash = "{hash}"
+            "#
+        ))
+        .unwrap();
+
+        let client_store = MapClientRepository::default();
+        Arc::new(RouterState {
             client_store,
-            template_engine,
-        };
+            owner_store,
+            ..RouterState::for_test()
+        })
+    }
 
-        let Html(response) = authentication_get_endpoint(State(Arc::new(router_state))).await;
+    #[tokio::test]
+    async fn test_authentication_endpoint() {
+        let Html(response) = authentication_get_endpoint(
+            State(create_router_state()),
+            Query(AuthenticationQuery::default()),
+        )
+        .await;
         assert_ne!(response.len(), 0);
     }
+
+    #[tokio::test]
+    async fn test_authentication_post_endpoint() {
+        let router_state = create_router_state();
+
+        let response = authentication_post_endpoint(
+            State(router_state.clone()),
+            Form(Credentials {
+                username: "alice".to_string(),
+                password: "wrong".to_string(),
+                return_to: None,
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+
+        let response = authentication_post_endpoint(
+            State(router_state.clone()),
+            Form(Credentials {
+                username: "alice".to_string(),
+                password: "password".to_string(),
+                return_to: Some("/device?user_code=WDJB-MJHT".to_string()),
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "/device?user_code=WDJB-MJHT"
+        );
+
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.contains("HttpOnly"));
+        assert!(cookie.contains("Secure"));
+
+        let mut headers = HeaderMap::new();
+        let (session_cookie, _) = cookie.split_once(';').unwrap();
+        headers.insert(
+            header::COOKIE,
+            format!("theme=dark; {session_cookie}").parse().unwrap(),
+        );
+        let session = current_session(&router_state, &headers).unwrap();
+        assert_eq!(session.owner, "alice");
+    }
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(local_path(Some("/device")), "/device");
+        assert_eq!(local_path(Some("//evil.example.com")), "/");
+        assert_eq!(local_path(Some("https://evil.example.com")), "/");
+        assert_eq!(local_path(None), "/");
+    }
 }
@@ -1,109 +1,801 @@
 use std::sync::Arc;
 
 use axum::{
-    extract::{Query, State},
-    http::{header, HeaderMap, StatusCode},
-    response::{IntoResponse, Response},
-    Json,
+    extract::{RawQuery, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Response},
 };
+use chrono::Utc;
+use serde::Serialize;
+use tera::{Context, Tera};
+use tracing::error;
+use url::{Url, form_urlencoded};
 
-use crate::core::authorization::{
-    self, AuthorizationErrorResponse, AuthorizationRequest, AuthorizationResponse,
-    AuthorizationSuccessResponse,
+use crate::core::{
+    authorization::{
+        self, AuthorizationError, AuthorizationErrorResponse, AuthorizationFailureResponse,
+        AuthorizationRequest, AuthorizationSuccessResponse, Prompt, ResponseMode,
+    },
+    pushed_authorization::{
+        self, PushedRequestRepository, REQUEST_URI_PREFIX, RequestUriReference,
+    },
+    request_object::{self, RequestObjectReference},
 };
 
-use super::RouterState;
+use super::{
+    AUTHORIZATION_PATH, RouterState,
+    authentication::{current_session, login_redirect, record_session_client},
+    token::NO_STORE_HEADERS,
+};
 
+/// Accepts the authorization request either in the query, as reference to a request the client
+/// pushed before (RFC 9126 section 4) or as request object (RFC 9101 section 5). Requests are
+/// kept like pushed requests while the owner logs in, and pushed requests are dropped once
+/// answered.
 pub async fn authorization_endpoint(
     State(router_state): State<Arc<RouterState>>,
-    Query(auth_request): Query<AuthorizationRequest>,
+    RawQuery(query): RawQuery,
     headers: HeaderMap,
 ) -> Response {
-    if !headers.contains_key("Authorization") {
-        return (
-            StatusCode::UNAUTHORIZED,
-            [(header::WWW_AUTHENTICATE, "Basic realm=\"Authorization\"")],
-            (),
-        )
-            .into_response();
+    let query = query.unwrap_or_default();
+    let request_uri_reference = serde_urlencoded::from_str::<RequestUriReference>(&query)
+        .ok()
+        .filter(|reference| reference.request_uri.starts_with(REQUEST_URI_PREFIX));
+    let request_object_reference = serde_urlencoded::from_str::<RequestObjectReference>(&query)
+        .ok()
+        .filter(|reference| reference.request.is_some() || reference.request_uri.is_some());
+    let auth_request = match (&request_uri_reference, &request_object_reference) {
+        (Some(request_uri_reference), _) => pushed_authorization::pushed_authorization_request(
+            request_uri_reference,
+            &router_state.pushed_request_store,
+        ),
+        (None, Some(request_object_reference)) => {
+            request_object::request_object_authorization_request(
+                request_object_reference,
+                &router_state.token_settings,
+                &router_state.client_store,
+            )
+            .await
+        }
+        (None, None) => {
+            serde_urlencoded::from_str::<AuthorizationRequest>(&query).map_err(|error| {
+                let mut auth_error_response =
+                    AuthorizationErrorResponse::new(AuthorizationError::InvalidRequest, None);
+                auth_error_response.error_description = Some(error.to_string());
+                auth_error_response
+            })
+        }
+    };
+    let auth_request = match auth_request {
+        Ok(auth_request) => auth_request,
+        Err(auth_error_response) => {
+            return error_page(&router_state.template_engine, auth_error_response);
+        }
+    };
+
+    let session = current_session(&router_state, &headers);
+    let client_id = auth_request.client_id.clone();
+    // Requests already sent to the login page are answered with `login_required` instead of
+    // sending the owner there again
+    let interactive = auth_request.login_requested.is_none()
+        && auth_request
+            .prompts()
+            .is_ok_and(|prompts| !prompts.contains(&Prompt::None));
+    let login_hint = auth_request.login_hint.clone();
+    let response_mode = auth_request.response_mode.unwrap_or_default();
+    let login_request = interactive.then(|| auth_request.clone());
+
+    let result = authorization::authorization_code(
+        auth_request,
+        session.as_ref(),
+        &router_state.token_settings,
+        &router_state.client_store,
+        &router_state.authorization_code_store,
+        &router_state.key_store,
+    )
+    .await;
+
+    if let Err(AuthorizationFailureResponse(auth_error_response, Some(_))) = &result
+        && auth_error_response.error == AuthorizationError::LoginRequired
+        && let Some(login_request) = login_request
+    {
+        return match store_login_request(&router_state, request_uri_reference, login_request) {
+            Ok(request_uri) => {
+                let query = serde_urlencoded::to_string([
+                    ("client_id", client_id.as_str()),
+                    ("request_uri", &request_uri),
+                ])
+                .unwrap_or_default();
+                login_redirect(
+                    &format!("{AUTHORIZATION_PATH}?{query}"),
+                    login_hint.as_deref(),
+                )
+            }
+            Err(error) => {
+                error!("Could not store authorization request for login: {error}");
+                error_page(
+                    &router_state.template_engine,
+                    AuthorizationErrorResponse::new(AuthorizationError::ServerError, None),
+                )
+            }
+        };
     }
 
-    match authorization::authorization_code(auth_request, &router_state.client_store).await {
-        Ok(AuthorizationSuccessResponse(auth_response, _redirect_uri)) => {
-            auth_response.into_response()
+    if let Some(request_uri_reference) = &request_uri_reference {
+        router_state
+            .pushed_request_store
+            .delete_pushed_request(&request_uri_reference.request_uri);
+    }
+
+    match result {
+        Ok(AuthorizationSuccessResponse(auth_response, redirect_uri)) => {
+            if let Some(session) = &session {
+                record_session_client(&router_state, session, &client_id);
+            }
+            authorization_response(
+                &router_state,
+                &redirect_uri,
+                response_mode,
+                &client_id,
+                &auth_response,
+            )
+        }
+        Err(AuthorizationFailureResponse(auth_error_response, Some(redirect_uri))) => {
+            authorization_response(
+                &router_state,
+                &redirect_uri,
+                response_mode,
+                &client_id,
+                &auth_error_response,
+            )
+        }
+        Err(AuthorizationFailureResponse(auth_error_response, None)) => {
+            error_page(&router_state.template_engine, auth_error_response)
         }
-        Err(auth_error_response) => auth_error_response.into_response(),
     }
 }
 
-impl IntoResponse for AuthorizationResponse {
-    fn into_response(self) -> Response {
-        Json(self).into_response()
+/// Keeps the request around while the owner logs in and returns the request URI to resume it
+/// with. The request remembers when the login was requested, so only a login after that
+/// satisfies it, and the request cannot be altered through the user agent in the meantime.
+fn store_login_request(
+    router_state: &RouterState,
+    request_uri_reference: Option<RequestUriReference>,
+    login_request: AuthorizationRequest,
+) -> anyhow::Result<String> {
+    match request_uri_reference {
+        Some(request_uri_reference) => {
+            pushed_authorization::request_login_for_pushed_request(
+                &request_uri_reference.request_uri,
+                &router_state.pushed_request_store,
+            )?;
+            Ok(request_uri_reference.request_uri)
+        }
+        None => pushed_authorization::store_pushed_request(
+            AuthorizationRequest {
+                login_requested: Some(Utc::now()),
+                ..login_request
+            },
+            &router_state.pushed_request_store,
+        ),
     }
 }
 
-impl IntoResponse for AuthorizationErrorResponse {
-    fn into_response(self) -> Response {
-        let status_code = match self.error {
-            authorization::AuthorizationError::InvalidRequest => StatusCode::BAD_REQUEST,
-            authorization::AuthorizationError::UnauthorizedClient => StatusCode::UNAUTHORIZED,
-            authorization::AuthorizationError::AccessDenied => StatusCode::FORBIDDEN,
-            authorization::AuthorizationError::UnsupportedResponseType => StatusCode::BAD_REQUEST,
-            authorization::AuthorizationError::InvalidScope => StatusCode::BAD_REQUEST,
-            authorization::AuthorizationError::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
-            authorization::AuthorizationError::TemporarilyUnavailable => {
-                StatusCode::SERVICE_UNAVAILABLE
-            }
-        };
+/// Delivers the parameters of an authorization or error response to `redirect_uri` in the
+/// response mode the client asked for. JWT response modes wrap them into the `response`
+/// parameter first.
+fn authorization_response<T: Serialize>(
+    router_state: &RouterState,
+    redirect_uri: &str,
+    response_mode: ResponseMode,
+    client_id: &str,
+    parameters: &T,
+) -> Response {
+    let Ok(url) = Url::parse(redirect_uri) else {
+        let mut auth_error_response =
+            AuthorizationErrorResponse::new(AuthorizationError::InvalidRequest, None);
+        auth_error_response.error_description =
+            Some(format!("Invalid redirect URI {redirect_uri}"));
+        return error_page(&router_state.template_engine, auth_error_response);
+    };
+
+    let (response_mode, jwt) = response_mode.delivery();
+    let parameters = if jwt {
+        authorization::jwt_authorization_response(
+            parameters,
+            client_id,
+            &router_state.token_settings,
+            &router_state.key_store,
+        )
+        .and_then(|response| Ok(serde_urlencoded::to_string([("response", response)])?))
+    } else {
+        serde_urlencoded::to_string(parameters).map_err(anyhow::Error::from)
+    };
+
+    let response = parameters.and_then(|parameters| match response_mode {
+        ResponseMode::Fragment => Ok(fragment_redirect(url, &parameters)),
+        ResponseMode::FormPost => form_post_page(&router_state.template_engine, url, &parameters),
+        _ => Ok(query_redirect(url, &parameters)),
+    });
+
+    response.unwrap_or_else(|error| {
+        error!("Could not deliver authorization response to {redirect_uri}: {error}");
+        error_page(
+            &router_state.template_engine,
+            AuthorizationErrorResponse::new(AuthorizationError::ServerError, None),
+        )
+    })
+}
+
+/// Redirects the user agent, appending `parameters` to any query the redirect URI already
+/// carries (RFC 6749 section 4.1.2)
+fn query_redirect(mut url: Url, parameters: &str) -> Response {
+    let query = match url.query() {
+        Some(query) if !query.is_empty() => format!("{query}&{parameters}"),
+        _ => parameters.to_string(),
+    };
+    url.set_query(Some(&query));
+
+    (StatusCode::FOUND, [(header::LOCATION, url.to_string())]).into_response()
+}
+
+/// Redirects the user agent with `parameters` in the fragment, which never reaches the server of
+/// the client
+fn fragment_redirect(mut url: Url, parameters: &str) -> Response {
+    url.set_fragment(Some(parameters));
+
+    (StatusCode::FOUND, [(header::LOCATION, url.to_string())]).into_response()
+}
+
+/// Page that makes the user agent post `parameters` to the redirect URI right away (OAuth 2.0
+/// Form Post Response Mode section 2)
+fn form_post_page(template_engine: &Tera, url: Url, parameters: &str) -> anyhow::Result<Response> {
+    let mut context = Context::new();
+    context.insert("redirect_uri", url.as_str());
+    context.insert(
+        "parameters",
+        &form_urlencoded::parse(parameters.as_bytes())
+            .into_owned()
+            .collect::<Vec<_>>(),
+    );
+    let html = template_engine.render("form_post", &context)?;
 
-        (status_code, Json(self)).into_response()
+    Ok((StatusCode::OK, NO_STORE_HEADERS, Html(html)).into_response())
+}
+
+fn error_page(template_engine: &Tera, auth_error_response: AuthorizationErrorResponse) -> Response {
+    let status_code = match auth_error_response.error {
+        AuthorizationError::InvalidRequest => StatusCode::BAD_REQUEST,
+        AuthorizationError::UnauthorizedClient => StatusCode::UNAUTHORIZED,
+        AuthorizationError::UnsupportedResponseType => StatusCode::BAD_REQUEST,
+        AuthorizationError::InvalidScope => StatusCode::BAD_REQUEST,
+        AuthorizationError::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+        AuthorizationError::LoginRequired => StatusCode::UNAUTHORIZED,
+        AuthorizationError::ConsentRequired => StatusCode::FORBIDDEN,
+        AuthorizationError::InteractionRequired => StatusCode::FORBIDDEN,
+        AuthorizationError::InvalidRequestUri => StatusCode::BAD_REQUEST,
+        AuthorizationError::InvalidRequestObject => StatusCode::BAD_REQUEST,
+    };
+
+    let html = Context::from_serialize(&auth_error_response)
+        .and_then(|context| template_engine.render("error", &context));
+
+    match html {
+        Ok(html) => (status_code, Html(html)).into_response(),
+        Err(_) => status_code.into_response(),
     }
 }
+
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
     use axum::{
-        extract::{Query, State},
-        http::{HeaderMap, StatusCode},
+        body::to_bytes,
+        extract::{RawQuery, State},
+        http::{HeaderMap, StatusCode, header},
     };
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+    use url::{Url, form_urlencoded};
 
     use crate::{
-        api::{self, authorization::authorization_endpoint, RouterState},
-        core::authorization::{AuthorizationRequest, ResponseType},
-        repository::client::TestClientRepository,
+        api::{RouterState, authorization::authorization_endpoint},
+        core::{
+            authentication::{Session, SessionRepository},
+            authorization::ClientRepository,
+            jose::{Algorithm, SigningKey},
+            key::{JwkSet, KeyRepository},
+        },
+        repository::{
+            client::MapClientRepository, key::MapKeyRepository, session::MapSessionRepository,
+        },
     };
 
+    fn create_router_state() -> Arc<RouterState> {
+        let client_store = MapClientRepository::try_from_toml(
+            r#"
+            [foobar]
+            name = "Example Client"
+            client_type = "public"
+            redirect_uris = [
+                "https://client.example.com/cb",
+                "https://client.example.com/cb?foo=bar",
+            ]
+
+            [markup]
+            name = "Misconfigured Client"
+            client_type = "public"
+            redirect_uris = ["<script>alert(1)</script>"]
+
+            [scripted]
+            name = "Scripted Client"
+            client_type = "public"
+            redirect_uris = ["javascript:fetch('/authorization')//"]
+        "#,
+        )
+        .unwrap();
+        let session_store = MapSessionRepository::default();
+        let authenticated = Utc::now();
+        session_store
+            .create_session(Session {
+                id: "alice_session".to_string(),
+                sid: "08a5019c".to_string(),
+                csrf_token: "Ks7mT2pQ".to_string(),
+                failed_user_codes: 0,
+                owner: "alice".to_string(),
+                clients: Vec::new(),
+                authenticated,
+                expires: authenticated + Duration::seconds(3600),
+            })
+            .unwrap();
+
+        Arc::new(RouterState {
+            client_store,
+            key_store: MapKeyRepository::from_signing_keys(vec![
+                SigningKey::from_pem(include_str!("../core/testdata/ed25519.pem")).unwrap(),
+            ]),
+            session_store,
+            ..RouterState::for_test()
+        })
+    }
+
+    fn create_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            "keyper_session=alice_session".parse().unwrap(),
+        );
+
+        headers
+    }
+
+    fn create_query(parameters: &[(&str, &str)]) -> RawQuery {
+        RawQuery(Some(serde_urlencoded::to_string(parameters).unwrap()))
+    }
+
     #[tokio::test]
     async fn test_authorization_endpoint() {
-        let request = AuthorizationRequest {
-            response_type: ResponseType::Code,
-            client_id: "foobar".to_string(),
-            state: Some("xyz".to_string()),
-            redirect_uri: Some("https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()),
-            scope: None,
-        };
+        let parameters = [
+            ("response_type", "code"),
+            ("client_id", "foobar"),
+            ("state", "xyz"),
+            ("redirect_uri", "https://client.example.com/cb"),
+            ("login_hint", "alice"),
+        ];
 
-        let client_store = TestClientRepository {
-            client_ids: vec!["foobar".to_string()],
-        };
-        let template_engine =
-            api::create_template_engine().expect("Could not create template engine");
-        let router_state = RouterState {
-            client_store,
-            template_engine,
+        let response = authorization_endpoint(
+            State(create_router_state()),
+            create_query(&parameters),
+            HeaderMap::new(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.starts_with("/authentication?return_to=%2Fauthorization%3F"));
+        assert!(location.ends_with("&login_hint=alice"));
+
+        let response = authorization_endpoint(
+            State(create_router_state()),
+            create_query(&[parameters.as_slice(), &[("prompt", "none")]].concat()),
+            HeaderMap::new(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://client.example.com/cb?error=login_required&state=xyz"
+        );
+
+        let response = authorization_endpoint(
+            State(create_router_state()),
+            create_query(&[("client_id", "foobar")]),
+            create_headers(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_authorization_endpoint_request_object() {
+        let router_state = create_router_state();
+        let signing_key = SigningKey::generate(Algorithm::ES256).unwrap();
+        let mut client = router_state.client_store.read_client("foobar").unwrap();
+        client.jwks = Some(JwkSet {
+            keys: vec![signing_key.public_jwk()],
+        });
+        router_state.client_store.update_client(client).unwrap();
+
+        let request_object = signing_key
+            .sign(
+                "oauth-authz-req+jwt",
+                &json!({
+                    "iss": "foobar",
+                    "aud": router_state.token_settings.issuer,
+                    "exp": (Utc::now() + Duration::seconds(60)).timestamp(),
+                    "response_type": "code",
+                    "client_id": "foobar",
+                    "state": "xyz",
+                    "redirect_uri": "https://client.example.com/cb",
+                }),
+            )
+            .unwrap();
+        let parameters = [
+            ("client_id", "foobar"),
+            ("request", request_object.as_str()),
+            ("state", "ignored"),
+        ];
+
+        let response = authorization_endpoint(
+            State(router_state.clone()),
+            create_query(&parameters),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.starts_with("https://client.example.com/cb?code="));
+        assert!(location.ends_with("&state=xyz"));
+
+        // Without session, the verified request is resumed by reference after the login
+        let response = authorization_endpoint(
+            State(router_state.clone()),
+            create_query(&parameters),
+            HeaderMap::new(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        let location = Url::parse(&format!("https://keyper.example.com{location}")).unwrap();
+        let (_, return_to) = location
+            .query_pairs()
+            .find(|(name, _)| name == "return_to")
+            .unwrap();
+        let (_, query) = return_to.split_once('?').unwrap();
+        assert!(query.contains("request_uri=urn%3Aietf%3Aparams%3Aoauth%3Arequest_uri%3A"));
+
+        let response = authorization_endpoint(
+            State(router_state.clone()),
+            RawQuery(Some(query.to_string())),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.ends_with("&state=xyz"));
+
+        let forged_request_object = SigningKey::generate(Algorithm::ES256)
+            .unwrap()
+            .sign("oauth-authz-req+jwt", &json!({"iss": "foobar"}))
+            .unwrap();
+        let response = authorization_endpoint(
+            State(router_state),
+            create_query(&[
+                ("client_id", "foobar"),
+                ("request", forged_request_object.as_str()),
+            ]),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(!response.headers().contains_key(header::LOCATION));
+    }
+
+    #[tokio::test]
+    async fn test_authorization_endpoint_redirect() {
+        let response = authorization_endpoint(
+            State(create_router_state()),
+            create_query(&[
+                ("response_type", "code"),
+                ("client_id", "foobar"),
+                ("state", "xyz"),
+                ("redirect_uri", "https://client.example.com/cb?foo=bar"),
+            ]),
+            create_headers(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.starts_with("https://client.example.com/cb?foo=bar&code="));
+        assert!(location.ends_with("&state=xyz"));
+    }
+
+    #[tokio::test]
+    async fn test_authorization_endpoint_response_mode() {
+        let router_state = create_router_state();
+        let create_parameters = |response_mode| {
+            create_query(&[
+                ("response_type", "code"),
+                ("client_id", "foobar"),
+                ("state", "xyz"),
+                ("redirect_uri", "https://client.example.com/cb"),
+                ("response_mode", response_mode),
+            ])
         };
 
-        let mut headers = HeaderMap::new();
-        // headers.insert("Authorization", "foobarbaz".parse().unwrap());
+        let response = authorization_endpoint(
+            State(router_state.clone()),
+            create_parameters("fragment"),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.starts_with("https://client.example.com/cb#code="));
+        assert!(location.ends_with("&state=xyz"));
+
+        let response = authorization_endpoint(
+            State(router_state.clone()),
+            create_parameters("form_post"),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(
+            r#"<form method="post" action="https:&#x2F;&#x2F;client.example.com&#x2F;cb">"#
+        ));
+        assert!(body.contains(r#"<input type="hidden" name="state" value="xyz">"#));
+        assert!(body.contains(r#"name="code""#));
 
         let response = authorization_endpoint(
-            State(Arc::new(router_state)),
-            Query(request.clone()),
-            headers,
+            State(router_state.clone()),
+            create_parameters("fragment.jwt"),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = Url::parse(response.headers()[header::LOCATION].to_str().unwrap()).unwrap();
+        let (name, jwt) = form_urlencoded::parse(location.fragment().unwrap().as_bytes())
+            .next()
+            .unwrap();
+        assert_eq!(name, "response");
+        let claims = router_state
+            .key_store
+            .read_signing_key()
+            .unwrap()
+            .verify(&jwt)
+            .unwrap();
+        assert_eq!(claims["aud"], "foobar");
+        assert_eq!(claims["state"], "xyz");
+        assert!(claims["code"].is_string());
+
+        let response = authorization_endpoint(
+            State(router_state),
+            create_query(&[
+                ("response_type", "token"),
+                ("client_id", "foobar"),
+                ("state", "xyz"),
+                ("redirect_uri", "https://client.example.com/cb"),
+                ("response_mode", "query.jwt"),
+            ]),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.starts_with("https://client.example.com/cb?response="));
+    }
+
+    #[tokio::test]
+    async fn test_authorization_endpoint_redirect_error() {
+        let response = authorization_endpoint(
+            State(create_router_state()),
+            create_query(&[
+                ("response_type", "token"),
+                ("client_id", "foobar"),
+                ("state", "xyz"),
+                ("redirect_uri", "https://client.example.com/cb"),
+            ]),
+            create_headers(),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://client.example.com/cb?error=unsupported_response_type&state=xyz"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authorization_endpoint_unknown_client() {
+        let response = authorization_endpoint(
+            State(create_router_state()),
+            create_query(&[
+                ("response_type", "code"),
+                ("client_id", "unknown"),
+                ("state", "xyz"),
+                ("redirect_uri", "https://client.example.com/cb"),
+            ]),
+            create_headers(),
         )
         .await;
 
         assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(!response.headers().contains_key(header::LOCATION));
+    }
+
+    #[tokio::test]
+    async fn test_authorization_endpoint_error_page_escaped() {
+        for (parameters, headers) in [
+            (
+                [
+                    ("response_type", "<script>alert(1)</script>"),
+                    ("client_id", "foobar"),
+                ],
+                HeaderMap::new(),
+            ),
+            (
+                [("response_type", "code"), ("client_id", "markup")],
+                create_headers(),
+            ),
+        ] {
+            let response = authorization_endpoint(
+                State(create_router_state()),
+                create_query(&parameters),
+                headers,
+            )
+            .await;
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            assert!(!body.contains("<script>"));
+            assert!(body.contains("&lt;script&gt;alert(1)&lt;&#x2F;script&gt;"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authorization_endpoint_script_redirect_uri() {
+        let router_state = create_router_state();
+        for response_mode in ["query", "form_post"] {
+            let parameters = [
+                ("response_type", "code"),
+                ("client_id", "scripted"),
+                ("response_mode", response_mode),
+            ];
+            let response = authorization_endpoint(
+                State(router_state.clone()),
+                create_query(&parameters),
+                create_headers(),
+            )
+            .await;
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            assert!(response.headers().get(header::LOCATION).is_none());
+            let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body = String::from_utf8(body.to_vec()).unwrap();
+            assert!(!body.contains("<form"));
+            assert!(body.contains("invalid_request"));
+        }
+    }
+
+    /// Sends the request to the login page and returns the query to resume it with
+    async fn login_return_to(
+        router_state: &Arc<RouterState>,
+        parameters: &[(&str, &str)],
+    ) -> String {
+        let response = authorization_endpoint(
+            State(router_state.clone()),
+            create_query(parameters),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        let location = Url::parse(&format!("https://keyper.example.com{location}")).unwrap();
+        let (_, return_to) = location
+            .query_pairs()
+            .find(|(name, _)| name == "return_to")
+            .unwrap();
+        let (_, query) = return_to.split_once('?').unwrap();
+
+        query.to_string()
+    }
+
+    fn create_session(router_state: &RouterState, id: &str, owner: &str) -> HeaderMap {
+        let authenticated = Utc::now();
+        router_state
+            .session_store
+            .create_session(Session {
+                id: id.to_string(),
+                sid: id.to_string(),
+                csrf_token: "Ks7mT2pQ".to_string(),
+                failed_user_codes: 0,
+                owner: owner.to_string(),
+                clients: Vec::new(),
+                authenticated,
+                expires: authenticated + Duration::seconds(3600),
+            })
+            .unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            format!("keyper_session={id}").parse().unwrap(),
+        );
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_authorization_endpoint_login_request() {
+        let router_state = create_router_state();
+        let parameters = [
+            ("response_type", "code"),
+            ("client_id", "foobar"),
+            ("state", "xyz"),
+            ("redirect_uri", "https://client.example.com/cb"),
+            ("prompt", "login"),
+            ("max_age", "0"),
+            ("login_hint", "alice"),
+        ];
+
+        // Returning with the session that existed before does not count as login
+        let query = login_return_to(&router_state, &parameters).await;
+        let response = authorization_endpoint(
+            State(router_state.clone()),
+            RawQuery(Some(query)),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://client.example.com/cb?error=login_required&state=xyz"
+        );
+
+        // Logging in as someone else than the hinted owner does not either
+        let query = login_return_to(&router_state, &parameters).await;
+        let headers = create_session(&router_state, "bob_session", "bob");
+        let response =
+            authorization_endpoint(State(router_state.clone()), RawQuery(Some(query)), headers)
+                .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.starts_with("https://client.example.com/cb?error=login_required"));
+
+        let query = login_return_to(&router_state, &parameters).await;
+        let headers = create_session(&router_state, "fresh_session", "alice");
+        let response = authorization_endpoint(
+            State(router_state.clone()),
+            RawQuery(Some(query.clone())),
+            headers.clone(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.starts_with("https://client.example.com/cb?code="));
+
+        // The stored request is answered only once
+        let response =
+            authorization_endpoint(State(router_state), RawQuery(Some(query)), headers).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 }
@@ -0,0 +1,458 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Form, Query, State, rejection::FormRejection},
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Response},
+};
+use serde::Deserialize;
+use tera::{Context, Tera};
+use tracing::error;
+
+use super::{
+    DEVICE_PATH, RouterState,
+    authentication::{current_session, login_redirect, record_session_client},
+    token::{NO_STORE_HEADERS, client_basic_credentials},
+};
+use crate::core::{
+    authentication::{Session, SessionRepository},
+    authorization::ClientRepository,
+    device::{
+        self, DeviceAuthorizationRequest, DeviceAuthorizationResponse, USER_CODE_ATTEMPTS,
+        format_user_code, pending_device_code,
+    },
+    token::{AccessTokenError, AccessTokenErrorResponse, ClientCredentials, authenticate_client},
+};
+
+pub async fn device_authorization_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    headers: HeaderMap,
+    device_authorization_request: Result<Form<DeviceAuthorizationRequest>, FormRejection>,
+) -> Result<DeviceAuthorizationResponse, AccessTokenErrorResponse> {
+    let Form(mut device_authorization_request) =
+        device_authorization_request.map_err(|rejection| {
+            let mut access_token_error_response =
+                AccessTokenErrorResponse::new(AccessTokenError::InvalidRequest);
+            access_token_error_response.error_description = Some(rejection.body_text());
+            access_token_error_response
+        })?;
+
+    let client_credentials = ClientCredentials::from_request(
+        client_basic_credentials(&headers),
+        device_authorization_request.client_id.clone(),
+        device_authorization_request.client_secret.take(),
+    )?;
+    let client = authenticate_client(client_credentials, &router_state.client_store)?;
+
+    device::device_authorization(
+        &device_authorization_request,
+        &client,
+        &format!("{}{DEVICE_PATH}", router_state.token_settings.issuer),
+        &router_state.device_code_store,
+    )
+}
+
+#[derive(Deserialize, Default, Debug)]
+pub struct DeviceQuery {
+    pub user_code: Option<String>,
+}
+
+pub async fn device_get_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    Query(device_query): Query<DeviceQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(session) = current_session(&router_state, &headers) else {
+        let return_to = match &device_query.user_code {
+            Some(user_code) => format!(
+                "/device?{}",
+                serde_urlencoded::to_string([("user_code", user_code)]).unwrap_or_default()
+            ),
+            None => "/device".to_string(),
+        };
+        return login_redirect(&return_to, None);
+    };
+
+    let mut context = Context::new();
+    let Some(user_code) = device_query.user_code else {
+        return device_page(&router_state.template_engine, StatusCode::OK, &context);
+    };
+
+    if session.failed_user_codes >= USER_CODE_ATTEMPTS {
+        return too_many_user_codes(&router_state.template_engine);
+    }
+
+    let Some(device_code) = pending_device_code(&user_code, &router_state.device_code_store) else {
+        return unknown_user_code(&router_state, &session);
+    };
+
+    let client_name = router_state
+        .client_store
+        .read_client(&device_code.client_id)
+        .map(|client| client.name)
+        .unwrap_or(device_code.client_id);
+    context.insert("client_name", &client_name);
+    context.insert("user_code", &format_user_code(&device_code.user_code));
+    context.insert("scopes", &device_code.scopes);
+
+    device_page(&router_state.template_engine, StatusCode::OK, &context)
+}
+
+#[derive(Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceAction {
+    Approve,
+    Deny,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct DeviceDecision {
+    pub user_code: String,
+    pub action: DeviceAction,
+}
+
+pub async fn device_post_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    headers: HeaderMap,
+    Form(device_decision): Form<DeviceDecision>,
+) -> Response {
+    let Some(session) = current_session(&router_state, &headers) else {
+        return login_redirect("/device", None);
+    };
+
+    if session.failed_user_codes >= USER_CODE_ATTEMPTS {
+        return too_many_user_codes(&router_state.template_engine);
+    }
+
+    let result = device::decide_device_code(
+        &device_decision.user_code,
+        &session.owner,
+        device_decision.action == DeviceAction::Approve,
+        &router_state.device_code_store,
+    );
+
+    let mut context = Context::new();
+    match result {
+        Ok(Some(device_code)) => {
+            if device_decision.action == DeviceAction::Approve {
+                record_session_client(&router_state, &session, &device_code.client_id);
+            }
+            context.insert(
+                "approved",
+                &(device_decision.action == DeviceAction::Approve),
+            );
+            device_page(&router_state.template_engine, StatusCode::OK, &context)
+        }
+        Ok(None) => unknown_user_code(&router_state, &session),
+        Err(error) => {
+            error!("Could not store device code decision: {error}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Counts the user code against the session before telling the resource owner it is unknown
+fn unknown_user_code(router_state: &RouterState, session: &Session) -> Response {
+    if let Err(error) = router_state.session_store.add_failed_user_code(&session.id) {
+        error!("Could not count failed user code: {error}");
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+
+    let mut context = Context::new();
+    context.insert("error", "The code is unknown or has expired");
+    device_page(
+        &router_state.template_engine,
+        StatusCode::BAD_REQUEST,
+        &context,
+    )
+}
+
+fn too_many_user_codes(template_engine: &Tera) -> Response {
+    let mut context = Context::new();
+    context.insert(
+        "error",
+        "Too many unknown codes were entered, please log in again",
+    );
+    device_page(template_engine, StatusCode::TOO_MANY_REQUESTS, &context)
+}
+
+fn device_page(template_engine: &Tera, status_code: StatusCode, context: &Context) -> Response {
+    match template_engine.render("device", context) {
+        Ok(html) => (status_code, Html(html)).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+impl IntoResponse for DeviceAuthorizationResponse {
+    fn into_response(self) -> Response {
+        (NO_STORE_HEADERS, Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::to_bytes,
+        extract::{Form, Query, State},
+        http::{HeaderMap, StatusCode, header},
+        response::IntoResponse,
+    };
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        api::{
+            RouterState,
+            device::{
+                DeviceAction, DeviceDecision, DeviceQuery, device_authorization_endpoint,
+                device_get_endpoint, device_post_endpoint,
+            },
+        },
+        core::{
+            authentication::{Session, SessionRepository},
+            device::{
+                DeviceAuthorizationRequest, DeviceCodeRepository, DeviceCodeStatus,
+                USER_CODE_ATTEMPTS,
+            },
+        },
+        repository::{client::MapClientRepository, session::MapSessionRepository},
+    };
+
+    fn create_router_state() -> Arc<RouterState> {
+        let client_store = MapClientRepository::try_from_toml(
+            r#"
+            [foobar]
+            name = "Smart TV"
+            client_type = "public"
+            redirect_uris = []
+        "#,
+        )
+        .unwrap();
+
+        let session_store = MapSessionRepository::default();
+        let authenticated = Utc::now();
+        session_store
+            .create_session(Session {
+                id: "alice_session".to_string(),
+                sid: "08a5019c".to_string(),
+                csrf_token: "Ks7mT2pQ".to_string(),
+                failed_user_codes: 0,
+                owner: "alice".to_string(),
+                clients: Vec::new(),
+                authenticated,
+                expires: authenticated + Duration::seconds(3600),
+            })
+            .unwrap();
+
+        Arc::new(RouterState {
+            client_store,
+            session_store,
+            ..RouterState::for_test()
+        })
+    }
+
+    fn create_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            "keyper_session=alice_session".parse().unwrap(),
+        );
+        headers
+    }
+
+    async fn create_user_code(router_state: &Arc<RouterState>) -> String {
+        let request = DeviceAuthorizationRequest {
+            client_id: Some("foobar".to_string()),
+            scope: Some("read".to_string()),
+            ..Default::default()
+        };
+        let response = device_authorization_endpoint(
+            State(router_state.clone()),
+            HeaderMap::new(),
+            Ok(Form(request)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            response.verification_uri,
+            "https://keyper.example.com/device"
+        );
+
+        response.user_code
+    }
+
+    #[tokio::test]
+    async fn test_device_authorization_endpoint() {
+        let router_state = create_router_state();
+        let request = DeviceAuthorizationRequest {
+            client_id: Some("unknown".to_string()),
+            ..Default::default()
+        };
+
+        let response = device_authorization_endpoint(
+            State(router_state.clone()),
+            HeaderMap::new(),
+            Ok(Form(request)),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let request = DeviceAuthorizationRequest {
+            client_id: Some("foobar".to_string()),
+            ..Default::default()
+        };
+        let response =
+            device_authorization_endpoint(State(router_state), HeaderMap::new(), Ok(Form(request)))
+                .await
+                .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#""device_code":"#));
+        assert!(body.contains(r#""expires_in":600"#));
+    }
+
+    #[tokio::test]
+    async fn test_device_get_endpoint() {
+        let router_state = create_router_state();
+        let user_code = create_user_code(&router_state).await;
+
+        let response = device_get_endpoint(
+            State(router_state.clone()),
+            Query(DeviceQuery {
+                user_code: Some(user_code.clone()),
+            }),
+            HeaderMap::new(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            format!("/authentication?return_to=%2Fdevice%3Fuser_code%3D{user_code}")
+        );
+
+        let response = device_get_endpoint(
+            State(router_state.clone()),
+            Query(DeviceQuery {
+                user_code: Some(user_code.clone()),
+            }),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("Smart TV"));
+        assert!(body.contains(&user_code));
+
+        let response = device_get_endpoint(
+            State(router_state),
+            Query(DeviceQuery {
+                user_code: Some("BBBB-BBBB".to_string()),
+            }),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_device_endpoint_user_code_attempts() {
+        let router_state = create_router_state();
+        let user_code = create_user_code(&router_state).await;
+
+        for _ in 0..USER_CODE_ATTEMPTS {
+            let response = device_get_endpoint(
+                State(router_state.clone()),
+                Query(DeviceQuery {
+                    user_code: Some("BBBB-BBBB".to_string()),
+                }),
+                create_headers(),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+
+        // Even the pending user code is refused once the session entered too many unknown ones
+        let response = device_get_endpoint(
+            State(router_state.clone()),
+            Query(DeviceQuery {
+                user_code: Some(user_code.clone()),
+            }),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+
+        let response = device_post_endpoint(
+            State(router_state.clone()),
+            create_headers(),
+            Form(DeviceDecision {
+                user_code,
+                action: DeviceAction::Approve,
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let device_code = router_state
+            .device_code_store
+            .data
+            .lock()
+            .unwrap()
+            .values()
+            .next()
+            .cloned()
+            .unwrap();
+        assert_eq!(device_code.status, DeviceCodeStatus::Pending);
+    }
+
+    #[tokio::test]
+    async fn test_device_post_endpoint() {
+        let router_state = create_router_state();
+        let user_code = create_user_code(&router_state).await;
+        let device_decision = DeviceDecision {
+            user_code: user_code.clone(),
+            action: DeviceAction::Approve,
+        };
+
+        let response = device_post_endpoint(
+            State(router_state.clone()),
+            HeaderMap::new(),
+            Form(device_decision.clone()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let response = device_post_endpoint(
+            State(router_state.clone()),
+            create_headers(),
+            Form(device_decision.clone()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let device_code = router_state
+            .device_code_store
+            .read_device_code_by_user_code(&user_code.replace('-', ""))
+            .unwrap();
+        assert_eq!(
+            device_code.status,
+            DeviceCodeStatus::Approved("alice".to_string())
+        );
+        let session = router_state
+            .session_store
+            .read_session("alice_session")
+            .unwrap();
+        assert_eq!(session.clients, vec![device_code.client_id]);
+
+        let response =
+            device_post_endpoint(State(router_state), create_headers(), Form(device_decision))
+                .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,179 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Form, State, rejection::FormRejection},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+
+use super::{
+    RouterState,
+    token::{NO_STORE_HEADERS, client_basic_credentials},
+};
+use crate::core::{
+    introspection::{IntrospectionRequest, IntrospectionResponse, introspect},
+    token::{AccessTokenError, AccessTokenErrorResponse, ClientCredentials},
+};
+
+pub async fn introspection_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    headers: HeaderMap,
+    introspection_request: Result<Form<IntrospectionRequest>, FormRejection>,
+) -> Result<IntrospectionResponse, AccessTokenErrorResponse> {
+    let Form(mut introspection_request) = introspection_request.map_err(|rejection| {
+        let mut access_token_error_response =
+            AccessTokenErrorResponse::new(AccessTokenError::InvalidRequest);
+        access_token_error_response.error_description = Some(rejection.body_text());
+        access_token_error_response
+    })?;
+
+    let client_credentials = ClientCredentials::from_request(
+        client_basic_credentials(&headers),
+        introspection_request.client_id.clone(),
+        introspection_request.client_secret.take(),
+    )?;
+
+    introspect(
+        introspection_request,
+        client_credentials,
+        &router_state.client_store,
+        &router_state.authorization_store,
+    )
+    .await
+}
+
+impl IntoResponse for IntrospectionResponse {
+    fn into_response(self) -> Response {
+        (NO_STORE_HEADERS, Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use argon2::{
+        Argon2, PasswordHasher,
+        password_hash::{SaltString, rand_core::OsRng},
+    };
+    use axum::{
+        body::to_bytes,
+        extract::{Form, State},
+        http::{HeaderMap, StatusCode, header},
+        response::IntoResponse,
+    };
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        api::{RouterState, introspection::introspection_endpoint},
+        core::{
+            introspection::IntrospectionRequest,
+            token::{Authorization, AuthorizationRepository},
+        },
+        repository::{authorization::MapAuthorizationRepository, client::MapClientRepository},
+    };
+
+    fn create_router_state() -> Arc<RouterState> {
+        let salt = SaltString::generate(&mut OsRng);
+        let secret_hash = Argon2::default()
+            .hash_password(b"gX1fBat3bV", &salt)
+            .unwrap()
+            .to_string();
+        let client_store = MapClientRepository::try_from_toml(&format!(
+            r#"
+            [s6BhdRkqt3]
+            name = "Resource Server"
+            client_type = "confidential"
+            redirect_uris = []
+            client_secret_hash = "{secret_hash}"
+        "#
+        ))
+        .unwrap();
+
+        let authorization_store = MapAuthorizationRepository::default();
+        let created = Utc::now();
+        authorization_store
+            .create_authorization(Authorization {
+                access_token: "2YotnFZFEjr1zCsicMWpAA".to_string(),
+                client_id: "foobar".to_string(),
+                scopes: vec!["read".to_string()],
+                owner: Some("alice".to_string()),
+                created,
+                expires: created + Duration::seconds(3600),
+                refresh_token: None,
+                jkt: None,
+            })
+            .unwrap();
+
+        Arc::new(RouterState {
+            client_store,
+            authorization_store,
+            ..RouterState::for_test()
+        })
+    }
+
+    fn create_headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, authorization.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_introspection_endpoint() {
+        let router_state = create_router_state();
+        let request = IntrospectionRequest {
+            token: "2YotnFZFEjr1zCsicMWpAA".to_string(),
+            ..Default::default()
+        };
+
+        let response = introspection_endpoint(
+            State(router_state.clone()),
+            create_headers("Basic czZCaGRSa3F0MzpnWDFmQmF0M2JW"),
+            Ok(Form(request)),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(
+            body.starts_with(r#"{"active":true,"scope":"read","client_id":"foobar","sub":"alice""#)
+        );
+
+        let request = IntrospectionRequest {
+            token: "unknown".to_string(),
+            ..Default::default()
+        };
+        let response = introspection_endpoint(
+            State(router_state.clone()),
+            create_headers("Basic czZCaGRSa3F0MzpnWDFmQmF0M2JW"),
+            Ok(Form(request)),
+        )
+        .await
+        .into_response();
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, r#"{"active":false}"#);
+    }
+
+    #[tokio::test]
+    async fn test_introspection_endpoint_unauthenticated() {
+        let request = IntrospectionRequest {
+            token: "2YotnFZFEjr1zCsicMWpAA".to_string(),
+            ..Default::default()
+        };
+
+        let response = introspection_endpoint(
+            State(create_router_state()),
+            create_headers("Basic czZCaGRSa3F0Mzp3cm9uZw=="),
+            Ok(Form(request)),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::State,
+    http::header,
+    response::{IntoResponse, Response},
+};
+
+use super::RouterState;
+use crate::core::key::{self, JwkSet};
+
+/// Verifiers may cache the key set for a short while, as keys are staged well before they are used
+const JWKS_MAX_AGE: &str = "public, max-age=300";
+
+pub async fn jwks_endpoint(State(router_state): State<Arc<RouterState>>) -> JwkSet {
+    key::jwk_set(&router_state.key_store)
+}
+
+impl IntoResponse for JwkSet {
+    fn into_response(self) -> Response {
+        ([(header::CACHE_CONTROL, JWKS_MAX_AGE)], Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::to_bytes,
+        extract::State,
+        http::{StatusCode, header},
+        response::IntoResponse,
+    };
+
+    use crate::{
+        api::{RouterState, jwks::jwks_endpoint},
+        core::{
+            jose::SigningKey,
+            key::{JwkSet, KeyRepository, KeySettings, rotate_keys},
+        },
+        repository::key::MapKeyRepository,
+    };
+
+    fn create_router_state() -> Arc<RouterState> {
+        let key_store = MapKeyRepository::from_signing_keys(vec![
+            SigningKey::from_pem(include_str!("../core/testdata/ed25519.pem")).unwrap(),
+        ]);
+
+        Arc::new(RouterState {
+            key_store,
+            ..RouterState::for_test()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_jwks_endpoint() {
+        let router_state = create_router_state();
+        let active_kid = router_state.key_store.read_signing_key().unwrap().kid;
+        rotate_keys(&KeySettings::default(), &router_state.key_store).unwrap();
+
+        let response = jwks_endpoint(State(router_state.clone()))
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(header::CACHE_CONTROL));
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let jwk_set: JwkSet = serde_json::from_slice(&body).unwrap();
+        assert_eq!(jwk_set.keys.len(), 3);
+        assert!(
+            jwk_set
+                .keys
+                .iter()
+                .any(|jwk| jwk.kid.as_deref() == Some(active_kid.as_str()))
+        );
+        assert!(
+            jwk_set
+                .keys
+                .iter()
+                .all(|jwk| jwk.key_use.as_deref() == Some("sig"))
+        );
+        assert!(jwk_set.keys.iter().all(|jwk| jwk.n.is_none()));
+    }
+}
@@ -0,0 +1,308 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Form, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{Html, IntoResponse, Response},
+};
+use serde::Deserialize;
+use tera::{Context, Tera};
+use tracing::error;
+
+use super::{
+    RouterState,
+    authentication::{current_session, expired_session_cookie},
+};
+use crate::core::{
+    authentication::Session,
+    logout::{self, BACKCHANNEL_LOGOUT_ATTEMPTS, BACKCHANNEL_LOGOUT_RETRY_DELAY, LogoutRequest},
+};
+
+/// Logout request as submitted from the confirmation page, which sets `confirm` and the CSRF
+/// token of the session
+#[derive(Deserialize, Default, Debug)]
+pub struct LogoutForm {
+    #[serde(flatten)]
+    pub logout_request: LogoutRequest,
+    pub confirm: Option<String>,
+    pub csrf_token: Option<String>,
+}
+
+pub async fn logout_get_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    Query(logout_request): Query<LogoutRequest>,
+    headers: HeaderMap,
+) -> Response {
+    logout_response(&router_state, &headers, logout_request, None)
+}
+
+/// Relying parties may also send their logout request as form post. The session is only ended
+/// once the owner confirmed it on the page rendered for either request, which carries the CSRF
+/// token of the session other sites cannot know.
+pub async fn logout_post_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    headers: HeaderMap,
+    Form(logout_form): Form<LogoutForm>,
+) -> Response {
+    let csrf_token = logout_form.confirm.and(logout_form.csrf_token);
+
+    logout_response(
+        &router_state,
+        &headers,
+        logout_form.logout_request,
+        csrf_token,
+    )
+}
+
+fn logout_response(
+    router_state: &RouterState,
+    headers: &HeaderMap,
+    logout_request: LogoutRequest,
+    csrf_token: Option<String>,
+) -> Response {
+    let template_engine = &router_state.template_engine;
+    let mut context = Context::new();
+
+    let validated_logout = match logout::validate_logout_request(
+        &logout_request,
+        &router_state.token_settings,
+        &router_state.client_store,
+        &router_state.key_store,
+    ) {
+        Ok(validated_logout) => validated_logout,
+        Err(error) => {
+            context.insert("error", error.description());
+            return logout_page(template_engine, StatusCode::BAD_REQUEST, &context);
+        }
+    };
+
+    let session = current_session(router_state, headers);
+    if let Some(session) = &session
+        && csrf_token.as_ref() != Some(&session.csrf_token)
+    {
+        context.insert("csrf_token", &session.csrf_token);
+        context.insert(
+            "client_name",
+            &validated_logout.client.map(|client| client.name),
+        );
+        context.insert("id_token_hint", &logout_request.id_token_hint);
+        context.insert("client_id", &logout_request.client_id);
+        context.insert(
+            "post_logout_redirect_uri",
+            &logout_request.post_logout_redirect_uri,
+        );
+        context.insert("state", &logout_request.state);
+        return logout_page(template_engine, StatusCode::OK, &context);
+    }
+
+    if let Some(session) =
+        session.and_then(|session| logout::end_session(&session.id, &router_state.session_store))
+    {
+        notify_clients(router_state, &session);
+    }
+    let cookie = expired_session_cookie(router_state);
+
+    match validated_logout.redirect_uri {
+        Some(redirect_uri) => (
+            StatusCode::SEE_OTHER,
+            [
+                (header::LOCATION, redirect_uri),
+                (header::SET_COOKIE, cookie),
+            ],
+        )
+            .into_response(),
+        None => {
+            context.insert("signed_out", &true);
+            (
+                [(header::SET_COOKIE, cookie)],
+                logout_page(template_engine, StatusCode::OK, &context),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Tells the clients of the ended session about the logout in the background, so the owner does
+/// not have to wait for them
+fn notify_clients(router_state: &RouterState, session: &Session) {
+    match logout::logout_notifications(
+        session,
+        &router_state.token_settings,
+        &router_state.client_store,
+        &router_state.key_store,
+    ) {
+        Ok(notifications) if notifications.is_empty() => (),
+        Ok(notifications) => {
+            tokio::spawn(logout::deliver_logout_notifications(
+                notifications,
+                BACKCHANNEL_LOGOUT_ATTEMPTS,
+                BACKCHANNEL_LOGOUT_RETRY_DELAY,
+            ));
+        }
+        Err(error) => error!("Could not create logout tokens: {error}"),
+    }
+}
+
+fn logout_page(template_engine: &Tera, status_code: StatusCode, context: &Context) -> Response {
+    match template_engine.render("logout", context) {
+        Ok(html) => (status_code, Html(html)).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::to_bytes,
+        extract::{Form, Query, State},
+        http::{HeaderMap, StatusCode, header},
+    };
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        api::{
+            RouterState,
+            logout::{LogoutForm, logout_get_endpoint, logout_post_endpoint},
+        },
+        core::{
+            authentication::{Session, SessionRepository},
+            logout::LogoutRequest,
+        },
+        repository::{client::MapClientRepository, session::MapSessionRepository},
+    };
+
+    fn create_router_state() -> Arc<RouterState> {
+        let client_store = MapClientRepository::try_from_toml(
+            r#"
+            [s6BhdRkqt3]
+            client_type = "public"
+            redirect_uris = ["https://client.example.com/cb"]
+            post_logout_redirect_uris = ["https://client.example.com/logged-out"]
+            name = "Example Client"
+            "#,
+        )
+        .unwrap();
+        let session_store = MapSessionRepository::default();
+        let authenticated = Utc::now();
+        session_store
+            .create_session(Session {
+                id: "foobar".to_string(),
+                sid: "08a5019c".to_string(),
+                csrf_token: "Ks7mT2pQ".to_string(),
+                failed_user_codes: 0,
+                owner: "alice".to_string(),
+                clients: Vec::new(),
+                authenticated,
+                expires: authenticated + Duration::seconds(3600),
+            })
+            .unwrap();
+
+        Arc::new(RouterState {
+            client_store,
+            session_store,
+            ..RouterState::for_test()
+        })
+    }
+
+    fn create_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, "keyper_session=foobar".parse().unwrap());
+        headers
+    }
+
+    fn create_logout_request() -> LogoutRequest {
+        LogoutRequest {
+            client_id: Some("s6BhdRkqt3".to_string()),
+            post_logout_redirect_uri: Some("https://client.example.com/logged-out".to_string()),
+            state: Some("af0ifjsldkj".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_logout_get_endpoint() {
+        let router_state = create_router_state();
+
+        let response = logout_get_endpoint(
+            State(router_state.clone()),
+            Query(create_logout_request()),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(header::SET_COOKIE).is_none());
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("Example Client"));
+        assert!(body.contains(r#"name="state" value="af0ifjsldkj""#));
+        assert!(body.contains(r#"name="csrf_token" value="Ks7mT2pQ""#));
+        assert!(router_state.session_store.read_session("foobar").is_some());
+
+        let response = logout_get_endpoint(
+            State(router_state),
+            Query(LogoutRequest {
+                post_logout_redirect_uri: Some("https://evil.example.com".to_string()),
+                ..create_logout_request()
+            }),
+            create_headers(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_logout_post_endpoint() {
+        let router_state = create_router_state();
+
+        for csrf_token in [None, Some("wrong")] {
+            let response = logout_post_endpoint(
+                State(router_state.clone()),
+                create_headers(),
+                Form(LogoutForm {
+                    logout_request: create_logout_request(),
+                    confirm: Some("true".to_string()),
+                    csrf_token: csrf_token.map(str::to_string),
+                }),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(response.headers().get(header::SET_COOKIE).is_none());
+            assert!(router_state.session_store.read_session("foobar").is_some());
+        }
+
+        let response = logout_post_endpoint(
+            State(router_state.clone()),
+            create_headers(),
+            Form(LogoutForm {
+                logout_request: create_logout_request(),
+                confirm: Some("true".to_string()),
+                csrf_token: Some("Ks7mT2pQ".to_string()),
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            response.headers()[header::LOCATION],
+            "https://client.example.com/logged-out?state=af0ifjsldkj"
+        );
+        let cookie = response.headers()[header::SET_COOKIE].to_str().unwrap();
+        assert!(cookie.starts_with("keyper_session=;"));
+        assert!(cookie.contains("Max-Age=0"));
+        assert!(router_state.session_store.read_session("foobar").is_none());
+
+        let response = logout_post_endpoint(
+            State(router_state),
+            HeaderMap::new(),
+            Form(LogoutForm::default()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("You have been signed out"));
+    }
+}
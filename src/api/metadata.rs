@@ -0,0 +1,364 @@
+use std::sync::Arc;
+
+use axum::{Json, extract::State};
+use serde::Serialize;
+
+use super::{
+    AUTHORIZATION_PATH, DEVICE_AUTHORIZATION_PATH, INTROSPECTION_PATH, JWKS_PATH, LOGOUT_PATH,
+    PUSHED_AUTHORIZATION_REQUEST_PATH, REGISTRATION_PATH, REVOCATION_PATH, RouterState, TOKEN_PATH,
+    USERINFO_PATH,
+};
+use crate::core::{
+    authorization::{CodeChallengeMethod, ResponseMode, ResponseType, TokenEndpointAuthMethod},
+    jose::Algorithm,
+    key::KeyRepository,
+    oidc::{OPENID_SCOPE, PASSWORD_ACR},
+    token::GrantType,
+};
+
+/// Claims keyper puts into ID tokens and UserInfo responses
+const CLAIMS_SUPPORTED: [&str; 13] = [
+    "iss",
+    "sub",
+    "aud",
+    "exp",
+    "iat",
+    "auth_time",
+    "nonce",
+    "sid",
+    "at_hash",
+    "acr",
+    "amr",
+    "preferred_username",
+    "email",
+];
+
+/// Authorization server metadata (RFC 8414 section 2)
+#[derive(Serialize, Debug)]
+pub struct AuthorizationServerMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub device_authorization_endpoint: String,
+    pub introspection_endpoint: String,
+    pub revocation_endpoint: String,
+    pub jwks_uri: String,
+    pub registration_endpoint: String,
+    /// RFC 9126 section 5
+    pub pushed_authorization_request_endpoint: String,
+    pub require_pushed_authorization_requests: bool,
+    /// RFC 9101 section 10.5 and OpenID Connect Discovery section 3
+    pub request_parameter_supported: bool,
+    pub request_uri_parameter_supported: bool,
+    pub request_object_signing_alg_values_supported: Vec<Algorithm>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub scopes_supported: Vec<String>,
+    pub response_types_supported: Vec<ResponseType>,
+    pub response_modes_supported: Vec<ResponseMode>,
+    /// JARM section 4.1
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub authorization_signing_alg_values_supported: Vec<Algorithm>,
+    pub grant_types_supported: Vec<GrantType>,
+    pub token_endpoint_auth_methods_supported: Vec<TokenEndpointAuthMethod>,
+    pub introspection_endpoint_auth_methods_supported: Vec<TokenEndpointAuthMethod>,
+    pub revocation_endpoint_auth_methods_supported: Vec<TokenEndpointAuthMethod>,
+    pub code_challenge_methods_supported: Vec<CodeChallengeMethod>,
+    /// RFC 9449 section 5.1
+    pub dpop_signing_alg_values_supported: Vec<Algorithm>,
+}
+
+impl AuthorizationServerMetadata {
+    /// Builds the metadata from the same paths `create_router` mounts the endpoints at
+    pub fn new(router_state: &RouterState) -> Self {
+        let issuer = &router_state.token_settings.issuer;
+
+        Self {
+            issuer: issuer.clone(),
+            authorization_endpoint: format!("{issuer}{AUTHORIZATION_PATH}"),
+            token_endpoint: format!("{issuer}{TOKEN_PATH}"),
+            device_authorization_endpoint: format!("{issuer}{DEVICE_AUTHORIZATION_PATH}"),
+            introspection_endpoint: format!("{issuer}{INTROSPECTION_PATH}"),
+            revocation_endpoint: format!("{issuer}{REVOCATION_PATH}"),
+            jwks_uri: format!("{issuer}{JWKS_PATH}"),
+            registration_endpoint: format!("{issuer}{REGISTRATION_PATH}"),
+            pushed_authorization_request_endpoint: format!(
+                "{issuer}{PUSHED_AUTHORIZATION_REQUEST_PATH}"
+            ),
+            require_pushed_authorization_requests: false,
+            request_parameter_supported: true,
+            request_uri_parameter_supported: true,
+            request_object_signing_alg_values_supported: vec![
+                Algorithm::EdDSA,
+                Algorithm::ES256,
+                Algorithm::RS256,
+            ],
+            scopes_supported: router_state.server_settings.scopes_supported.clone(),
+            response_types_supported: vec![ResponseType::Code],
+            response_modes_supported: vec![
+                ResponseMode::Query,
+                ResponseMode::Fragment,
+                ResponseMode::FormPost,
+                ResponseMode::Jwt,
+                ResponseMode::QueryJwt,
+                ResponseMode::FragmentJwt,
+                ResponseMode::FormPostJwt,
+            ],
+            authorization_signing_alg_values_supported: router_state
+                .key_store
+                .read_signing_key()
+                .map(|signing_key| vec![signing_key.algorithm()])
+                .unwrap_or_default(),
+            grant_types_supported: vec![
+                GrantType::AuthorizationCode,
+                GrantType::RefreshToken,
+                GrantType::ClientCredentials,
+                GrantType::DeviceCode,
+            ],
+            token_endpoint_auth_methods_supported: vec![
+                TokenEndpointAuthMethod::None,
+                TokenEndpointAuthMethod::ClientSecretBasic,
+                TokenEndpointAuthMethod::ClientSecretPost,
+            ],
+            introspection_endpoint_auth_methods_supported: vec![
+                TokenEndpointAuthMethod::ClientSecretBasic,
+                TokenEndpointAuthMethod::ClientSecretPost,
+            ],
+            revocation_endpoint_auth_methods_supported: vec![
+                TokenEndpointAuthMethod::None,
+                TokenEndpointAuthMethod::ClientSecretBasic,
+                TokenEndpointAuthMethod::ClientSecretPost,
+            ],
+            code_challenge_methods_supported: vec![
+                CodeChallengeMethod::S256,
+                CodeChallengeMethod::Plain,
+            ],
+            dpop_signing_alg_values_supported: vec![
+                Algorithm::EdDSA,
+                Algorithm::ES256,
+                Algorithm::RS256,
+            ],
+        }
+    }
+}
+
+/// OpenID provider metadata (OpenID Connect Discovery section 3), which extends the
+/// authorization server metadata
+#[derive(Serialize, Debug)]
+pub struct OpenIdProviderMetadata {
+    #[serde(flatten)]
+    pub authorization_server_metadata: AuthorizationServerMetadata,
+    pub userinfo_endpoint: String,
+    /// OpenID Connect RP-Initiated Logout section 2.1
+    pub end_session_endpoint: String,
+    /// OpenID Connect Back-Channel Logout section 2.1
+    pub backchannel_logout_supported: bool,
+    pub backchannel_logout_session_supported: bool,
+    pub subject_types_supported: Vec<String>,
+    pub id_token_signing_alg_values_supported: Vec<Algorithm>,
+    pub claims_supported: Vec<String>,
+    pub acr_values_supported: Vec<String>,
+}
+
+impl OpenIdProviderMetadata {
+    pub fn new(router_state: &RouterState) -> Self {
+        let mut authorization_server_metadata = AuthorizationServerMetadata::new(router_state);
+        let scopes_supported = &mut authorization_server_metadata.scopes_supported;
+        if !scopes_supported.iter().any(|scope| scope == OPENID_SCOPE) {
+            scopes_supported.insert(0, OPENID_SCOPE.to_string());
+        }
+
+        // Only the active key signs ID tokens, staged and retired keys merely verify
+        let id_token_signing_alg_values_supported = router_state
+            .key_store
+            .read_signing_key()
+            .map(|signing_key| vec![signing_key.algorithm()])
+            .unwrap_or_default();
+
+        Self {
+            userinfo_endpoint: format!("{}{USERINFO_PATH}", authorization_server_metadata.issuer),
+            end_session_endpoint: format!("{}{LOGOUT_PATH}", authorization_server_metadata.issuer),
+            authorization_server_metadata,
+            backchannel_logout_supported: true,
+            backchannel_logout_session_supported: true,
+            subject_types_supported: vec!["public".to_string()],
+            id_token_signing_alg_values_supported,
+            claims_supported: CLAIMS_SUPPORTED.map(str::to_string).to_vec(),
+            acr_values_supported: vec![PASSWORD_ACR.to_string()],
+        }
+    }
+}
+
+pub async fn authorization_server_metadata_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+) -> Json<AuthorizationServerMetadata> {
+    Json(AuthorizationServerMetadata::new(&router_state))
+}
+
+pub async fn openid_provider_metadata_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+) -> Json<OpenIdProviderMetadata> {
+    Json(OpenIdProviderMetadata::new(&router_state))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        Json,
+        body::{Body, to_bytes},
+        extract::State,
+        http::{Method, Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    use crate::{
+        api::{
+            AUTHORIZATION_SERVER_METADATA_PATH, LOGOUT_PATH, OPENID_PROVIDER_METADATA_PATH,
+            RouterState, ServerSettings, USERINFO_PATH, create_router,
+            metadata::{authorization_server_metadata_endpoint, openid_provider_metadata_endpoint},
+        },
+        core::{
+            jose::{Algorithm, SigningKey},
+            key::{KeySettings, initialize_keys},
+        },
+        repository::key::MapKeyRepository,
+    };
+
+    fn create_router_state() -> RouterState {
+        RouterState {
+            key_store: MapKeyRepository::from_signing_keys(vec![
+                SigningKey::from_pem(include_str!("../core/testdata/rsa.pem")).unwrap(),
+                SigningKey::from_pem(include_str!("../core/testdata/ed25519.pem")).unwrap(),
+            ]),
+            server_settings: ServerSettings {
+                scopes_supported: vec!["read".to_string(), "write".to_string()],
+                ..Default::default()
+            },
+            ..RouterState::for_test()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authorization_server_metadata_endpoint() {
+        let Json(metadata) =
+            authorization_server_metadata_endpoint(State(Arc::new(create_router_state()))).await;
+        assert_eq!(metadata.issuer, "https://keyper.example.com");
+        assert_eq!(metadata.token_endpoint, "https://keyper.example.com/token");
+        assert_eq!(metadata.scopes_supported, vec!["read", "write"]);
+        assert_eq!(
+            metadata.registration_endpoint,
+            "https://keyper.example.com/register"
+        );
+
+        let router = create_router(Arc::new(create_router_state()));
+        let response = router
+            .oneshot(
+                Request::get(AUTHORIZATION_SERVER_METADATA_PATH)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#""response_types_supported":["code"]"#));
+        assert!(body.contains(r#""code_challenge_methods_supported":["S256","plain"]"#));
+        assert!(body.contains(r#""urn:ietf:params:oauth:grant-type:device_code""#));
+        assert!(body.contains(r#""client_secret_basic""#));
+        assert!(body.contains(r#""request_uri_parameter_supported":true"#));
+        assert!(body.contains(r#""form_post.jwt""#));
+        assert!(body.contains(r#""dpop_signing_alg_values_supported":["EdDSA","ES256","RS256"]"#));
+    }
+
+    #[tokio::test]
+    async fn test_openid_provider_metadata_endpoint() {
+        let Json(metadata) =
+            openid_provider_metadata_endpoint(State(Arc::new(create_router_state()))).await;
+        assert_eq!(
+            metadata.userinfo_endpoint,
+            "https://keyper.example.com/userinfo"
+        );
+        assert_eq!(
+            metadata.authorization_server_metadata.scopes_supported,
+            vec!["openid", "read", "write"]
+        );
+
+        let router = create_router(Arc::new(create_router_state()));
+        let response = router
+            .oneshot(
+                Request::get(OPENID_PROVIDER_METADATA_PATH)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains(r#""issuer":"https://keyper.example.com""#));
+        assert!(body.contains(r#""jwks_uri":"https://keyper.example.com/jwks.json""#));
+        assert!(body.contains(r#""subject_types_supported":["public"]"#));
+        assert!(body.contains(r#""id_token_signing_alg_values_supported":["RS256"]"#));
+        assert!(body.contains(r#""sid""#));
+        assert!(body.contains(r#""email""#));
+    }
+
+    #[tokio::test]
+    async fn test_openid_provider_metadata_default_keys() {
+        let router_state = RouterState {
+            key_store: MapKeyRepository::default(),
+            ..create_router_state()
+        };
+        initialize_keys(&KeySettings::default(), &router_state.key_store).unwrap();
+
+        let Json(metadata) = openid_provider_metadata_endpoint(State(Arc::new(router_state))).await;
+        assert_eq!(
+            metadata.id_token_signing_alg_values_supported,
+            vec![Algorithm::RS256]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_advertised_endpoints_are_routed() {
+        let Json(metadata) =
+            authorization_server_metadata_endpoint(State(Arc::new(create_router_state()))).await;
+
+        for (method, endpoint) in [
+            (Method::GET, metadata.authorization_endpoint),
+            (Method::POST, metadata.token_endpoint),
+            (Method::POST, metadata.device_authorization_endpoint),
+            (Method::POST, metadata.introspection_endpoint),
+            (Method::POST, metadata.revocation_endpoint),
+            (Method::GET, metadata.jwks_uri),
+            (Method::POST, metadata.registration_endpoint),
+            (Method::POST, metadata.pushed_authorization_request_endpoint),
+            (Method::GET, format!("{}{USERINFO_PATH}", metadata.issuer)),
+            (Method::POST, format!("{}{USERINFO_PATH}", metadata.issuer)),
+            (Method::GET, format!("{}{LOGOUT_PATH}", metadata.issuer)),
+            (
+                Method::GET,
+                format!("{}{OPENID_PROVIDER_METADATA_PATH}", metadata.issuer),
+            ),
+        ] {
+            let path = endpoint.strip_prefix(&metadata.issuer).unwrap();
+            let router = create_router(Arc::new(create_router_state()));
+            let response = router
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(path)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_ne!(response.status(), StatusCode::NOT_FOUND, "{path}");
+            assert_ne!(response.status(), StatusCode::METHOD_NOT_ALLOWED, "{path}");
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{RawForm, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use super::{RouterState, token::NO_STORE_HEADERS, token::client_basic_credentials};
+use crate::core::{
+    authorization::{AuthorizationError, AuthorizationErrorResponse, AuthorizationRequest},
+    pushed_authorization::{
+        PushedAuthorizationErrorResponse, PushedAuthorizationResponse, push_authorization_request,
+    },
+    token::ClientCredentials,
+};
+
+/// Client secret sent along with the pushed parameters by `client_secret_post` clients
+#[derive(Deserialize, Default, Debug)]
+pub struct PushedRequestCredentials {
+    pub client_secret: Option<String>,
+}
+
+/// Accepts the parameters of an authorization request in the form-encoded body (RFC 9126
+/// section 2.1). They are decoded twice since the client secret is no authorization parameter.
+pub async fn pushed_authorization_request_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    headers: HeaderMap,
+    RawForm(body): RawForm,
+) -> Result<(StatusCode, PushedAuthorizationResponse), PushedAuthorizationErrorResponse> {
+    let invalid_request = |error: serde_urlencoded::de::Error| {
+        let mut auth_error_response =
+            AuthorizationErrorResponse::new(AuthorizationError::InvalidRequest, None);
+        auth_error_response.error_description = Some(error.to_string());
+        PushedAuthorizationErrorResponse::Request(auth_error_response)
+    };
+    let auth_request: AuthorizationRequest =
+        serde_urlencoded::from_bytes(&body).map_err(invalid_request)?;
+    let credentials: PushedRequestCredentials =
+        serde_urlencoded::from_bytes(&body).map_err(invalid_request)?;
+
+    let client_credentials = ClientCredentials::from_request(
+        client_basic_credentials(&headers),
+        Some(auth_request.client_id.clone()),
+        credentials.client_secret,
+    )
+    .map_err(PushedAuthorizationErrorResponse::Client)?;
+
+    let response = push_authorization_request(
+        auth_request,
+        client_credentials,
+        &router_state.client_store,
+        &router_state.pushed_request_store,
+    )?;
+
+    Ok((StatusCode::CREATED, response))
+}
+
+impl IntoResponse for PushedAuthorizationResponse {
+    fn into_response(self) -> Response {
+        (NO_STORE_HEADERS, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for PushedAuthorizationErrorResponse {
+    fn into_response(self) -> Response {
+        match self {
+            PushedAuthorizationErrorResponse::Client(access_token_error_response) => {
+                access_token_error_response.into_response()
+            }
+            PushedAuthorizationErrorResponse::Request(auth_error_response) => {
+                let status_code = match auth_error_response.error {
+                    AuthorizationError::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                (status_code, NO_STORE_HEADERS, Json(auth_error_response)).into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Method, Request, StatusCode, header},
+    };
+    use serde_json::Value;
+    use tower::ServiceExt;
+
+    use crate::{
+        api::{RouterState, create_router},
+        repository::client::MapClientRepository,
+    };
+
+    fn create_router_state() -> Arc<RouterState> {
+        let client_store = MapClientRepository::try_from_toml(
+            r#"
+            [s6BhdRkqt3]
+            name = "Example Client"
+            client_type = "public"
+            redirect_uris = ["https://client.example.com/cb"]
+            require_pushed_authorization_requests = true
+            "#,
+        )
+        .unwrap();
+
+        Arc::new(RouterState {
+            client_store,
+            ..RouterState::for_test()
+        })
+    }
+
+    fn create_request(method: Method, uri: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pushed_authorization_request_endpoint() {
+        let router = create_router(create_router_state());
+
+        let response = router
+            .clone()
+            .oneshot(create_request(
+                Method::GET,
+                "/authorization?response_type=code&client_id=s6BhdRkqt3&state=xyz",
+                "",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.starts_with("https://client.example.com/cb?error=invalid_request"));
+
+        let response = router
+            .clone()
+            .oneshot(create_request(
+                Method::POST,
+                "/par",
+                "response_type=token&client_id=s6BhdRkqt3&state=xyz",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "unsupported_response_type");
+        assert!(body.get("state").is_none());
+
+        let response = router
+            .clone()
+            .oneshot(create_request(
+                Method::POST,
+                "/par",
+                "response_type=code&client_id=s6BhdRkqt3&state=xyz&client_secret=gX1fBat3bV",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = router
+            .clone()
+            .oneshot(create_request(
+                Method::POST,
+                "/par",
+                "response_type=code&client_id=s6BhdRkqt3&state=xyz&max_age=0",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["expires_in"], 300);
+        let request_uri = body["request_uri"].as_str().unwrap();
+
+        let query = serde_urlencoded::to_string([
+            ("client_id", "s6BhdRkqt3"),
+            ("request_uri", request_uri),
+        ])
+        .unwrap();
+        let response = router
+            .clone()
+            .oneshot(create_request(
+                Method::GET,
+                &format!("/authorization?{query}"),
+                "",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.starts_with("/authentication?return_to="));
+        assert!(location.contains("request_uri"));
+
+        let query = serde_urlencoded::to_string([
+            ("client_id", "s6BhdRkqt3"),
+            ("request_uri", "urn:ietf:params:oauth:request_uri:unknown"),
+        ])
+        .unwrap();
+        let response = router
+            .oneshot(create_request(
+                Method::GET,
+                &format!("/authorization?{query}"),
+                "",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(!response.headers().contains_key(header::LOCATION));
+    }
+}
@@ -0,0 +1,331 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Path, State, rejection::JsonRejection},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+
+use super::{REGISTRATION_PATH, RouterState, token::NO_STORE_HEADERS, userinfo::bearer_token};
+use crate::core::registration::{
+    self, ClientInformationResponse, ClientMetadata, ClientRegistrationError,
+    ClientRegistrationErrorResponse, ClientUpdateRequest,
+};
+
+/// Registers a client (RFC 7591 section 3). If an initial access token is configured, it has to
+/// be presented as bearer token. Otherwise clients are registered as `self_registered`.
+pub async fn registration_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    headers: HeaderMap,
+    client_metadata: Result<Json<ClientMetadata>, JsonRejection>,
+) -> Result<(StatusCode, ClientInformationResponse), ClientRegistrationErrorResponse> {
+    if let Some(initial_access_token) = &router_state.server_settings.initial_access_token
+        && bearer_token(&headers).as_ref() != Some(initial_access_token)
+    {
+        return Err(ClientRegistrationErrorResponse::new(
+            ClientRegistrationError::InvalidToken,
+        ));
+    }
+    let Json(client_metadata) = client_metadata.map_err(invalid_client_metadata)?;
+
+    let response = registration::register_client(
+        client_metadata,
+        router_state.server_settings.initial_access_token.is_none(),
+        &router_state.server_settings.scopes_supported,
+        &registration_endpoint_uri(&router_state),
+        &router_state.client_store,
+    )?;
+
+    Ok((StatusCode::CREATED, response))
+}
+
+pub async fn client_configuration_get_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    Path(client_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<ClientInformationResponse, ClientRegistrationErrorResponse> {
+    let registration_access_token = registration_access_token(&headers)?;
+
+    registration::read_client_registration(
+        &client_id,
+        &registration_access_token,
+        &registration_endpoint_uri(&router_state),
+        &router_state.client_store,
+    )
+}
+
+pub async fn client_configuration_put_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    Path(client_id): Path<String>,
+    headers: HeaderMap,
+    update_request: Result<Json<ClientUpdateRequest>, JsonRejection>,
+) -> Result<ClientInformationResponse, ClientRegistrationErrorResponse> {
+    let registration_access_token = registration_access_token(&headers)?;
+    let Json(update_request) = update_request.map_err(invalid_client_metadata)?;
+
+    registration::update_client_registration(
+        &client_id,
+        &registration_access_token,
+        update_request,
+        &router_state.server_settings.scopes_supported,
+        &registration_endpoint_uri(&router_state),
+        &router_state.client_store,
+    )
+}
+
+pub async fn client_configuration_delete_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    Path(client_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ClientRegistrationErrorResponse> {
+    let registration_access_token = registration_access_token(&headers)?;
+
+    registration::delete_client_registration(
+        &client_id,
+        &registration_access_token,
+        &router_state.client_store,
+    )?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn registration_access_token(
+    headers: &HeaderMap,
+) -> Result<String, ClientRegistrationErrorResponse> {
+    bearer_token(headers)
+        .ok_or_else(|| ClientRegistrationErrorResponse::new(ClientRegistrationError::InvalidToken))
+}
+
+fn registration_endpoint_uri(router_state: &RouterState) -> String {
+    format!("{}{REGISTRATION_PATH}", router_state.token_settings.issuer)
+}
+
+fn invalid_client_metadata(rejection: JsonRejection) -> ClientRegistrationErrorResponse {
+    let mut error_response =
+        ClientRegistrationErrorResponse::new(ClientRegistrationError::InvalidClientMetadata);
+    error_response.error_description = Some(rejection.body_text());
+    error_response
+}
+
+impl IntoResponse for ClientInformationResponse {
+    fn into_response(self) -> Response {
+        (NO_STORE_HEADERS, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for ClientRegistrationErrorResponse {
+    fn into_response(self) -> Response {
+        match self.error {
+            ClientRegistrationError::InvalidToken => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Bearer error=\"invalid_token\"")],
+            )
+                .into_response(),
+            ClientRegistrationError::ServerError => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                NO_STORE_HEADERS,
+                Json(self),
+            )
+                .into_response(),
+            ClientRegistrationError::InvalidRedirectUri
+            | ClientRegistrationError::InvalidClientMetadata => {
+                (StatusCode::BAD_REQUEST, NO_STORE_HEADERS, Json(self)).into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::{Body, to_bytes},
+        http::{Method, Request, StatusCode, header},
+    };
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use crate::{
+        api::{RouterState, ServerSettings, create_router},
+        core::authorization::ClientRepository,
+    };
+
+    fn create_router_state() -> Arc<RouterState> {
+        Arc::new(RouterState {
+            server_settings: ServerSettings {
+                scopes_supported: vec!["read".to_string()],
+                initial_access_token: Some("ejHm6qxWTd".to_string()),
+            },
+            ..RouterState::for_test()
+        })
+    }
+
+    fn create_request(
+        method: Method,
+        uri: &str,
+        token: &str,
+        body: Option<Value>,
+    ) -> Request<Body> {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {token}"));
+
+        match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+            None => request.body(Body::empty()).unwrap(),
+        }
+    }
+
+    async fn json_body(response: axum::response::Response) -> Value {
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_registration_endpoint() {
+        let router_state = create_router_state();
+        let router = create_router(router_state.clone());
+        let client_metadata = json!({
+            "redirect_uris": ["https://client.example.com/cb"],
+            "client_name": "Example Client",
+            "scope": "read",
+        });
+
+        let response = router
+            .clone()
+            .oneshot(create_request(
+                Method::POST,
+                "/register",
+                "wrong",
+                Some(client_metadata.clone()),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = router
+            .clone()
+            .oneshot(create_request(
+                Method::POST,
+                "/register",
+                "ejHm6qxWTd",
+                Some(json!({"redirect_uris": ["cb"]})),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json_body(response).await["error"], "invalid_redirect_uri");
+
+        let response = router
+            .clone()
+            .oneshot(create_request(
+                Method::POST,
+                "/register",
+                "ejHm6qxWTd",
+                Some(client_metadata),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+
+        let body = json_body(response).await;
+        let client_id = body["client_id"].as_str().unwrap();
+        assert!(body["client_secret"].is_string());
+        assert_eq!(body["client_secret_expires_at"], 0);
+        assert_eq!(body["token_endpoint_auth_method"], "client_secret_basic");
+        assert_eq!(
+            body["registration_client_uri"],
+            format!("https://keyper.example.com/register/{client_id}")
+        );
+        assert!(router_state.client_store.read_client(client_id).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_client_configuration_endpoints() {
+        let router_state = create_router_state();
+        let router = create_router(router_state.clone());
+
+        let response = router
+            .clone()
+            .oneshot(create_request(
+                Method::POST,
+                "/register",
+                "ejHm6qxWTd",
+                Some(json!({
+                    "redirect_uris": ["https://client.example.com/cb"],
+                    "token_endpoint_auth_method": "none",
+                })),
+            ))
+            .await
+            .unwrap();
+        let body = json_body(response).await;
+        let client_id = body["client_id"].as_str().unwrap();
+        let token = body["registration_access_token"].as_str().unwrap();
+        let uri = format!("/register/{client_id}");
+
+        let response = router
+            .clone()
+            .oneshot(create_request(Method::GET, &uri, token, None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["client_id"], client_id);
+        assert_eq!(body["registration_access_token"], token);
+        assert!(body.get("client_secret").is_none());
+
+        let response = router
+            .clone()
+            .oneshot(create_request(Method::GET, &uri, "ejHm6qxWTd", None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            "Bearer error=\"invalid_token\""
+        );
+
+        let response = router
+            .clone()
+            .oneshot(create_request(
+                Method::PUT,
+                &uri,
+                token,
+                Some(json!({
+                    "client_id": client_id,
+                    "redirect_uris": ["https://client.example.com/callback"],
+                    "client_name": "Renamed Client",
+                })),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["client_name"], "Renamed Client");
+        let client = router_state.client_store.read_client(client_id).unwrap();
+        assert_eq!(
+            client.redirect_uris,
+            vec!["https://client.example.com/callback"]
+        );
+
+        let response = router
+            .clone()
+            .oneshot(create_request(Method::DELETE, &uri, token, None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        assert!(router_state.client_store.read_client(client_id).is_none());
+
+        let response = router
+            .oneshot(create_request(Method::GET, &uri, token, None))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
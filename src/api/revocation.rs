@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Form, State, rejection::FormRejection},
+    http::{HeaderMap, StatusCode},
+};
+
+use super::{RouterState, token::client_basic_credentials};
+use crate::core::{
+    revocation::{RevocationRequest, revoke},
+    token::{AccessTokenError, AccessTokenErrorResponse, ClientCredentials},
+};
+
+pub async fn revocation_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    headers: HeaderMap,
+    revocation_request: Result<Form<RevocationRequest>, FormRejection>,
+) -> Result<StatusCode, AccessTokenErrorResponse> {
+    let Form(mut revocation_request) = revocation_request.map_err(|rejection| {
+        let mut access_token_error_response =
+            AccessTokenErrorResponse::new(AccessTokenError::InvalidRequest);
+        access_token_error_response.error_description = Some(rejection.body_text());
+        access_token_error_response
+    })?;
+
+    let client_credentials = ClientCredentials::from_request(
+        client_basic_credentials(&headers),
+        revocation_request.client_id.clone(),
+        revocation_request.client_secret.take(),
+    )?;
+
+    revoke(
+        revocation_request,
+        client_credentials,
+        &router_state.client_store,
+        &router_state.authorization_store,
+        &router_state.refresh_token_store,
+    )
+    .await?;
+
+    Ok(StatusCode::OK)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        extract::{Form, State},
+        http::{HeaderMap, StatusCode},
+        response::IntoResponse,
+    };
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        api::{RouterState, revocation::revocation_endpoint},
+        core::{
+            revocation::RevocationRequest,
+            token::{Authorization, AuthorizationRepository},
+        },
+        repository::{authorization::MapAuthorizationRepository, client::MapClientRepository},
+    };
+
+    fn create_router_state() -> Arc<RouterState> {
+        let client_store = MapClientRepository::try_from_toml(
+            r#"
+            [foobar]
+            name = "Public Client"
+            client_type = "public"
+            redirect_uris = []
+        "#,
+        )
+        .unwrap();
+
+        let authorization_store = MapAuthorizationRepository::default();
+        let created = Utc::now();
+        authorization_store
+            .create_authorization(Authorization {
+                access_token: "2YotnFZFEjr1zCsicMWpAA".to_string(),
+                client_id: "foobar".to_string(),
+                scopes: Vec::new(),
+                owner: Some("alice".to_string()),
+                created,
+                expires: created + Duration::seconds(3600),
+                refresh_token: None,
+                jkt: None,
+            })
+            .unwrap();
+
+        Arc::new(RouterState {
+            client_store,
+            authorization_store,
+            ..RouterState::for_test()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_revocation_endpoint() {
+        let router_state = create_router_state();
+
+        let request = RevocationRequest {
+            token: "2YotnFZFEjr1zCsicMWpAA".to_string(),
+            client_id: Some("unknown".to_string()),
+            ..Default::default()
+        };
+        let response = revocation_endpoint(
+            State(router_state.clone()),
+            HeaderMap::new(),
+            Ok(Form(request)),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(
+            router_state
+                .authorization_store
+                .read_authorization("2YotnFZFEjr1zCsicMWpAA")
+                .is_some()
+        );
+
+        let request = RevocationRequest {
+            token: "2YotnFZFEjr1zCsicMWpAA".to_string(),
+            client_id: Some("foobar".to_string()),
+            ..Default::default()
+        };
+        let response = revocation_endpoint(
+            State(router_state.clone()),
+            HeaderMap::new(),
+            Ok(Form(request)),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            router_state
+                .authorization_store
+                .read_authorization("2YotnFZFEjr1zCsicMWpAA")
+                .is_none()
+        );
+    }
+}
@@ -1,27 +1,386 @@
 use axum::{
-    extract::{Query, State},
+    Json,
+    extract::{Form, State, rejection::FormRejection},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
+use percent_encoding::percent_decode_str;
 use std::sync::Arc;
+use tracing::error;
 
-use crate::core::token::{access_token, AccessTokenErrorResponse, AccessTokenRequest, AccessTokenResponse};
-use super::RouterState;
+use super::{RouterState, TOKEN_PATH, basic_credentials};
+use crate::core::{
+    dpop::{self, DpopError},
+    token::{
+        AccessTokenError, AccessTokenErrorResponse, AccessTokenRequest, AccessTokenResponse,
+        ClientCredentials, access_token,
+    },
+};
+
+pub const NO_STORE_HEADERS: [(HeaderName, &str); 2] = [
+    (header::CACHE_CONTROL, "no-store"),
+    (header::PRAGMA, "no-cache"),
+];
+pub const DPOP_HEADER: HeaderName = HeaderName::from_static("dpop");
+pub const DPOP_NONCE_HEADER: HeaderName = HeaderName::from_static("dpop-nonce");
 
+/// Issues access tokens. Requests carrying a DPoP proof get tokens bound to its key, and every
+/// response to them carries the nonce for the next proof (RFC 9449 section 8).
 pub async fn token_endpoint(
     State(router_state): State<Arc<RouterState>>,
-    Query(access_token_request): Query<AccessTokenRequest>,
+    headers: HeaderMap,
+    access_token_request: Result<Form<AccessTokenRequest>, FormRejection>,
+) -> Response {
+    let response = token_response(&router_state, &headers, access_token_request)
+        .await
+        .into_response();
+
+    if headers.contains_key(DPOP_HEADER) {
+        with_dpop_nonce(&router_state, response)
+    } else {
+        response
+    }
+}
+
+async fn token_response(
+    router_state: &RouterState,
+    headers: &HeaderMap,
+    access_token_request: Result<Form<AccessTokenRequest>, FormRejection>,
 ) -> Result<AccessTokenResponse, AccessTokenErrorResponse> {
-    access_token(access_token_request).await
+    let Form(mut access_token_request) = access_token_request.map_err(|rejection| {
+        let mut access_token_error_response =
+            AccessTokenErrorResponse::new(AccessTokenError::InvalidRequest);
+        access_token_error_response.error_description = Some(rejection.body_text());
+        access_token_error_response
+    })?;
+
+    let client_credentials = ClientCredentials::from_request(
+        client_basic_credentials(headers),
+        access_token_request.client_id.clone(),
+        access_token_request.client_secret.take(),
+    )?;
+
+    if let Some(proof) = dpop_proof(headers)? {
+        let htu = format!("{}{TOKEN_PATH}", router_state.token_settings.issuer);
+        access_token_request.dpop_jkt = Some(dpop::verify_dpop_proof(
+            proof,
+            "POST",
+            &htu,
+            None,
+            &router_state.dpop_store,
+        )?);
+    }
+
+    access_token(
+        access_token_request,
+        client_credentials,
+        &router_state.token_settings,
+        &router_state.client_store,
+        &router_state.authorization_code_store,
+        &router_state.authorization_store,
+        &router_state.refresh_token_store,
+        &router_state.device_code_store,
+        &router_state.owner_store,
+        &router_state.key_store,
+    )
+    .await
+}
+
+/// The single DPoP proof of a request, if any (RFC 9449 section 4.3)
+pub fn dpop_proof(headers: &HeaderMap) -> Result<Option<&str>, DpopError> {
+    let mut proofs = headers.get_all(DPOP_HEADER).iter();
+    match (proofs.next(), proofs.next()) {
+        (None, _) => Ok(None),
+        (Some(proof), None) => proof
+            .to_str()
+            .map(Some)
+            .map_err(|_| DpopError::InvalidProof("DPoP proof is no JWS")),
+        (Some(_), Some(_)) => Err(DpopError::InvalidProof("Only one DPoP proof is allowed")),
+    }
+}
+
+/// Hands out the nonce clients have to use in their next DPoP proof
+pub fn with_dpop_nonce(router_state: &RouterState, mut response: Response) -> Response {
+    match dpop::dpop_nonce(&router_state.dpop_store)
+        .and_then(|nonce| Ok(HeaderValue::from_str(&nonce)?))
+    {
+        Ok(nonce) => {
+            response.headers_mut().insert(DPOP_NONCE_HEADER, nonce);
+        }
+        Err(error) => error!("Could not issue DPoP nonce: {error}"),
+    }
+
+    response
+}
+
+/// Client ID and secret are form-urlencoded before being put into the basic authorization header
+/// (RFC 6749 section 2.3.1).
+pub fn client_basic_credentials(headers: &HeaderMap) -> Option<(String, String)> {
+    let (client_id, client_secret) = basic_credentials(headers)?;
+    let decode = |value: &str| {
+        percent_decode_str(&value.replace('+', " "))
+            .decode_utf8()
+            .map(|value| value.into_owned())
+            .ok()
+    };
+
+    Some((decode(&client_id)?, decode(&client_secret)?))
 }
 
 impl IntoResponse for AccessTokenResponse {
     fn into_response(self) -> Response {
-        todo!()
+        (NO_STORE_HEADERS, Json(self)).into_response()
     }
 }
 
 impl IntoResponse for AccessTokenErrorResponse {
     fn into_response(self) -> Response {
-        todo!()
+        if self.error == AccessTokenError::InvalidClient {
+            return (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, "Basic realm=\"Token\"")],
+                NO_STORE_HEADERS,
+                Json(self),
+            )
+                .into_response();
+        }
+
+        let status_code = match self.error {
+            AccessTokenError::InvalidClient => StatusCode::UNAUTHORIZED,
+            AccessTokenError::InvalidRequest
+            | AccessTokenError::InvalidGrant
+            | AccessTokenError::UnauthorizedClient
+            | AccessTokenError::UnsupportedGrantType
+            | AccessTokenError::InvalidScope
+            | AccessTokenError::AuthorizationPending
+            | AccessTokenError::SlowDown
+            | AccessTokenError::ExpiredToken
+            | AccessTokenError::AccessDenied
+            | AccessTokenError::InvalidDpopProof
+            | AccessTokenError::UseDpopNonce => StatusCode::BAD_REQUEST,
+            AccessTokenError::ServerError => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status_code, NO_STORE_HEADERS, Json(self)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use argon2::{
+        Argon2, PasswordHasher,
+        password_hash::{SaltString, rand_core::OsRng},
+    };
+    use axum::{
+        body::to_bytes,
+        extract::{Form, State},
+        http::{HeaderMap, StatusCode, header},
+        response::IntoResponse,
+    };
+    use chrono::{Duration, Utc};
+    use serde_json::Value;
+
+    use crate::{
+        api::{
+            RouterState,
+            token::{DPOP_HEADER, DPOP_NONCE_HEADER, token_endpoint},
+        },
+        core::{
+            authorization::{AuthorizationCode, AuthorizationCodeRepository, CodeChallengeMethod},
+            dpop::tests::create_proof,
+            jose::{Algorithm, SigningKey},
+            token::{AccessTokenRequest, GrantType},
+        },
+        repository::{
+            authorization_code::MapAuthorizationCodeRepository, client::MapClientRepository,
+        },
+    };
+
+    fn create_router_state(client_id: &str) -> Arc<RouterState> {
+        let salt = SaltString::generate(&mut OsRng);
+        let secret_hash = Argon2::default()
+            .hash_password(b"gX1fBat3bV", &salt)
+            .unwrap()
+            .to_string();
+        let client_store = MapClientRepository::try_from_toml(&format!(
+            r#"
+            [foobar]
+            name = "Public Client"
+            client_type = "public"
+            redirect_uris = []
+
+            [s6BhdRkqt3]
+            name = "Confidential Client"
+            client_type = "confidential"
+            redirect_uris = []
+            client_secret_hash = "{secret_hash}"
+        "#
+        ))
+        .unwrap();
+
+        let authorization_code_store = MapAuthorizationCodeRepository::default();
+        let created = Utc::now();
+        authorization_code_store
+            .create_authorization_code(AuthorizationCode {
+                code: "SplxlOBeZQQYbYS6WxSbIA".to_string(),
+                client_id: client_id.to_string(),
+                redirect_uri: None,
+                scopes: Vec::new(),
+                owner: "alice".to_string(),
+                code_challenge: None,
+                code_challenge_method: CodeChallengeMethod::Plain,
+                nonce: None,
+                auth_time: created,
+                sid: None,
+                expires: created + Duration::seconds(600),
+            })
+            .unwrap();
+
+        Arc::new(RouterState {
+            client_store,
+            authorization_code_store,
+            ..RouterState::for_test()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_token_endpoint() {
+        let request = AccessTokenRequest {
+            grant_type: GrantType::AuthorizationCode,
+            code: Some("SplxlOBeZQQYbYS6WxSbIA".to_string()),
+            redirect_uri: None,
+            client_id: Some("foobar".to_string()),
+            ..Default::default()
+        };
+
+        let response = token_endpoint(
+            State(create_router_state("foobar")),
+            HeaderMap::new(),
+            Ok(Form(request)),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+        assert_eq!(response.headers()[header::PRAGMA], "no-cache");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+    }
+
+    #[tokio::test]
+    async fn test_token_endpoint_invalid_grant() {
+        let request = AccessTokenRequest {
+            grant_type: GrantType::AuthorizationCode,
+            code: Some("unknown".to_string()),
+            redirect_uri: None,
+            client_id: Some("foobar".to_string()),
+            ..Default::default()
+        };
+
+        let response = token_endpoint(
+            State(create_router_state("foobar")),
+            HeaderMap::new(),
+            Ok(Form(request)),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, r#"{"error":"invalid_grant"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_token_endpoint_client_secret_basic() {
+        for (authorization, expected_status) in [
+            ("Basic czZCaGRSa3F0MzpnWDFmQmF0M2JW", StatusCode::OK),
+            ("Basic czZCaGRSa3F0Mzp3cm9uZw==", StatusCode::UNAUTHORIZED),
+        ] {
+            let request = AccessTokenRequest {
+                grant_type: GrantType::AuthorizationCode,
+                code: Some("SplxlOBeZQQYbYS6WxSbIA".to_string()),
+                ..Default::default()
+            };
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, authorization.parse().unwrap());
+
+            let response = token_endpoint(
+                State(create_router_state("s6BhdRkqt3")),
+                headers,
+                Ok(Form(request)),
+            )
+            .await
+            .into_response();
+
+            assert_eq!(response.status(), expected_status);
+            if expected_status == StatusCode::UNAUTHORIZED {
+                assert!(response.headers().contains_key(header::WWW_AUTHENTICATE));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_token_endpoint_missing_client_secret() {
+        let request = AccessTokenRequest {
+            grant_type: GrantType::AuthorizationCode,
+            code: Some("SplxlOBeZQQYbYS6WxSbIA".to_string()),
+            client_id: Some("s6BhdRkqt3".to_string()),
+            ..Default::default()
+        };
+
+        let response = token_endpoint(
+            State(create_router_state("s6BhdRkqt3")),
+            HeaderMap::new(),
+            Ok(Form(request)),
+        )
+        .await
+        .into_response();
+
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, r#"{"error":"invalid_client"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_token_endpoint_dpop() {
+        let router_state = create_router_state("foobar");
+        let signing_key = SigningKey::generate(Algorithm::ES256).unwrap();
+        let htu = "https://keyper.example.com/token";
+        let request = || AccessTokenRequest {
+            grant_type: GrantType::AuthorizationCode,
+            code: Some("SplxlOBeZQQYbYS6WxSbIA".to_string()),
+            client_id: Some("foobar".to_string()),
+            ..Default::default()
+        };
+
+        let mut headers = HeaderMap::new();
+        let proof = create_proof(&signing_key, "POST", htu, None, None);
+        headers.insert(DPOP_HEADER, proof.parse().unwrap());
+        let response =
+            token_endpoint(State(router_state.clone()), headers, Ok(Form(request()))).await;
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let nonce = response.headers()[DPOP_NONCE_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"], "use_dpop_nonce");
+
+        let mut headers = HeaderMap::new();
+        let proof = create_proof(&signing_key, "POST", htu, Some(&nonce), None);
+        headers.insert(DPOP_HEADER, proof.parse().unwrap());
+        let response = token_endpoint(State(router_state), headers, Ok(Form(request()))).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().contains_key(DPOP_NONCE_HEADER));
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["token_type"], "DPoP");
     }
 }
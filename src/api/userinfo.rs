@@ -0,0 +1,354 @@
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::{Form, State, rejection::FormRejection},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use tracing::error;
+
+use super::{
+    RouterState, USERINFO_PATH,
+    token::{DPOP_NONCE_HEADER, NO_STORE_HEADERS, dpop_proof},
+};
+use crate::core::{
+    dpop::{self, DpopError},
+    oidc::{self, BearerTokenError, UserInfoResponse},
+};
+
+#[derive(Deserialize, Default, Debug)]
+pub struct UserInfoRequest {
+    pub access_token: Option<String>,
+}
+
+pub async fn userinfo_get_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    headers: HeaderMap,
+) -> Result<UserInfoResponse, BearerTokenError> {
+    let (access_token, jkt) = authorization_token(&router_state, &headers, "GET")?
+        .ok_or(BearerTokenError::InvalidRequest)?;
+
+    oidc::userinfo(
+        &access_token,
+        jkt.as_deref(),
+        &router_state.authorization_store,
+        &router_state.owner_store,
+    )
+}
+
+/// Clients may also send the access token in the form-encoded body (RFC 6750 section 2.2), but
+/// not in both places at once
+pub async fn userinfo_post_endpoint(
+    State(router_state): State<Arc<RouterState>>,
+    headers: HeaderMap,
+    userinfo_request: Result<Form<UserInfoRequest>, FormRejection>,
+) -> Result<UserInfoResponse, BearerTokenError> {
+    let body_token = userinfo_request
+        .map(|Form(userinfo_request)| userinfo_request.access_token)
+        .unwrap_or_default();
+    let (access_token, jkt) = match (
+        authorization_token(&router_state, &headers, "POST")?,
+        body_token,
+    ) {
+        (Some(authorization_token), None) => authorization_token,
+        (None, Some(access_token)) => (access_token, None),
+        _ => return Err(BearerTokenError::InvalidRequest),
+    };
+
+    oidc::userinfo(
+        &access_token,
+        jkt.as_deref(),
+        &router_state.authorization_store,
+        &router_state.owner_store,
+    )
+}
+
+/// Access token of the `Authorization` header, along with the JWK thumbprint of the DPoP proof
+/// for tokens presented with the `DPoP` scheme (RFC 9449 section 7.1)
+fn authorization_token(
+    router_state: &RouterState,
+    headers: &HeaderMap,
+    htm: &str,
+) -> Result<Option<(String, Option<String>)>, BearerTokenError> {
+    if let Some(access_token) = bearer_token(headers) {
+        return Ok(Some((access_token, None)));
+    }
+    let Some(access_token) = scheme_token(headers, "DPoP") else {
+        return Ok(None);
+    };
+
+    let htu = format!("{}{USERINFO_PATH}", router_state.token_settings.issuer);
+    let jkt = dpop_proof(headers)
+        .and_then(|proof| proof.ok_or(DpopError::InvalidProof("DPoP proof is missing")))
+        .and_then(|proof| {
+            dpop::verify_dpop_proof(
+                proof,
+                htm,
+                &htu,
+                Some(&access_token),
+                &router_state.dpop_store,
+            )
+        })
+        .map_err(|error| match error {
+            DpopError::UseNonce => match dpop::dpop_nonce(&router_state.dpop_store) {
+                Ok(nonce) => BearerTokenError::UseDpopNonce(nonce),
+                Err(error) => {
+                    error!("Could not issue DPoP nonce: {error}");
+                    BearerTokenError::InvalidDpopProof
+                }
+            },
+            DpopError::InvalidProof(_) | DpopError::ServerError => {
+                BearerTokenError::InvalidDpopProof
+            }
+        })?;
+
+    Ok(Some((access_token, Some(jkt))))
+}
+
+/// Extracts the access token from an `Authorization: Bearer` header (RFC 6750 section 2.1)
+pub fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    scheme_token(headers, "Bearer")
+}
+
+fn scheme_token(headers: &HeaderMap, scheme: &str) -> Option<String> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (value_scheme, token) = value.split_once(' ')?;
+
+    (value_scheme.eq_ignore_ascii_case(scheme) && !token.trim().is_empty())
+        .then(|| token.trim().to_string())
+}
+
+impl IntoResponse for UserInfoResponse {
+    fn into_response(self) -> Response {
+        (NO_STORE_HEADERS, Json(self)).into_response()
+    }
+}
+
+impl IntoResponse for BearerTokenError {
+    fn into_response(self) -> Response {
+        let (status_code, challenge) = match &self {
+            BearerTokenError::InvalidRequest => (StatusCode::UNAUTHORIZED, "Bearer".to_string()),
+            BearerTokenError::InvalidToken => (
+                StatusCode::UNAUTHORIZED,
+                "Bearer error=\"invalid_token\"".to_string(),
+            ),
+            BearerTokenError::InsufficientScope => (
+                StatusCode::FORBIDDEN,
+                format!(
+                    "Bearer error=\"insufficient_scope\", scope=\"{}\"",
+                    oidc::OPENID_SCOPE
+                ),
+            ),
+            BearerTokenError::InvalidDpopProof => (
+                StatusCode::UNAUTHORIZED,
+                "DPoP error=\"invalid_dpop_proof\"".to_string(),
+            ),
+            BearerTokenError::UseDpopNonce(_) => (
+                StatusCode::UNAUTHORIZED,
+                "DPoP error=\"use_dpop_nonce\"".to_string(),
+            ),
+        };
+
+        let mut response = (status_code, [(header::WWW_AUTHENTICATE, challenge)]).into_response();
+        if let BearerTokenError::UseDpopNonce(nonce) = self
+            && let Ok(nonce) = nonce.parse()
+        {
+            response.headers_mut().insert(DPOP_NONCE_HEADER, nonce);
+        }
+
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::to_bytes,
+        extract::{Form, State},
+        http::{HeaderMap, StatusCode, header},
+        response::IntoResponse,
+    };
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        api::{
+            RouterState,
+            token::{DPOP_HEADER, DPOP_NONCE_HEADER},
+            userinfo::{UserInfoRequest, userinfo_get_endpoint, userinfo_post_endpoint},
+        },
+        core::{
+            dpop::tests::create_proof,
+            jose::{Algorithm, SigningKey},
+            token::{Authorization, AuthorizationRepository},
+        },
+        repository::{authorization::MapAuthorizationRepository, owner::MapOwnerRepository},
+    };
+
+    fn create_router_state() -> Arc<RouterState> {
+        let authorization_store = MapAuthorizationRepository::default();
+        let created = Utc::now();
+        authorization_store
+            .create_authorization(Authorization {
+                access_token: "SlAV32hkKG".to_string(),
+                client_id: "s6BhdRkqt3".to_string(),
+                scopes: vec!["openid".to_string(), "email".to_string()],
+                owner: Some("alice".to_string()),
+                created,
+                expires: created + Duration::seconds(3600),
+                refresh_token: None,
+                jkt: None,
+            })
+            .unwrap();
+        let owner_store = MapOwnerRepository::try_from_toml(
+            r#"
+            [alice]
+            email = "alice@example.com"
+            hash = "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaA"
+            "#,
+        )
+        .unwrap();
+
+        Arc::new(RouterState {
+            authorization_store,
+            owner_store,
+            ..RouterState::for_test()
+        })
+    }
+
+    fn create_headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, authorization.parse().unwrap());
+        headers
+    }
+
+    #[tokio::test]
+    async fn test_userinfo_get_endpoint() {
+        let router_state = create_router_state();
+
+        let response = userinfo_get_endpoint(
+            State(router_state.clone()),
+            create_headers("Bearer SlAV32hkKG"),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], "no-store");
+
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(body, r#"{"sub":"alice","email":"alice@example.com"}"#);
+
+        let response = userinfo_get_endpoint(
+            State(router_state.clone()),
+            create_headers("Bearer unknown"),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            "Bearer error=\"invalid_token\""
+        );
+
+        let response = userinfo_get_endpoint(State(router_state), HeaderMap::new())
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+    }
+
+    #[tokio::test]
+    async fn test_userinfo_post_endpoint() {
+        let router_state = create_router_state();
+
+        let response = userinfo_post_endpoint(
+            State(router_state.clone()),
+            HeaderMap::new(),
+            Ok(Form(UserInfoRequest {
+                access_token: Some("SlAV32hkKG".to_string()),
+            })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.sub, "alice");
+
+        let response = userinfo_post_endpoint(
+            State(router_state),
+            create_headers("Bearer SlAV32hkKG"),
+            Ok(Form(UserInfoRequest {
+                access_token: Some("SlAV32hkKG".to_string()),
+            })),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_userinfo_dpop() {
+        let router_state = create_router_state();
+        let signing_key = SigningKey::generate(Algorithm::ES256).unwrap();
+        let htu = "https://keyper.example.com/userinfo";
+        let created = Utc::now();
+        router_state
+            .authorization_store
+            .create_authorization(Authorization {
+                access_token: "Kz~8mXK1EalY".to_string(),
+                client_id: "s6BhdRkqt3".to_string(),
+                scopes: vec!["openid".to_string()],
+                owner: Some("alice".to_string()),
+                created,
+                expires: created + Duration::seconds(3600),
+                refresh_token: None,
+                jkt: Some(signing_key.public_jwk().thumbprint()),
+            })
+            .unwrap();
+
+        let response = userinfo_get_endpoint(
+            State(router_state.clone()),
+            create_headers("Bearer Kz~8mXK1EalY"),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let mut headers = create_headers("DPoP Kz~8mXK1EalY");
+        let proof = create_proof(&signing_key, "GET", htu, None, Some("Kz~8mXK1EalY"));
+        headers.insert(DPOP_HEADER, proof.parse().unwrap());
+        let response = userinfo_get_endpoint(State(router_state.clone()), headers)
+            .await
+            .into_response();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            "DPoP error=\"use_dpop_nonce\""
+        );
+        let nonce = response.headers()[DPOP_NONCE_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+
+        let mut headers = create_headers("DPoP Kz~8mXK1EalY");
+        let proof = create_proof(&signing_key, "GET", htu, Some(&nonce), Some("other"));
+        headers.insert(DPOP_HEADER, proof.parse().unwrap());
+        let response = userinfo_get_endpoint(State(router_state.clone()), headers)
+            .await
+            .into_response();
+        assert_eq!(
+            response.headers()[header::WWW_AUTHENTICATE],
+            "DPoP error=\"invalid_dpop_proof\""
+        );
+
+        let mut headers = create_headers("DPoP Kz~8mXK1EalY");
+        let proof = create_proof(&signing_key, "GET", htu, Some(&nonce), Some("Kz~8mXK1EalY"));
+        headers.insert(DPOP_HEADER, proof.parse().unwrap());
+        let response = userinfo_get_endpoint(State(router_state), headers)
+            .await
+            .unwrap();
+        assert_eq!(response.sub, "alice");
+    }
+}
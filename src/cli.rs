@@ -4,6 +4,18 @@ use getopts::Options;
 pub struct Params {
     pub help: Option<String>,
     pub port: u16,
+    pub clients: Option<String>,
+    pub owners: Option<String>,
+    pub issuer: Option<String>,
+    pub scopes: Vec<String>,
+    pub signing_keys: Vec<String>,
+    pub key_set: Option<String>,
+    pub key_rotation_interval: Option<i64>,
+    pub rotate_keys: bool,
+    pub audience: Option<String>,
+    pub refresh_token_idle_ttl: Option<i64>,
+    pub refresh_token_absolute_ttl: Option<i64>,
+    pub initial_access_token: Option<String>,
 }
 
 pub fn parse_args(args: &[String]) -> Result<Params> {
@@ -23,7 +35,48 @@ pub fn parse_args(args: &[String]) -> Result<Params> {
         .parse()
         .with_context(|| format!("Could not parse argument {port_str} as valid port number"))?;
 
-    Ok(Params { help, port })
+    let clients = matches.opt_str("c");
+    let owners = matches.opt_str("o");
+    let issuer = matches.opt_str("issuer");
+    let scopes = matches
+        .opt_str("scopes")
+        .map(|scopes| scopes.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+    let signing_keys = matches.opt_strs("signing-key");
+    let audience = matches.opt_str("audience");
+    let key_set = matches.opt_str("key-set");
+    let key_rotation_interval = parse_seconds(matches.opt_str("key-rotation-interval"))?;
+    let rotate_keys = matches.opt_present("rotate-keys");
+    let refresh_token_idle_ttl = parse_seconds(matches.opt_str("refresh-idle-ttl"))?;
+    let refresh_token_absolute_ttl = parse_seconds(matches.opt_str("refresh-absolute-ttl"))?;
+    let initial_access_token = matches.opt_str("initial-access-token");
+
+    Ok(Params {
+        help,
+        port,
+        clients,
+        owners,
+        issuer,
+        scopes,
+        signing_keys,
+        key_set,
+        key_rotation_interval,
+        rotate_keys,
+        audience,
+        refresh_token_idle_ttl,
+        refresh_token_absolute_ttl,
+        initial_access_token,
+    })
+}
+
+fn parse_seconds(seconds_str: Option<String>) -> Result<Option<i64>> {
+    seconds_str
+        .map(|seconds_str| {
+            seconds_str
+                .parse()
+                .with_context(|| format!("Could not parse argument {seconds_str} as seconds"))
+        })
+        .transpose()
 }
 
 pub fn create_help(program: &str) -> String {
@@ -36,6 +89,67 @@ fn create_options() -> Options {
     let mut opts = Options::new();
     opts.optflag("h", "help", "Show help & exit");
     opts.optopt("p", "port", "Port to listen on", "PORT");
+    opts.optopt("c", "clients", "TOML file with registered clients", "FILE");
+    opts.optopt("o", "owners", "TOML file with resource owners", "FILE");
+    opts.optopt(
+        "",
+        "issuer",
+        "Base URL keyper is reachable at (default: http://localhost:PORT)",
+        "URL",
+    );
+    opts.optopt(
+        "",
+        "scopes",
+        "Space-separated scopes advertised in the server metadata",
+        "SCOPES",
+    );
+    opts.optmulti(
+        "",
+        "signing-key",
+        "PEM file with an RSA private key, the first one signs new tokens",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "key-set",
+        "TOML file the signing keys are persisted to across restarts and rotations",
+        "FILE",
+    );
+    opts.optopt(
+        "",
+        "key-rotation-interval",
+        "Seconds a signing key signs new tokens before it is rotated",
+        "SECONDS",
+    );
+    opts.optflag(
+        "",
+        "rotate-keys",
+        "Rotate the signing keys in the key set file & exit",
+    );
+    opts.optopt(
+        "",
+        "audience",
+        "Audience of JWT access tokens (default: issuer)",
+        "URL",
+    );
+    opts.optopt(
+        "",
+        "refresh-idle-ttl",
+        "Seconds a refresh token stays valid without being used",
+        "SECONDS",
+    );
+    opts.optopt(
+        "",
+        "refresh-absolute-ttl",
+        "Seconds a refresh token stays valid after the initial grant",
+        "SECONDS",
+    );
+    opts.optopt(
+        "",
+        "initial-access-token",
+        "File with the token required to register clients (default: open registration)",
+        "FILE",
+    );
 
     opts
 }
@@ -49,5 +163,81 @@ mod tests {
         let args = vec!["keyper".to_string(), "-p".to_string(), "1337".to_string()];
         let params = parse_args(&args).unwrap();
         assert_eq!(params.port, 1337u16);
+        assert_eq!(params.clients, None);
+
+        let args = vec![
+            "keyper".to_string(),
+            "--clients".to_string(),
+            "clients.toml".to_string(),
+        ];
+        let params = parse_args(&args).unwrap();
+        assert_eq!(params.clients, Some("clients.toml".to_string()));
+
+        let args = vec![
+            "keyper".to_string(),
+            "-o".to_string(),
+            "owners.toml".to_string(),
+            "--issuer".to_string(),
+            "https://keyper.example.com".to_string(),
+            "--scopes".to_string(),
+            "openid profile".to_string(),
+        ];
+        let params = parse_args(&args).unwrap();
+        assert_eq!(params.owners, Some("owners.toml".to_string()));
+        assert_eq!(
+            params.issuer,
+            Some("https://keyper.example.com".to_string())
+        );
+        assert_eq!(params.scopes, vec!["openid", "profile"]);
+        assert!(params.signing_keys.is_empty());
+        assert!(!params.rotate_keys);
+        assert_eq!(params.audience, None);
+
+        let args = vec![
+            "keyper".to_string(),
+            "--signing-key".to_string(),
+            "ed25519.pem".to_string(),
+            "--signing-key".to_string(),
+            "rsa.pem".to_string(),
+            "--audience".to_string(),
+            "https://api.example.com".to_string(),
+        ];
+        let params = parse_args(&args).unwrap();
+        assert_eq!(params.signing_keys, vec!["ed25519.pem", "rsa.pem"]);
+        assert_eq!(params.audience, Some("https://api.example.com".to_string()));
+
+        let args = vec![
+            "keyper".to_string(),
+            "--key-set".to_string(),
+            "keys.toml".to_string(),
+            "--key-rotation-interval".to_string(),
+            "86400".to_string(),
+            "--rotate-keys".to_string(),
+        ];
+        let params = parse_args(&args).unwrap();
+        assert_eq!(params.key_set, Some("keys.toml".to_string()));
+        assert_eq!(params.key_rotation_interval, Some(86400));
+        assert!(params.rotate_keys);
+
+        let args = vec![
+            "keyper".to_string(),
+            "--refresh-idle-ttl".to_string(),
+            "3600".to_string(),
+        ];
+        let params = parse_args(&args).unwrap();
+        assert_eq!(params.refresh_token_idle_ttl, Some(3600));
+        assert_eq!(params.refresh_token_absolute_ttl, None);
+        assert_eq!(params.initial_access_token, None);
+
+        let args = vec![
+            "keyper".to_string(),
+            "--initial-access-token".to_string(),
+            "registration.token".to_string(),
+        ];
+        let params = parse_args(&args).unwrap();
+        assert_eq!(
+            params.initial_access_token,
+            Some("registration.token".to_string())
+        );
     }
 }
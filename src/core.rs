@@ -1,2 +1,14 @@
+pub mod authentication;
 pub mod authorization;
+pub mod device;
+pub mod dpop;
+pub mod introspection;
+pub mod jose;
+pub mod key;
+pub mod logout;
+pub mod oidc;
+pub mod pushed_authorization;
+pub mod registration;
+pub mod request_object;
+pub mod revocation;
 pub mod token;
@@ -0,0 +1,160 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use rand::{distributions, prelude::*};
+
+use crate::core::token::{OwnerRepository, verify_hash};
+
+const SESSION_TTL: i64 = 12 * 3600;
+
+pub trait SessionRepository {
+    fn create_session(&self, session: Session) -> Result<()>;
+    fn read_session(&self, id: &str) -> Option<Session>;
+    fn delete_session(&self, id: &str) -> Option<Session>;
+    /// Remembers that `client_id` was authorized in the session, so it can be notified at logout
+    fn add_session_client(&self, id: &str, client_id: &str) -> Result<()>;
+    /// Counts a user code that matched no pending device authorization and returns the new count
+    fn add_failed_user_code(&self, id: &str) -> Result<u32>;
+}
+
+/// Login session of a resource owner, referenced by the session cookie
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub id: String,
+    /// Session identifier shared with clients (OpenID Connect Back-Channel Logout section 2.1).
+    /// Unlike `id` it does not grant access to the session.
+    pub sid: String,
+    /// Put into forms that act on the session, which proves they were rendered for its owner and
+    /// not submitted by another site
+    pub csrf_token: String,
+    /// User codes entered during the session that matched no pending device authorization
+    pub failed_user_codes: u32,
+    pub owner: String,
+    /// Clients authorized during the session
+    pub clients: Vec<String>,
+    pub authenticated: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+}
+
+/// Verifies the credentials of a resource owner and starts a new login session
+pub fn authenticate<O: OwnerRepository, S: SessionRepository>(
+    username: &str,
+    password: &str,
+    owner_store: &O,
+    session_store: &S,
+) -> Result<Option<Session>> {
+    let Some(owner) = owner_store.read_owner(username) else {
+        return Ok(None);
+    };
+
+    if !verify_hash(password, &owner.hash) {
+        return Ok(None);
+    }
+
+    let now = Utc::now();
+    let session = Session {
+        id: generate_session_id(),
+        sid: generate_session_id(),
+        csrf_token: generate_session_id(),
+        failed_user_codes: 0,
+        owner: owner.name,
+        clients: Vec::new(),
+        authenticated: now,
+        expires: now + Duration::seconds(SESSION_TTL),
+    };
+    session_store.create_session(session.clone())?;
+
+    Ok(Some(session))
+}
+
+/// Looks up a login session that has not expired yet
+pub fn active_session<S: SessionRepository>(id: &str, session_store: &S) -> Option<Session> {
+    session_store
+        .read_session(id)
+        .filter(|session| session.expires > Utc::now())
+}
+
+fn generate_session_id() -> String {
+    let rng = thread_rng();
+    rng.sample_iter(distributions::Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::{
+        Argon2, PasswordHasher,
+        password_hash::{SaltString, rand_core::OsRng},
+    };
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        core::authentication::{Session, SessionRepository, active_session, authenticate},
+        repository::{owner::MapOwnerRepository, session::MapSessionRepository},
+    };
+
+    fn create_owner_store() -> MapOwnerRepository {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::default()
+            .hash_password(b"password", &salt)
+            .unwrap()
+            .to_string();
+
+        MapOwnerRepository::try_from_toml(&format!(
+            r#"
+            [alice]
+            email = "alice@example.com"
+            hash = "{hash}"
+            "#
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let owner_store = create_owner_store();
+        let session_store = MapSessionRepository::default();
+
+        assert!(
+            authenticate("alice", "wrong", &owner_store, &session_store)
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            authenticate("bob", "password", &owner_store, &session_store)
+                .unwrap()
+                .is_none()
+        );
+
+        let session = authenticate("alice", "password", &owner_store, &session_store)
+            .unwrap()
+            .unwrap();
+        assert_eq!(session.owner, "alice");
+        assert_eq!(
+            active_session(&session.id, &session_store).unwrap().owner,
+            "alice"
+        );
+    }
+
+    #[test]
+    fn test_active_session_expired() {
+        let session_store = MapSessionRepository::default();
+        let authenticated = Utc::now() - Duration::seconds(7200);
+        session_store
+            .create_session(Session {
+                id: "expired".to_string(),
+                sid: "08a5019c".to_string(),
+                csrf_token: "Ks7mT2pQ".to_string(),
+                failed_user_codes: 0,
+                owner: "alice".to_string(),
+                clients: Vec::new(),
+                authenticated,
+                expires: authenticated + Duration::seconds(3600),
+            })
+            .unwrap();
+
+        assert!(active_session("expired", &session_store).is_none());
+        assert!(active_session("unknown", &session_store).is_none());
+    }
+}
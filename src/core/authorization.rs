@@ -1,53 +1,200 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, Utc};
 use rand::{distributions, prelude::*};
 use serde::{Deserialize, Serialize};
+use tracing::debug;
+use url::{Host, Url};
 
-#[derive(Deserialize, Clone, Debug)]
+use crate::core::{
+    authentication::Session,
+    key::{JwkSet, KeyRepository},
+    oidc::{self, OPENID_SCOPE, OWNER_CLAIM_SCOPES, PASSWORD_ACR},
+    token::TokenSettings,
+};
+
+const AUTHORIZATION_CODE_TTL: i64 = 600;
+/// Only has to outlive the redirect to the client (JARM section 2.1)
+const JWT_RESPONSE_TTL: i64 = 600;
+
+#[derive(Deserialize, Default, Clone, Debug)]
 pub struct AuthorizationRequest {
     pub response_type: ResponseType,
     pub client_id: String,
     pub redirect_uri: Option<String>,
     pub scope: Option<String>,
     pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<CodeChallengeMethod>,
+    /// Value the client expects to find in the ID token, to mitigate replay attacks
+    pub nonce: Option<String>,
+    /// Space separated list of `Prompt` values
+    pub prompt: Option<String>,
+    /// Seconds since the last active authentication of the owner, after which the owner has to
+    /// authenticate again
+    pub max_age: Option<i64>,
+    /// Name of the owner the client expects to authenticate
+    pub login_hint: Option<String>,
+    /// ID token previously issued to the client for the owner it expects to authenticate
+    pub id_token_hint: Option<String>,
+    /// Space separated authentication context classes in order of preference. They are voluntary
+    /// claims and keyper only knows password logins, so they do not change the outcome.
+    pub acr_values: Option<String>,
+    pub response_mode: Option<ResponseMode>,
+    /// Whether the client pushed the request to the PAR endpoint instead of sending it through
+    /// the user agent
+    #[serde(skip)]
+    pub pushed: bool,
+    /// Time keyper sent the owner to log in for this request. Logins since then satisfy `prompt`
+    /// and `max_age`, while the hints still have to match the owner who logged in.
+    #[serde(skip)]
+    pub login_requested: Option<DateTime<Utc>>,
 }
 
-#[derive(Deserialize, PartialEq, Clone, Debug)]
+impl AuthorizationRequest {
+    /// Parses the `prompt` parameter (OpenID Connect Core section 3.1.2.1)
+    pub fn prompts(&self) -> Result<Vec<Prompt>, &'static str> {
+        let prompts = self
+            .prompt
+            .iter()
+            .flat_map(|prompt| prompt.split_whitespace())
+            .map(|prompt| match prompt {
+                "none" => Ok(Prompt::None),
+                "login" => Ok(Prompt::Login),
+                "consent" => Ok(Prompt::Consent),
+                "select_account" => Ok(Prompt::SelectAccount),
+                _ => Err("prompt contains an unsupported value"),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if prompts.contains(&Prompt::None) && prompts.len() > 1 {
+            return Err("prompt=none must not be combined with other values");
+        }
+
+        Ok(prompts)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Prompt {
+    /// The request must be answered without any user interaction
+    None,
+    Login,
+    Consent,
+    SelectAccount,
+}
+
+#[derive(Deserialize, Serialize, Default, PartialEq, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum ResponseType {
+    #[default]
     Code,
     Token,
 }
 
+/// How the authorization response is delivered to the client (OAuth 2.0 Multiple Response Type
+/// Encoding Practices section 2.1, OAuth 2.0 Form Post Response Mode section 2 and JARM section
+/// 2.3)
+#[derive(Deserialize, Serialize, Default, PartialEq, Clone, Copy, Debug)]
+pub enum ResponseMode {
+    #[default]
+    #[serde(rename = "query")]
+    Query,
+    #[serde(rename = "fragment")]
+    Fragment,
+    #[serde(rename = "form_post")]
+    FormPost,
+    /// Default mode of the response type with the parameters wrapped into a JWT, which is
+    /// `query.jwt` for the authorization code flow
+    #[serde(rename = "jwt")]
+    Jwt,
+    #[serde(rename = "query.jwt")]
+    QueryJwt,
+    #[serde(rename = "fragment.jwt")]
+    FragmentJwt,
+    #[serde(rename = "form_post.jwt")]
+    FormPostJwt,
+}
+
+impl ResponseMode {
+    /// Splits the mode into the plain mode that carries the parameters and whether they are
+    /// wrapped into a JWT
+    pub fn delivery(self) -> (Self, bool) {
+        match self {
+            Self::Query | Self::Fragment | Self::FormPost => (self, false),
+            Self::Jwt | Self::QueryJwt => (Self::Query, true),
+            Self::FragmentJwt => (Self::Fragment, true),
+            Self::FormPostJwt => (Self::FormPost, true),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, PartialEq, Clone, Copy, Debug)]
+pub enum CodeChallengeMethod {
+    #[default]
+    #[serde(rename = "plain")]
+    Plain,
+    S256,
+}
+
 #[derive(Serialize, Debug)]
 pub struct AuthorizationResponse {
     pub code: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub state: Option<String>,
 }
 
 #[derive(Serialize, Debug)]
 pub struct AuthorizationErrorResponse {
     pub error: AuthorizationError,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error_description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error_uri: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub state: Option<String>,
 }
 
+impl AuthorizationErrorResponse {
+    pub fn new(error: AuthorizationError, state: Option<String>) -> Self {
+        Self {
+            error,
+            error_description: None,
+            error_uri: None,
+            state,
+        }
+    }
+}
+
 #[derive(Serialize, PartialEq, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum AuthorizationError {
     InvalidRequest,
     UnauthorizedClient,
-    AccessDenied,
     UnsupportedResponseType,
     InvalidScope,
     ServerError,
-    TemporarilyUnavailable,
+    /// OpenID Connect Core section 3.1.2.6
+    LoginRequired,
+    ConsentRequired,
+    InteractionRequired,
+    /// OpenID Connect Core section 6.4
+    InvalidRequestUri,
+    InvalidRequestObject,
 }
 
 #[derive(Debug)]
 pub struct AuthorizationSuccessResponse(pub AuthorizationResponse, pub String);
 
+/// Error response together with the redirect URI it should be delivered to, if the client and
+/// redirect URI could be verified. Otherwise the error must not be redirected.
+#[derive(Debug)]
+pub struct AuthorizationFailureResponse(pub AuthorizationErrorResponse, pub Option<String>);
+
 pub trait ClientRepository {
+    fn create_client(&self, client: Client) -> Result<()>;
     fn read_client(&self, id: &str) -> Option<Client>;
+    fn update_client(&self, client: Client) -> Result<()>;
+    fn delete_client(&self, id: &str) -> Option<Client>;
 }
 
 #[derive(Deserialize, Debug)]
@@ -56,6 +203,32 @@ pub struct Client {
     pub client_type: ClientType,
     pub redirect_uris: Vec<String>,
     pub name: String,
+    pub require_pkce: bool,
+    pub scopes: Vec<String>,
+    pub secret_hash: Option<String>,
+    pub token_endpoint_auth_method: TokenEndpointAuthMethod,
+    pub access_token_format: AccessTokenFormat,
+    /// Where the client may ask to be sent back to after logout
+    pub post_logout_redirect_uris: Vec<String>,
+    /// Receives logout tokens when a session the client was authorized in ends
+    pub backchannel_logout_uri: Option<String>,
+    /// Only accept authorization requests pushed to the PAR endpoint (RFC 9126 section 6)
+    pub require_pushed_authorization_requests: bool,
+    /// Keys request objects of the client are signed with, unless they are published at
+    /// `jwks_uri`
+    pub jwks: Option<JwkSet>,
+    pub jwks_uri: Option<String>,
+    /// URLs keyper may fetch request objects of the client from
+    pub request_uris: Vec<String>,
+    /// Accept request objects without signature, which carry no proof of their origin
+    pub allow_unsigned_request_objects: bool,
+    /// Only issue access tokens bound to a DPoP key (RFC 9449 section 5.2)
+    pub dpop_bound_access_tokens: bool,
+    /// Argon2 PHC string of the token authorizing the client to manage its own registration
+    pub registration_access_token_hash: Option<String>,
+    /// Registered without initial access token by anyone, so the client holds no scopes and may
+    /// not use the client credentials grant
+    pub self_registered: bool,
 }
 
 #[derive(Deserialize, PartialEq, Clone, Debug)]
@@ -65,56 +238,373 @@ pub enum ClientType {
     Public,
 }
 
-pub async fn authorization_code<C: ClientRepository>(
+#[derive(Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenEndpointAuthMethod {
+    None,
+    ClientSecretBasic,
+    ClientSecretPost,
+}
+
+/// Access tokens are either random strings only keyper can resolve or self-contained JWTs
+/// (RFC 9068) that resource servers validate with the published keys.
+#[derive(Deserialize, Serialize, Default, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessTokenFormat {
+    #[default]
+    Opaque,
+    Jwt,
+}
+
+pub trait AuthorizationCodeRepository {
+    fn create_authorization_code(&self, authorization_code: AuthorizationCode) -> Result<()>;
+    fn consume_authorization_code(&self, code: &str) -> Option<AuthorizationCode>;
+}
+
+#[derive(Clone, Debug)]
+pub struct AuthorizationCode {
+    pub code: String,
+    pub client_id: String,
+    pub redirect_uri: Option<String>,
+    pub scopes: Vec<String>,
+    pub owner: String,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: CodeChallengeMethod,
+    pub nonce: Option<String>,
+    /// Time the resource owner authenticated to grant this code
+    pub auth_time: DateTime<Utc>,
+    /// Client facing identifier of the login session the code was granted in
+    pub sid: Option<String>,
+    pub expires: DateTime<Utc>,
+}
+
+/// Issues an authorization code for the owner of the login session. Without a session, or if the
+/// session does not satisfy the OpenID Connect authentication parameters of the request, this
+/// fails with `login_required`, which callers able to interact with the owner should answer with
+/// a login instead.
+pub async fn authorization_code<
+    C: ClientRepository,
+    A: AuthorizationCodeRepository,
+    K: KeyRepository,
+>(
     auth_request: AuthorizationRequest,
+    session: Option<&Session>,
+    token_settings: &TokenSettings,
     client_store: &C,
-) -> Result<AuthorizationSuccessResponse, AuthorizationErrorResponse> {
-    if auth_request.response_type != ResponseType::Code {
-        return Err(AuthorizationErrorResponse {
-            error: AuthorizationError::UnsupportedResponseType,
-            error_description: None,
-            error_uri: None,
+    authorization_code_store: &A,
+    key_store: &K,
+) -> Result<AuthorizationSuccessResponse, AuthorizationFailureResponse> {
+    let (client, redirect_uri) = validate_authorization_request(&auth_request, client_store)?;
+    let scopes: Vec<String> = auth_request
+        .scope
+        .as_ref()
+        .map(|scope| scope.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default();
+
+    if let Some(scope) = scopes.iter().find(|scope| {
+        !client.scopes.contains(scope) && !OWNER_CLAIM_SCOPES.contains(&scope.as_str())
+    }) {
+        let mut auth_error_response =
+            AuthorizationErrorResponse::new(AuthorizationError::InvalidScope, auth_request.state);
+        auth_error_response.error_description =
+            Some(format!("Scope {scope} is not registered for this client"));
+        return Err(AuthorizationFailureResponse(
+            auth_error_response,
+            Some(redirect_uri),
+        ));
+    }
+
+    let session = match check_authentication(&auth_request, session, token_settings, key_store) {
+        Ok(session) => session,
+        Err((error, error_description)) => {
+            let mut auth_error_response =
+                AuthorizationErrorResponse::new(error, auth_request.state);
+            auth_error_response.error_description = error_description.map(str::to_string);
+            return Err(AuthorizationFailureResponse(
+                auth_error_response,
+                Some(redirect_uri),
+            ));
+        }
+    };
+
+    let authorization_code = AuthorizationCode {
+        code: generate_authorization_code(),
+        client_id: client.id,
+        redirect_uri: auth_request.redirect_uri,
+        scopes,
+        owner: session.owner.clone(),
+        code_challenge: auth_request.code_challenge,
+        code_challenge_method: auth_request.code_challenge_method.unwrap_or_default(),
+        nonce: auth_request.nonce,
+        auth_time: session.authenticated,
+        sid: Some(session.sid.clone()),
+        expires: Utc::now() + Duration::seconds(AUTHORIZATION_CODE_TTL),
+    };
+    let code = authorization_code.code.clone();
+
+    if authorization_code_store
+        .create_authorization_code(authorization_code)
+        .is_err()
+    {
+        return Err(AuthorizationFailureResponse(
+            AuthorizationErrorResponse::new(AuthorizationError::ServerError, auth_request.state),
+            Some(redirect_uri),
+        ));
+    }
+
+    Ok(AuthorizationSuccessResponse(
+        AuthorizationResponse {
+            code,
             state: auth_request.state,
-        });
+        },
+        redirect_uri,
+    ))
+}
+
+#[derive(Serialize, Debug)]
+struct JwtResponseClaims<'a, T: Serialize> {
+    iss: &'a str,
+    aud: &'a str,
+    exp: i64,
+    #[serde(flatten)]
+    parameters: &'a T,
+}
+
+/// Wraps the parameters of an authorization or error response into a JWT signed by keyper, which
+/// is passed to the client in the single `response` parameter (JARM section 2.1)
+pub fn jwt_authorization_response<T: Serialize, K: KeyRepository>(
+    parameters: &T,
+    client_id: &str,
+    token_settings: &TokenSettings,
+    key_store: &K,
+) -> Result<String> {
+    let signing_key = key_store
+        .read_signing_key()
+        .ok_or_else(|| anyhow!("No signing key available for authorization responses"))?;
+
+    signing_key.sign(
+        "JWT",
+        &JwtResponseClaims {
+            iss: &token_settings.issuer,
+            aud: client_id,
+            exp: (Utc::now() + Duration::seconds(JWT_RESPONSE_TTL)).timestamp(),
+            parameters,
+        },
+    )
+}
+
+/// Responses are only delivered to https URIs, or http URIs on the loopback interface for native
+/// apps (RFC 8252 section 7.3). Schemes like `javascript:` or `data:` would run in the origin of
+/// keyper when redirected to or submitted as form post.
+pub fn is_web_redirect_uri(uri: &str) -> bool {
+    let Ok(url) = Url::parse(uri) else {
+        return false;
+    };
+
+    match (url.scheme(), url.host()) {
+        ("https", Some(_)) => true,
+        ("http", Some(Host::Domain(domain))) => domain == "localhost",
+        ("http", Some(Host::Ipv4(address))) => address.is_loopback(),
+        ("http", Some(Host::Ipv6(address))) => address.is_loopback(),
+        _ => false,
     }
+}
 
+/// Validates the request against the registration of the client and returns the client along
+/// with the redirect URI responses go to. Errors come without redirect URI as long as it is not
+/// known to belong to the client.
+pub fn validate_authorization_request<C: ClientRepository>(
+    auth_request: &AuthorizationRequest,
+    client_store: &C,
+) -> Result<(Client, String), AuthorizationFailureResponse> {
+    let state = &auth_request.state;
     let Some(client) = client_store.read_client(&auth_request.client_id) else {
-        return Err(AuthorizationErrorResponse {
-            error: AuthorizationError::UnauthorizedClient,
-            error_description: None,
-            error_uri: None,
-            state: auth_request.state,
-        });
+        return Err(AuthorizationFailureResponse(
+            AuthorizationErrorResponse::new(AuthorizationError::UnauthorizedClient, state.clone()),
+            None,
+        ));
     };
 
-    let redirect_uri = match (auth_request.redirect_uri, &client.redirect_uris.as_slice()) {
-        (None, &[]) => Err(AuthorizationError::InvalidRequest),
-        (None, &[redirect_uri, ..]) => Ok(redirect_uri.to_string()),
-        (Some(redirect_uri), &[]) => Ok(redirect_uri),
-        (Some(redirect_uri), redirect_uris) => {
-            if redirect_uris.contains(&redirect_uri) {
-                Ok(redirect_uri)
-            } else {
-                Err(AuthorizationError::InvalidRequest)
-            }
+    let redirect_uri = match (&auth_request.redirect_uri, &client.redirect_uris.as_slice()) {
+        (None, &[]) => None,
+        (None, &[redirect_uri, ..]) => Some(redirect_uri.to_string()),
+        (Some(redirect_uri), redirect_uris) => redirect_uris
+            .contains(redirect_uri)
+            .then(|| redirect_uri.to_string()),
+    };
+
+    let Some(redirect_uri) = redirect_uri else {
+        return Err(AuthorizationFailureResponse(
+            AuthorizationErrorResponse::new(AuthorizationError::InvalidRequest, state.clone()),
+            None,
+        ));
+    };
+
+    if !is_web_redirect_uri(&redirect_uri) {
+        let mut auth_error_response =
+            AuthorizationErrorResponse::new(AuthorizationError::InvalidRequest, state.clone());
+        auth_error_response.error_description = Some(format!(
+            "Redirect URI {redirect_uri} has to use https, or http on the loopback interface"
+        ));
+        return Err(AuthorizationFailureResponse(auth_error_response, None));
+    }
+
+    let invalid_request = |error_description: &str| {
+        let mut auth_error_response =
+            AuthorizationErrorResponse::new(AuthorizationError::InvalidRequest, state.clone());
+        auth_error_response.error_description = Some(error_description.to_string());
+        AuthorizationFailureResponse(auth_error_response, Some(redirect_uri.clone()))
+    };
+
+    if client.require_pushed_authorization_requests && !auth_request.pushed {
+        return Err(invalid_request(
+            "This client has to push authorization requests to the PAR endpoint",
+        ));
+    }
+
+    if auth_request.response_type != ResponseType::Code {
+        return Err(AuthorizationFailureResponse(
+            AuthorizationErrorResponse::new(
+                AuthorizationError::UnsupportedResponseType,
+                state.clone(),
+            ),
+            Some(redirect_uri),
+        ));
+    }
+
+    if let Err(error_description) = validate_code_challenge(auth_request, &client) {
+        return Err(invalid_request(error_description));
+    }
+
+    let openid = auth_request
+        .scope
+        .iter()
+        .flat_map(|scope| scope.split_whitespace())
+        .any(|scope| scope == OPENID_SCOPE);
+    if openid && auth_request.redirect_uri.is_none() {
+        return Err(invalid_request(
+            "redirect_uri is required for OpenID Connect requests",
+        ));
+    }
+
+    if let Err(error_description) = auth_request.prompts() {
+        return Err(invalid_request(error_description));
+    }
+
+    Ok((client, redirect_uri))
+}
+
+/// Checks that the login session satisfies the `prompt`, `max_age`, `login_hint` and
+/// `id_token_hint` parameters and returns it
+fn check_authentication<'a, K: KeyRepository>(
+    auth_request: &AuthorizationRequest,
+    session: Option<&'a Session>,
+    token_settings: &TokenSettings,
+    key_store: &K,
+) -> Result<&'a Session, (AuthorizationError, Option<&'static str>)> {
+    let prompts = auth_request.prompts().map_err(|error_description| {
+        (AuthorizationError::InvalidRequest, Some(error_description))
+    })?;
+
+    let hint_subject = match &auth_request.id_token_hint {
+        Some(id_token_hint) => {
+            let claims = oidc::verify_id_token_hint(id_token_hint, token_settings, key_store)
+                .ok_or((
+                    AuthorizationError::InvalidRequest,
+                    Some("id_token_hint is not a valid ID token"),
+                ))?;
+            claims["sub"].as_str().map(str::to_string)
         }
+        None => None,
     };
 
-    match redirect_uri {
-        Err(error) => Err(AuthorizationErrorResponse {
-            error,
-            error_description: None,
-            error_uri: None,
-            state: auth_request.state,
-        }),
-        Ok(redirect_uri) => Ok(AuthorizationSuccessResponse(
-            AuthorizationResponse {
-                code: generate_authorization_code(),
-                state: auth_request.state,
-            },
-            redirect_uri,
-        )),
+    let Some(session) = session else {
+        return Err((AuthorizationError::LoginRequired, None));
+    };
+
+    let logged_in_since_request = auth_request
+        .login_requested
+        .is_some_and(|login_requested| session.authenticated >= login_requested);
+
+    if !logged_in_since_request
+        && (prompts.contains(&Prompt::Login) || prompts.contains(&Prompt::SelectAccount))
+    {
+        return Err((AuthorizationError::LoginRequired, None));
+    }
+
+    let authentication_expired = !logged_in_since_request
+        && auth_request.max_age.is_some_and(|max_age| {
+            session.authenticated + Duration::seconds(max_age.max(0)) < Utc::now()
+        });
+    if authentication_expired {
+        return Err((
+            AuthorizationError::LoginRequired,
+            Some("The last authentication is older than max_age"),
+        ));
+    }
+
+    if hint_subject.is_some_and(|subject| subject != session.owner) {
+        return Err((
+            AuthorizationError::LoginRequired,
+            Some("The owner is not the subject of id_token_hint"),
+        ));
     }
+
+    // Switching to the hinted account requires the owner to pick it, which is an interaction
+    if auth_request
+        .login_hint
+        .as_ref()
+        .is_some_and(|login_hint| *login_hint != session.owner)
+    {
+        let error = if prompts.contains(&Prompt::None) {
+            AuthorizationError::InteractionRequired
+        } else {
+            AuthorizationError::LoginRequired
+        };
+        return Err((error, Some("The owner does not match login_hint")));
+    }
+
+    // keyper grants access without asking for consent, so it cannot ask for it again either
+    if prompts.contains(&Prompt::Consent) {
+        return Err((AuthorizationError::ConsentRequired, None));
+    }
+
+    let acr_satisfied = auth_request.acr_values.as_ref().is_none_or(|acr_values| {
+        acr_values
+            .split_whitespace()
+            .any(|acr_value| acr_value == PASSWORD_ACR)
+    });
+    if !acr_satisfied {
+        debug!("Requested acr_values cannot be satisfied, answering with {PASSWORD_ACR}");
+    }
+
+    Ok(session)
+}
+
+fn validate_code_challenge(
+    auth_request: &AuthorizationRequest,
+    client: &Client,
+) -> Result<(), &'static str> {
+    match (
+        &auth_request.code_challenge,
+        auth_request.code_challenge_method,
+    ) {
+        (None, None) if client.require_pkce => Err("code_challenge is required for this client"),
+        (None, None) => Ok(()),
+        (None, Some(_)) => Err("code_challenge_method requires a code_challenge"),
+        (Some(code_challenge), _) if !is_pkce_value(code_challenge) => {
+            Err("code_challenge must be 43 to 128 unreserved characters")
+        }
+        (Some(_), _) => Ok(()),
+    }
+}
+
+/// Checks the ABNF shared by `code_verifier` and `code_challenge` (RFC 7636 section 4.1).
+pub fn is_pkce_value(value: &str) -> bool {
+    (43..=128).contains(&value.len())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'))
 }
 
 fn generate_authorization_code() -> String {
@@ -127,16 +617,62 @@ fn generate_authorization_code() -> String {
 
 #[cfg(test)]
 mod tests {
-    use crate::core::authorization::{
-        authorization_code, AuthorizationError, AuthorizationRequest, AuthorizationSuccessResponse,
-        Client, ClientRepository, ClientType, ResponseType,
+    use chrono::{DateTime, Duration, Utc};
+    use serde_json::json;
+
+    use crate::{
+        core::{
+            authentication::Session,
+            authorization::{
+                AccessTokenFormat, AuthorizationCodeRepository, AuthorizationError,
+                AuthorizationErrorResponse, AuthorizationFailureResponse, AuthorizationRequest,
+                AuthorizationResponse, AuthorizationSuccessResponse, Client, ClientRepository,
+                ClientType, CodeChallengeMethod, ResponseMode, ResponseType,
+                TokenEndpointAuthMethod, authorization_code, is_web_redirect_uri,
+                jwt_authorization_response,
+            },
+            jose::{SigningKey, verify},
+            token::TokenSettings,
+        },
+        repository::{authorization_code::MapAuthorizationCodeRepository, key::MapKeyRepository},
     };
 
     use super::generate_authorization_code;
 
+    fn create_session(authenticated: DateTime<Utc>) -> Session {
+        Session {
+            id: "foobar".to_string(),
+            sid: "08a5019c".to_string(),
+            csrf_token: "Ks7mT2pQ".to_string(),
+            failed_user_codes: 0,
+            owner: "alice".to_string(),
+            clients: Vec::new(),
+            authenticated,
+            expires: authenticated + Duration::seconds(3600),
+        }
+    }
+
+    /// Authorizes the request in a login session of alice that just started
+    async fn authorize(
+        request: AuthorizationRequest,
+        client_store: &TestClientRepository,
+        code_store: &MapAuthorizationCodeRepository,
+    ) -> Result<AuthorizationSuccessResponse, AuthorizationFailureResponse> {
+        authorization_code(
+            request,
+            Some(&create_session(Utc::now())),
+            &TokenSettings::default(),
+            client_store,
+            code_store,
+            &MapKeyRepository::default(),
+        )
+        .await
+    }
+
     struct TestClientRepository {
         client_ids: Vec<String>,
         redirect_uris: Vec<Vec<String>>,
+        require_pkce: bool,
     }
 
     impl ClientRepository for TestClientRepository {
@@ -149,8 +685,35 @@ mod tests {
                     client_type: ClientType::Public,
                     redirect_uris: self.redirect_uris[index].clone(),
                     name: "Example Client".to_string(),
+                    require_pkce: self.require_pkce,
+                    scopes: vec!["read".to_string()],
+                    secret_hash: None,
+                    token_endpoint_auth_method: TokenEndpointAuthMethod::None,
+                    access_token_format: AccessTokenFormat::Opaque,
+                    post_logout_redirect_uris: Vec::new(),
+                    backchannel_logout_uri: None,
+                    require_pushed_authorization_requests: false,
+                    jwks: None,
+                    jwks_uri: None,
+                    request_uris: Vec::new(),
+                    allow_unsigned_request_objects: false,
+                    dpop_bound_access_tokens: false,
+                    registration_access_token_hash: None,
+                    self_registered: false,
                 })
         }
+
+        fn create_client(&self, _client: Client) -> anyhow::Result<()> {
+            anyhow::bail!("Test client store is read-only")
+        }
+
+        fn update_client(&self, _client: Client) -> anyhow::Result<()> {
+            anyhow::bail!("Test client store is read-only")
+        }
+
+        fn delete_client(&self, _id: &str) -> Option<Client> {
+            None
+        }
     }
 
     #[tokio::test]
@@ -159,22 +722,137 @@ mod tests {
             response_type: ResponseType::Code,
             client_id: "s6BhdRkqt3".to_string(),
             state: Some("xyz".to_string()),
-            redirect_uri: Some("https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()),
+            redirect_uri: Some("https://client.example.com/cb".to_string()),
             scope: None,
+            ..Default::default()
         };
 
         let client_store = TestClientRepository {
             client_ids: vec!["s6BhdRkqt3".to_string()],
-            redirect_uris: vec![vec!["https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()]],
+            redirect_uris: vec![vec!["https://client.example.com/cb".to_string()]],
+            require_pkce: false,
         };
 
-        let response = authorization_code(request.clone(), &client_store).await;
+        let code_store = MapAuthorizationCodeRepository::default();
+
+        let response = authorize(request.clone(), &client_store, &code_store).await;
 
         assert!(response.is_ok());
-        let AuthorizationSuccessResponse(response, redirect_uri) = response.unwrap();
+        let AuthorizationSuccessResponse(response, _) = response.unwrap();
 
         assert_eq!(response.code.len(), 24);
         assert_eq!(response.state, request.state);
+
+        let stored_code = code_store
+            .consume_authorization_code(&response.code)
+            .unwrap();
+        assert_eq!(stored_code.client_id, request.client_id);
+        assert_eq!(stored_code.redirect_uri, request.redirect_uri);
+        assert_eq!(stored_code.owner, "alice");
+        assert!(stored_code.expires > Utc::now());
+    }
+
+    #[tokio::test]
+    async fn test_authorization_code_openid() {
+        let client_store = TestClientRepository {
+            client_ids: vec!["s6BhdRkqt3".to_string()],
+            redirect_uris: vec![vec!["https://client.example.com/cb".to_string()]],
+            require_pkce: false,
+        };
+        let code_store = MapAuthorizationCodeRepository::default();
+        let request = AuthorizationRequest {
+            response_type: ResponseType::Code,
+            client_id: "s6BhdRkqt3".to_string(),
+            redirect_uri: Some("https://client.example.com/cb".to_string()),
+            scope: Some("openid email".to_string()),
+            nonce: Some("n-0S6_WzA2Mj".to_string()),
+            ..Default::default()
+        };
+
+        let AuthorizationSuccessResponse(response, _) =
+            authorize(request.clone(), &client_store, &code_store)
+                .await
+                .unwrap();
+        let stored_code = code_store
+            .consume_authorization_code(&response.code)
+            .unwrap();
+        assert_eq!(stored_code.scopes, vec!["openid", "email"]);
+        assert_eq!(stored_code.nonce, Some("n-0S6_WzA2Mj".to_string()));
+        assert!(stored_code.auth_time <= Utc::now());
+        assert_eq!(stored_code.sid, Some("08a5019c".to_string()));
+
+        let request = AuthorizationRequest {
+            redirect_uri: None,
+            ..request
+        };
+        let AuthorizationFailureResponse(error_response, redirect_uri) =
+            authorize(request, &client_store, &code_store)
+                .await
+                .unwrap_err();
+        assert_eq!(error_response.error, AuthorizationError::InvalidRequest);
+        assert_eq!(
+            redirect_uri,
+            Some("https://client.example.com/cb".to_string())
+        );
+    }
+
+    #[test]
+    fn test_is_web_redirect_uri() {
+        for (uri, expected) in [
+            ("https://client.example.com/cb", true),
+            ("http://localhost:8080/cb", true),
+            ("http://127.0.0.1:8080/cb", true),
+            ("http://[::1]/cb", true),
+            ("http://client.example.com/cb", false),
+            ("javascript:alert(document.cookie)", false),
+            ("data:text/html,<script>alert(1)</script>", false),
+            ("com.example.app:/cb", false),
+            ("/cb", false),
+        ] {
+            assert_eq!(is_web_redirect_uri(uri), expected, "{uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authorization_code_invalid_scope() {
+        let client_store = TestClientRepository {
+            client_ids: vec!["s6BhdRkqt3".to_string()],
+            redirect_uris: vec![vec!["https://client.example.com/cb".to_string()]],
+            require_pkce: false,
+        };
+        let code_store = MapAuthorizationCodeRepository::default();
+        let request = AuthorizationRequest {
+            response_type: ResponseType::Code,
+            client_id: "s6BhdRkqt3".to_string(),
+            redirect_uri: Some("https://client.example.com/cb".to_string()),
+            state: Some("xyz".to_string()),
+            scope: Some("openid profile read".to_string()),
+            ..Default::default()
+        };
+
+        let AuthorizationSuccessResponse(response, _) =
+            authorize(request.clone(), &client_store, &code_store)
+                .await
+                .unwrap();
+        let stored_code = code_store
+            .consume_authorization_code(&response.code)
+            .unwrap();
+        assert_eq!(stored_code.scopes, vec!["openid", "profile", "read"]);
+
+        let request = AuthorizationRequest {
+            scope: Some("read admin".to_string()),
+            ..request
+        };
+        let AuthorizationFailureResponse(error_response, redirect_uri) =
+            authorize(request, &client_store, &code_store)
+                .await
+                .unwrap_err();
+        assert_eq!(error_response.error, AuthorizationError::InvalidScope);
+        assert_eq!(error_response.state, Some("xyz".to_string()));
+        assert_eq!(
+            redirect_uri,
+            Some("https://client.example.com/cb".to_string())
+        );
     }
 
     #[tokio::test]
@@ -183,22 +861,27 @@ mod tests {
             response_type: ResponseType::Token,
             client_id: "s6BhdRkqt3".to_string(),
             state: Some("xyz".to_string()),
-            redirect_uri: Some("https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()),
+            redirect_uri: Some("https://client.example.com/cb".to_string()),
             scope: None,
+            ..Default::default()
         };
 
         let client_store = TestClientRepository {
             client_ids: vec!["s6BhdRkqt3".to_string()],
-            redirect_uris: vec![vec!["https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()]],
+            redirect_uris: vec![vec!["https://client.example.com/cb".to_string()]],
+            require_pkce: false,
         };
 
-        let response = authorization_code(request.clone(), &client_store).await;
+        let code_store = MapAuthorizationCodeRepository::default();
+
+        let response = authorize(request.clone(), &client_store, &code_store).await;
 
         assert!(response.is_err());
-        let response = response.unwrap_err();
+        let AuthorizationFailureResponse(response, redirect_uri) = response.unwrap_err();
 
         assert_eq!(response.error, AuthorizationError::UnsupportedResponseType);
         assert_eq!(response.state, request.state);
+        assert_eq!(redirect_uri, request.redirect_uri);
     }
 
     #[tokio::test]
@@ -206,23 +889,28 @@ mod tests {
         let request = AuthorizationRequest {
             response_type: ResponseType::Code,
             client_id: "wrong".to_string(),
-            redirect_uri: Some("https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()),
+            redirect_uri: Some("https://client.example.com/cb".to_string()),
             scope: None,
             state: Some("xyz".to_string()),
+            ..Default::default()
         };
 
         let client_store = TestClientRepository {
             client_ids: vec!["s6BhdRkqt3".to_string()],
-            redirect_uris: vec![vec!["https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()]],
+            redirect_uris: vec![vec!["https://client.example.com/cb".to_string()]],
+            require_pkce: false,
         };
 
-        let response = authorization_code(request.clone(), &client_store).await;
+        let code_store = MapAuthorizationCodeRepository::default();
+
+        let response = authorize(request.clone(), &client_store, &code_store).await;
 
         assert!(response.is_err());
-        let response = response.unwrap_err();
+        let AuthorizationFailureResponse(response, redirect_uri) = response.unwrap_err();
 
         assert_eq!(response.error, AuthorizationError::UnauthorizedClient);
         assert_eq!(response.state, request.state);
+        assert_eq!(redirect_uri, None);
     }
 
     #[tokio::test]
@@ -233,20 +921,35 @@ mod tests {
             redirect_uri: None,
             scope: None,
             state: Some("xyz".to_string()),
+            ..Default::default()
         };
 
         let client_store = TestClientRepository {
             client_ids: vec!["s6BhdRkqt3".to_string()],
             redirect_uris: vec![Vec::new()],
+            require_pkce: false,
         };
 
-        let response = authorization_code(request.clone(), &client_store).await;
+        let code_store = MapAuthorizationCodeRepository::default();
+
+        let response = authorize(request.clone(), &client_store, &code_store).await;
 
         assert!(response.is_err());
-        let response = response.unwrap_err();
+        let AuthorizationFailureResponse(response, redirect_uri) = response.unwrap_err();
 
         assert_eq!(response.error, AuthorizationError::InvalidRequest);
         assert_eq!(response.state, request.state);
+        assert_eq!(redirect_uri, None);
+
+        // Without registered redirect URIs, none of the client's choice is trusted either
+        let request = AuthorizationRequest {
+            redirect_uri: Some("https://attacker.example.com/cb".to_string()),
+            ..request
+        };
+        let response = authorize(request, &client_store, &code_store).await;
+        let AuthorizationFailureResponse(response, redirect_uri) = response.unwrap_err();
+        assert_eq!(response.error, AuthorizationError::InvalidRequest);
+        assert_eq!(redirect_uri, None);
     }
 
     #[tokio::test]
@@ -257,22 +960,23 @@ mod tests {
             redirect_uri: None,
             scope: None,
             state: Some("xyz".to_string()),
+            ..Default::default()
         };
 
         let client_store = TestClientRepository {
             client_ids: vec!["s6BhdRkqt3".to_string()],
-            redirect_uris: vec![vec!["https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()]],
+            redirect_uris: vec![vec!["https://client.example.com/cb".to_string()]],
+            require_pkce: false,
         };
 
-        let response = authorization_code(request.clone(), &client_store).await;
+        let code_store = MapAuthorizationCodeRepository::default();
+
+        let response = authorize(request.clone(), &client_store, &code_store).await;
 
         assert!(response.is_ok());
         let AuthorizationSuccessResponse(response, redirect_uri) = response.unwrap();
 
-        assert_eq!(
-            redirect_uri,
-            "https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()
-        );
+        assert_eq!(redirect_uri, "https://client.example.com/cb".to_string());
         assert_eq!(response.state, request.state);
     }
 
@@ -281,25 +985,26 @@ mod tests {
         let request = AuthorizationRequest {
             response_type: ResponseType::Code,
             client_id: "s6BhdRkqt3".to_string(),
-            redirect_uri: Some("https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()),
+            redirect_uri: Some("https://client.example.com/cb".to_string()),
             scope: None,
             state: Some("xyz".to_string()),
+            ..Default::default()
         };
 
         let client_store = TestClientRepository {
             client_ids: vec!["s6BhdRkqt3".to_string()],
-            redirect_uris: vec![vec!["https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()]],
+            redirect_uris: vec![vec!["https://client.example.com/cb".to_string()]],
+            require_pkce: false,
         };
 
-        let response = authorization_code(request.clone(), &client_store).await;
+        let code_store = MapAuthorizationCodeRepository::default();
+
+        let response = authorize(request.clone(), &client_store, &code_store).await;
 
         assert!(response.is_ok());
         let AuthorizationSuccessResponse(response, redirect_uri) = response.unwrap();
 
-        assert_eq!(
-            redirect_uri,
-            "https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()
-        );
+        assert_eq!(redirect_uri, "https://client.example.com/cb".to_string());
         assert_eq!(response.state, request.state);
     }
 
@@ -311,20 +1016,87 @@ mod tests {
             redirect_uri: Some("https%3A%2F%2Fclient%2Eexample%2Ecom".to_string()),
             scope: None,
             state: Some("xyz".to_string()),
+            ..Default::default()
         };
 
         let client_store = TestClientRepository {
             client_ids: vec!["s6BhdRkqt3".to_string()],
-            redirect_uris: vec![vec!["https%3A%2F%2Fclient%2Eexample%2Ecom%2Fcb".to_string()]],
+            redirect_uris: vec![vec!["https://client.example.com/cb".to_string()]],
+            require_pkce: false,
         };
 
-        let response = authorization_code(request.clone(), &client_store).await;
+        let code_store = MapAuthorizationCodeRepository::default();
+
+        let response = authorize(request.clone(), &client_store, &code_store).await;
 
         assert!(response.is_err());
-        let response = response.unwrap_err();
+        let AuthorizationFailureResponse(response, redirect_uri) = response.unwrap_err();
 
         assert_eq!(response.error, AuthorizationError::InvalidRequest);
         assert_eq!(response.state, request.state);
+        assert_eq!(redirect_uri, None);
+    }
+
+    #[tokio::test]
+    async fn test_authorization_code_pkce_required() {
+        let request = AuthorizationRequest {
+            response_type: ResponseType::Code,
+            client_id: "s6BhdRkqt3".to_string(),
+            redirect_uri: Some("https://client.example.com/cb".to_string()),
+            state: Some("xyz".to_string()),
+            ..Default::default()
+        };
+
+        let client_store = TestClientRepository {
+            client_ids: vec!["s6BhdRkqt3".to_string()],
+            redirect_uris: vec![vec!["https://client.example.com/cb".to_string()]],
+            require_pkce: true,
+        };
+        let code_store = MapAuthorizationCodeRepository::default();
+
+        let response = authorize(request.clone(), &client_store, &code_store).await;
+
+        let AuthorizationFailureResponse(response, redirect_uri) = response.unwrap_err();
+        assert_eq!(response.error, AuthorizationError::InvalidRequest);
+        assert_eq!(redirect_uri, request.redirect_uri);
+
+        let request = AuthorizationRequest {
+            code_challenge: Some("3rVY5lb1B1U6wNRRVjUQviq87vkocpnjj0LyG6p3vu0".to_string()),
+            code_challenge_method: Some(CodeChallengeMethod::S256),
+            ..request
+        };
+
+        let response = authorize(request.clone(), &client_store, &code_store).await;
+
+        let AuthorizationSuccessResponse(response, _) = response.unwrap();
+        let stored_code = code_store
+            .consume_authorization_code(&response.code)
+            .unwrap();
+        assert_eq!(stored_code.code_challenge, request.code_challenge);
+        assert_eq!(stored_code.code_challenge_method, CodeChallengeMethod::S256);
+    }
+
+    #[tokio::test]
+    async fn test_authorization_code_invalid_code_challenge() {
+        let request = AuthorizationRequest {
+            response_type: ResponseType::Code,
+            client_id: "s6BhdRkqt3".to_string(),
+            redirect_uri: Some("https://client.example.com/cb".to_string()),
+            code_challenge: Some("too-short".to_string()),
+            ..Default::default()
+        };
+
+        let client_store = TestClientRepository {
+            client_ids: vec!["s6BhdRkqt3".to_string()],
+            redirect_uris: vec![vec!["https://client.example.com/cb".to_string()]],
+            require_pkce: false,
+        };
+        let code_store = MapAuthorizationCodeRepository::default();
+
+        let response = authorize(request.clone(), &client_store, &code_store).await;
+
+        let AuthorizationFailureResponse(response, _) = response.unwrap_err();
+        assert_eq!(response.error, AuthorizationError::InvalidRequest);
     }
 
     #[test]
@@ -332,4 +1104,259 @@ mod tests {
         let auth_code = generate_authorization_code();
         assert_eq!(auth_code.len(), 24);
     }
+
+    #[test]
+    fn test_response_mode() {
+        let auth_request: AuthorizationRequest = serde_urlencoded::from_str(
+            "response_type=code&client_id=s6BhdRkqt3&response_mode=form_post.jwt",
+        )
+        .unwrap();
+        assert_eq!(auth_request.response_mode, Some(ResponseMode::FormPostJwt));
+        assert_eq!(
+            ResponseMode::FormPostJwt.delivery(),
+            (ResponseMode::FormPost, true)
+        );
+        assert_eq!(ResponseMode::Jwt.delivery(), (ResponseMode::Query, true));
+        assert_eq!(
+            ResponseMode::Fragment.delivery(),
+            (ResponseMode::Fragment, false)
+        );
+        assert!(
+            serde_urlencoded::from_str::<AuthorizationRequest>(
+                "response_type=code&client_id=s6BhdRkqt3&response_mode=web_message"
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_jwt_authorization_response() {
+        let signing_key = SigningKey::from_pem(include_str!("testdata/p256.pem")).unwrap();
+        let key_store = MapKeyRepository::from_signing_keys(vec![signing_key.clone()]);
+        let token_settings = TokenSettings {
+            issuer: "https://keyper.example.com".to_string(),
+            ..Default::default()
+        };
+
+        let response = jwt_authorization_response(
+            &AuthorizationResponse {
+                code: "PyyFaux2o7Q0YfXBU32jhw.5FXSQpvr8akv9CeRDSd0QA".to_string(),
+                state: Some("S8NJ7uqk5fY4EjNvP_G_FtyJu6pUsvH9jsYni9dMAJw".to_string()),
+            },
+            "s6BhdRkqt3",
+            &token_settings,
+            &key_store,
+        )
+        .unwrap();
+
+        let (header, claims) = verify(&signing_key, &response);
+        assert_eq!(header["alg"], "ES256");
+        assert_eq!(claims["iss"], "https://keyper.example.com");
+        assert_eq!(claims["aud"], "s6BhdRkqt3");
+        assert!(claims["exp"].as_i64().unwrap() > Utc::now().timestamp());
+        assert_eq!(
+            claims["code"],
+            "PyyFaux2o7Q0YfXBU32jhw.5FXSQpvr8akv9CeRDSd0QA"
+        );
+        assert_eq!(
+            claims["state"],
+            "S8NJ7uqk5fY4EjNvP_G_FtyJu6pUsvH9jsYni9dMAJw"
+        );
+
+        assert!(
+            jwt_authorization_response(
+                &AuthorizationErrorResponse::new(AuthorizationError::LoginRequired, None),
+                "s6BhdRkqt3",
+                &token_settings,
+                &MapKeyRepository::default(),
+            )
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_authorization_code_authentication() {
+        let client_store = TestClientRepository {
+            client_ids: vec!["s6BhdRkqt3".to_string()],
+            redirect_uris: vec![vec!["https://client.example.com/cb".to_string()]],
+            require_pkce: false,
+        };
+        let code_store = MapAuthorizationCodeRepository::default();
+        let signing_key = SigningKey::from_pem(include_str!("testdata/ed25519.pem")).unwrap();
+        let key_store = MapKeyRepository::from_signing_keys(vec![signing_key.clone()]);
+        let token_settings = TokenSettings::default();
+        let id_token_hint = |sub: &str| {
+            signing_key
+                .sign("JWT", &json!({"iss": token_settings.issuer, "sub": sub}))
+                .unwrap()
+        };
+        let request = AuthorizationRequest {
+            response_type: ResponseType::Code,
+            client_id: "s6BhdRkqt3".to_string(),
+            redirect_uri: Some("https://client.example.com/cb".to_string()),
+            scope: Some("openid".to_string()),
+            ..Default::default()
+        };
+        let session = create_session(Utc::now() - Duration::seconds(600));
+        let login_requested = Some(Utc::now() - Duration::seconds(300));
+        let fresh_session = create_session(Utc::now() - Duration::seconds(60));
+
+        for (request, session, expected) in [
+            (
+                AuthorizationRequest {
+                    prompt: Some("none".to_string()),
+                    max_age: Some(3600),
+                    login_hint: Some("alice".to_string()),
+                    id_token_hint: Some(id_token_hint("alice")),
+                    acr_values: Some("urn:example:acr:mfa".to_string()),
+                    ..request.clone()
+                },
+                Some(&session),
+                None,
+            ),
+            (
+                AuthorizationRequest {
+                    prompt: Some("none".to_string()),
+                    ..request.clone()
+                },
+                None,
+                Some(AuthorizationError::LoginRequired),
+            ),
+            (
+                AuthorizationRequest {
+                    prompt: Some("login".to_string()),
+                    ..request.clone()
+                },
+                Some(&session),
+                Some(AuthorizationError::LoginRequired),
+            ),
+            (
+                AuthorizationRequest {
+                    max_age: Some(60),
+                    ..request.clone()
+                },
+                Some(&session),
+                Some(AuthorizationError::LoginRequired),
+            ),
+            (
+                AuthorizationRequest {
+                    id_token_hint: Some(id_token_hint("bob")),
+                    ..request.clone()
+                },
+                Some(&session),
+                Some(AuthorizationError::LoginRequired),
+            ),
+            (
+                AuthorizationRequest {
+                    prompt: Some("none".to_string()),
+                    login_hint: Some("bob".to_string()),
+                    ..request.clone()
+                },
+                Some(&session),
+                Some(AuthorizationError::InteractionRequired),
+            ),
+            (
+                AuthorizationRequest {
+                    prompt: Some("consent".to_string()),
+                    ..request.clone()
+                },
+                Some(&session),
+                Some(AuthorizationError::ConsentRequired),
+            ),
+            (
+                AuthorizationRequest {
+                    prompt: Some("none login".to_string()),
+                    ..request.clone()
+                },
+                Some(&session),
+                Some(AuthorizationError::InvalidRequest),
+            ),
+            (
+                AuthorizationRequest {
+                    id_token_hint: Some("foobar".to_string()),
+                    ..request.clone()
+                },
+                Some(&session),
+                Some(AuthorizationError::InvalidRequest),
+            ),
+            // A login after the request was sent to the login page satisfies it, an older one
+            // does not
+            (
+                AuthorizationRequest {
+                    prompt: Some("login".to_string()),
+                    max_age: Some(0),
+                    login_hint: Some("alice".to_string()),
+                    id_token_hint: Some(id_token_hint("alice")),
+                    login_requested,
+                    ..request.clone()
+                },
+                Some(&fresh_session),
+                None,
+            ),
+            (
+                AuthorizationRequest {
+                    prompt: Some("login".to_string()),
+                    login_requested,
+                    ..request.clone()
+                },
+                Some(&session),
+                Some(AuthorizationError::LoginRequired),
+            ),
+            (
+                AuthorizationRequest {
+                    max_age: Some(0),
+                    login_requested,
+                    ..request.clone()
+                },
+                Some(&session),
+                Some(AuthorizationError::LoginRequired),
+            ),
+            (
+                AuthorizationRequest {
+                    prompt: Some("login".to_string()),
+                    id_token_hint: Some(id_token_hint("bob")),
+                    login_requested,
+                    ..request.clone()
+                },
+                Some(&fresh_session),
+                Some(AuthorizationError::LoginRequired),
+            ),
+            (
+                AuthorizationRequest {
+                    login_hint: Some("bob".to_string()),
+                    login_requested,
+                    ..request.clone()
+                },
+                Some(&fresh_session),
+                Some(AuthorizationError::LoginRequired),
+            ),
+        ] {
+            let description = format!("{request:?}");
+            let result = authorization_code(
+                request,
+                session,
+                &token_settings,
+                &client_store,
+                &code_store,
+                &key_store,
+            )
+            .await;
+
+            match expected {
+                None => {
+                    let AuthorizationSuccessResponse(response, _) = result.unwrap();
+                    let stored_code = code_store
+                        .consume_authorization_code(&response.code)
+                        .unwrap();
+                    assert_eq!(stored_code.auth_time, session.unwrap().authenticated);
+                }
+                Some(expected) => {
+                    let AuthorizationFailureResponse(error_response, redirect_uri) =
+                        result.unwrap_err();
+                    assert_eq!(error_response.error, expected, "{description}");
+                    assert!(redirect_uri.is_some());
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,448 @@
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use rand::{distributions, prelude::*};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::core::{
+    authorization::Client,
+    token::{AccessTokenError, AccessTokenErrorResponse, AccessTokenRequest},
+};
+
+const DEVICE_CODE_TTL: i64 = 600;
+const POLLING_INTERVAL: i64 = 5;
+/// Added to the polling interval whenever a client polls too fast (RFC 8628 section 3.5)
+const SLOW_DOWN_INCREMENT: i64 = 5;
+/// Consonants only, so user codes neither spell words nor contain ambiguous characters
+/// (RFC 8628 section 6.1)
+const USER_CODE_ALPHABET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+const USER_CODE_LENGTH: usize = 8;
+/// User codes generated before giving up when every one of them is already pending
+const USER_CODE_GENERATION_ATTEMPTS: usize = 5;
+/// Unknown or expired user codes a login session may enter before it is refused any further
+/// lookups, which keeps pending user codes from being guessed (RFC 8628 section 5.1)
+pub const USER_CODE_ATTEMPTS: u32 = 5;
+
+#[derive(Deserialize, Default, Debug)]
+pub struct DeviceAuthorizationRequest {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub scope: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DeviceAuthorizationResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: String,
+    pub expires_in: i64,
+    pub interval: i64,
+}
+
+pub trait DeviceCodeRepository {
+    fn create_device_code(&self, device_code: DeviceCode) -> Result<()>;
+    fn read_device_code(&self, device_code: &str) -> Option<DeviceCode>;
+    fn read_device_code_by_user_code(&self, user_code: &str) -> Option<DeviceCode>;
+    /// Records a poll of the client and returns the device code with its current status. The
+    /// status is left untouched, so a decision made while the client polls is not lost.
+    fn update_device_code_polling(
+        &self,
+        device_code: &str,
+        interval: i64,
+        last_polled: DateTime<Utc>,
+    ) -> Result<DeviceCode>;
+    /// Moves a pending device code to `status` and returns it, unless it was decided before
+    fn update_device_code_status(
+        &self,
+        device_code: &str,
+        status: DeviceCodeStatus,
+    ) -> Result<Option<DeviceCode>>;
+    fn consume_device_code(&self, device_code: &str) -> Option<DeviceCode>;
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceCode {
+    pub device_code: String,
+    /// Normalized user code without separators
+    pub user_code: String,
+    pub client_id: String,
+    pub scopes: Vec<String>,
+    pub status: DeviceCodeStatus,
+    /// Seconds the client has to wait between two polls
+    pub interval: i64,
+    pub last_polled: Option<DateTime<Utc>>,
+    pub expires: DateTime<Utc>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum DeviceCodeStatus {
+    Pending,
+    /// Approved by the contained resource owner
+    Approved(String),
+    Denied,
+}
+
+/// Issues a device code and user code pair (RFC 8628 section 3.2). The user code has to be
+/// entered at the verification URI by the resource owner.
+pub fn device_authorization<D: DeviceCodeRepository>(
+    device_authorization_request: &DeviceAuthorizationRequest,
+    client: &Client,
+    verification_uri: &str,
+    device_code_store: &D,
+) -> Result<DeviceAuthorizationResponse, AccessTokenErrorResponse> {
+    let now = Utc::now();
+    let mut device_code = DeviceCode {
+        device_code: generate_device_code(),
+        user_code: String::new(),
+        client_id: client.id.clone(),
+        scopes: device_authorization_request
+            .scope
+            .as_deref()
+            .map(|scope| scope.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default(),
+        status: DeviceCodeStatus::Pending,
+        interval: POLLING_INTERVAL,
+        last_polled: None,
+        expires: now + Duration::seconds(DEVICE_CODE_TTL),
+    };
+
+    // The store refuses user codes that are already pending, so a colliding one is replaced
+    for _ in 0..USER_CODE_GENERATION_ATTEMPTS {
+        device_code.user_code = generate_user_code();
+        let user_code = format_user_code(&device_code.user_code);
+        let device_authorization_response = DeviceAuthorizationResponse {
+            device_code: device_code.device_code.clone(),
+            verification_uri: verification_uri.to_string(),
+            verification_uri_complete: format!("{verification_uri}?user_code={user_code}"),
+            user_code,
+            expires_in: DEVICE_CODE_TTL,
+            interval: POLLING_INTERVAL,
+        };
+
+        match device_code_store.create_device_code(device_code.clone()) {
+            Ok(()) => return Ok(device_authorization_response),
+            Err(error) => warn!("Could not store device code: {error}"),
+        }
+    }
+
+    Err(AccessTokenErrorResponse::new(AccessTokenError::ServerError))
+}
+
+/// Looks up a device code that is still waiting for the decision of a resource owner
+pub fn pending_device_code<D: DeviceCodeRepository>(
+    user_code: &str,
+    device_code_store: &D,
+) -> Option<DeviceCode> {
+    device_code_store
+        .read_device_code_by_user_code(&normalize_user_code(user_code))
+        .filter(|device_code| {
+            device_code.status == DeviceCodeStatus::Pending && device_code.expires > Utc::now()
+        })
+}
+
+/// Records whether the resource owner approved or denied the device authorization request
+/// identified by the user code
+pub fn decide_device_code<D: DeviceCodeRepository>(
+    user_code: &str,
+    owner: &str,
+    approved: bool,
+    device_code_store: &D,
+) -> Result<Option<DeviceCode>> {
+    let Some(device_code) = pending_device_code(user_code, device_code_store) else {
+        return Ok(None);
+    };
+
+    let status = if approved {
+        DeviceCodeStatus::Approved(owner.to_string())
+    } else {
+        DeviceCodeStatus::Denied
+    };
+
+    device_code_store.update_device_code_status(&device_code.device_code, status)
+}
+
+/// Answers a polling device access token request (RFC 8628 section 3.4). Returns the device code
+/// together with the approving resource owner once the request has been approved.
+pub fn redeem_device_code<D: DeviceCodeRepository>(
+    access_token_request: &AccessTokenRequest,
+    client: &Client,
+    device_code_store: &D,
+) -> Result<(DeviceCode, String), AccessTokenErrorResponse> {
+    let Some(code) = &access_token_request.device_code else {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidRequest,
+        ));
+    };
+
+    let Some(device_code) = device_code_store.read_device_code(code) else {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidGrant,
+        ));
+    };
+
+    if device_code.client_id != client.id {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidGrant,
+        ));
+    }
+
+    let now = Utc::now();
+    if device_code.expires <= now {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::ExpiredToken,
+        ));
+    }
+
+    let too_fast = device_code
+        .last_polled
+        .is_some_and(|last_polled| now < last_polled + Duration::seconds(device_code.interval));
+    let interval = if too_fast {
+        device_code.interval + SLOW_DOWN_INCREMENT
+    } else {
+        device_code.interval
+    };
+
+    let Ok(device_code) = device_code_store.update_device_code_polling(code, interval, now) else {
+        return Err(AccessTokenErrorResponse::new(AccessTokenError::ServerError));
+    };
+
+    if too_fast {
+        return Err(AccessTokenErrorResponse::new(AccessTokenError::SlowDown));
+    }
+
+    match device_code.status {
+        DeviceCodeStatus::Pending => Err(AccessTokenErrorResponse::new(
+            AccessTokenError::AuthorizationPending,
+        )),
+        DeviceCodeStatus::Denied => {
+            device_code_store.consume_device_code(code);
+            Err(AccessTokenErrorResponse::new(
+                AccessTokenError::AccessDenied,
+            ))
+        }
+        DeviceCodeStatus::Approved(_) => {
+            let Some(device_code) = device_code_store.consume_device_code(code) else {
+                warn!("Device code for client {} was redeemed twice", client.id);
+                return Err(AccessTokenErrorResponse::new(
+                    AccessTokenError::InvalidGrant,
+                ));
+            };
+
+            match device_code.status.clone() {
+                DeviceCodeStatus::Approved(owner) => Ok((device_code, owner)),
+                _ => Err(AccessTokenErrorResponse::new(
+                    AccessTokenError::InvalidGrant,
+                )),
+            }
+        }
+    }
+}
+
+/// Strips separators and whitespace so user codes can be entered case-insensitively
+pub fn normalize_user_code(user_code: &str) -> String {
+    user_code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Splits a normalized user code into two halves for readability, e.g. `WDJB-MJHT`
+pub fn format_user_code(user_code: &str) -> String {
+    let (first, second) = user_code.split_at(user_code.len() / 2);
+    format!("{first}-{second}")
+}
+
+fn generate_device_code() -> String {
+    let rng = thread_rng();
+    rng.sample_iter(distributions::Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+fn generate_user_code() -> String {
+    let mut rng = thread_rng();
+    (0..USER_CODE_LENGTH)
+        .map(|_| char::from(*USER_CODE_ALPHABET.choose(&mut rng).unwrap()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        core::{
+            authorization::{Client, ClientRepository},
+            device::{
+                DeviceAuthorizationRequest, DeviceCodeRepository, DeviceCodeStatus,
+                decide_device_code, device_authorization, format_user_code, normalize_user_code,
+                pending_device_code, redeem_device_code,
+            },
+            token::{AccessTokenError, AccessTokenRequest, GrantType},
+        },
+        repository::{client::TestClientRepository, device_code::MapDeviceCodeRepository},
+    };
+
+    fn create_client() -> Client {
+        TestClientRepository {
+            client_ids: vec!["s6BhdRkqt3".to_string()],
+        }
+        .read_client("s6BhdRkqt3")
+        .unwrap()
+    }
+
+    fn create_request(device_code: &str) -> AccessTokenRequest {
+        AccessTokenRequest {
+            grant_type: GrantType::DeviceCode,
+            device_code: Some(device_code.to_string()),
+            client_id: Some("s6BhdRkqt3".to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Pretends the last poll happened long enough ago to not trigger `slow_down`
+    fn wait_interval(device_code: &str, device_code_store: &MapDeviceCodeRepository) {
+        let mut data = device_code_store.data.lock().unwrap();
+        let device_code = data.get_mut(device_code).unwrap();
+        device_code.last_polled = device_code
+            .last_polled
+            .map(|last_polled| last_polled - Duration::seconds(device_code.interval));
+    }
+
+    #[test]
+    fn test_device_authorization() {
+        let device_code_store = MapDeviceCodeRepository::default();
+        let request = DeviceAuthorizationRequest {
+            scope: Some("read write".to_string()),
+            ..Default::default()
+        };
+
+        let response = device_authorization(
+            &request,
+            &create_client(),
+            "https://keyper.example.com/device",
+            &device_code_store,
+        )
+        .unwrap();
+        assert_eq!(response.user_code.len(), 9);
+        assert_eq!(response.interval, 5);
+        assert_eq!(
+            response.verification_uri_complete,
+            format!(
+                "https://keyper.example.com/device?user_code={}",
+                response.user_code
+            )
+        );
+
+        let device_code = pending_device_code(&response.user_code, &device_code_store).unwrap();
+        assert_eq!(device_code.device_code, response.device_code);
+        assert_eq!(device_code.scopes, vec!["read", "write"]);
+    }
+
+    #[test]
+    fn test_redeem_device_code() {
+        let device_code_store = MapDeviceCodeRepository::default();
+        let client = create_client();
+        let response = device_authorization(
+            &DeviceAuthorizationRequest::default(),
+            &client,
+            "https://keyper.example.com/device",
+            &device_code_store,
+        )
+        .unwrap();
+        let request = create_request(&response.device_code);
+
+        let error = redeem_device_code(&request, &client, &device_code_store).unwrap_err();
+        assert_eq!(error.error, AccessTokenError::AuthorizationPending);
+
+        let error = redeem_device_code(&request, &client, &device_code_store).unwrap_err();
+        assert_eq!(error.error, AccessTokenError::SlowDown);
+        let device_code = device_code_store
+            .read_device_code(&response.device_code)
+            .unwrap();
+        assert_eq!(device_code.interval, 10);
+
+        let user_code = response.user_code.to_lowercase().replace('-', " ");
+        let device_code = decide_device_code(&user_code, "alice", true, &device_code_store)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            device_code.status,
+            DeviceCodeStatus::Approved("alice".to_string())
+        );
+        assert!(pending_device_code(&user_code, &device_code_store).is_none());
+
+        wait_interval(&response.device_code, &device_code_store);
+        let (device_code, owner) =
+            redeem_device_code(&request, &client, &device_code_store).unwrap();
+        assert_eq!(device_code.client_id, "s6BhdRkqt3");
+        assert_eq!(owner, "alice");
+
+        let error = redeem_device_code(&request, &client, &device_code_store).unwrap_err();
+        assert_eq!(error.error, AccessTokenError::InvalidGrant);
+    }
+
+    #[test]
+    fn test_redeem_device_code_denied() {
+        let device_code_store = MapDeviceCodeRepository::default();
+        let client = create_client();
+        let response = device_authorization(
+            &DeviceAuthorizationRequest::default(),
+            &client,
+            "https://keyper.example.com/device",
+            &device_code_store,
+        )
+        .unwrap();
+        let request = create_request(&response.device_code);
+
+        decide_device_code(&response.user_code, "alice", false, &device_code_store)
+            .unwrap()
+            .unwrap();
+        assert!(
+            decide_device_code(&response.user_code, "alice", true, &device_code_store)
+                .unwrap()
+                .is_none()
+        );
+
+        let error = redeem_device_code(&request, &client, &device_code_store).unwrap_err();
+        assert_eq!(error.error, AccessTokenError::AccessDenied);
+        let error = redeem_device_code(&request, &client, &device_code_store).unwrap_err();
+        assert_eq!(error.error, AccessTokenError::InvalidGrant);
+    }
+
+    #[test]
+    fn test_redeem_device_code_expired() {
+        let device_code_store = MapDeviceCodeRepository::default();
+        let client = create_client();
+        let response = device_authorization(
+            &DeviceAuthorizationRequest::default(),
+            &client,
+            "https://keyper.example.com/device",
+            &device_code_store,
+        )
+        .unwrap();
+
+        device_code_store
+            .data
+            .lock()
+            .unwrap()
+            .get_mut(&response.device_code)
+            .unwrap()
+            .expires = Utc::now() - Duration::seconds(1);
+
+        let request = create_request(&response.device_code);
+        let error = redeem_device_code(&request, &client, &device_code_store).unwrap_err();
+        assert_eq!(error.error, AccessTokenError::ExpiredToken);
+        assert!(pending_device_code(&response.user_code, &device_code_store).is_none());
+    }
+
+    #[test]
+    fn test_user_code_format() {
+        assert_eq!(normalize_user_code("wdjb-mjht"), "WDJBMJHT");
+        assert_eq!(normalize_user_code(" WDJB MJHT "), "WDJBMJHT");
+        assert_eq!(format_user_code("WDJBMJHT"), "WDJB-MJHT");
+    }
+}
@@ -0,0 +1,279 @@
+use anyhow::Result;
+use base64::prelude::*;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::core::{
+    jose::ReceivedJwsHeader,
+    token::{AccessTokenError, AccessTokenErrorResponse, generate_token},
+};
+
+/// Seconds a server-supplied nonce is accepted in proofs
+pub const DPOP_NONCE_TTL: i64 = 300;
+/// Seconds `iat` of a proof may deviate from the server time. The `jti` of a proof is remembered
+/// at least as long as the proof would be accepted.
+const DPOP_PROOF_WINDOW: i64 = 300;
+
+pub trait DpopRepository {
+    /// Remembers the `jti` of a proof until `expires` and returns whether it was new
+    fn create_proof_jti(&self, jti: &str, expires: DateTime<Utc>) -> Result<bool>;
+    fn create_nonce(&self, nonce: DpopNonce) -> Result<()>;
+    fn read_nonces(&self) -> Vec<DpopNonce>;
+}
+
+#[derive(Clone, Debug)]
+pub struct DpopNonce {
+    pub nonce: String,
+    pub expires: DateTime<Utc>,
+}
+
+/// Confirmation claim binding a token to the key of a DPoP proof (RFC 9449 section 6)
+#[derive(Deserialize, Serialize, PartialEq, Clone, Debug)]
+pub struct Confirmation {
+    pub jkt: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct DpopProofClaims {
+    jti: String,
+    htm: String,
+    htu: String,
+    iat: i64,
+    nonce: Option<String>,
+    ath: Option<String>,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum DpopError {
+    InvalidProof(&'static str),
+    /// The proof lacks a nonce keyper currently accepts (RFC 9449 section 8)
+    UseNonce,
+    ServerError,
+}
+
+impl From<DpopError> for AccessTokenErrorResponse {
+    fn from(error: DpopError) -> Self {
+        match error {
+            DpopError::InvalidProof(error_description) => {
+                let mut access_token_error_response =
+                    AccessTokenErrorResponse::new(AccessTokenError::InvalidDpopProof);
+                access_token_error_response.error_description = Some(error_description.to_string());
+                access_token_error_response
+            }
+            DpopError::UseNonce => AccessTokenErrorResponse::new(AccessTokenError::UseDpopNonce),
+            DpopError::ServerError => AccessTokenErrorResponse::new(AccessTokenError::ServerError),
+        }
+    }
+}
+
+/// Checks a DPoP proof (RFC 9449 section 4.3) for a request with the given method and URI and
+/// returns the JWK thumbprint of its key. Proofs presented along with an access token have to
+/// carry its hash in `ath`.
+pub fn verify_dpop_proof<D: DpopRepository>(
+    proof: &str,
+    htm: &str,
+    htu: &str,
+    access_token: Option<&str>,
+    dpop_store: &D,
+) -> Result<String, DpopError> {
+    let header = ReceivedJwsHeader::decode(proof)
+        .map_err(|_| DpopError::InvalidProof("DPoP proof is no JWS"))?;
+    if header.typ.as_deref() != Some("dpop+jwt") {
+        return Err(DpopError::InvalidProof("typ has to be dpop+jwt"));
+    }
+    let jwk = header
+        .jwk
+        .ok_or(DpopError::InvalidProof("jwk is missing"))?;
+    let claims: DpopProofClaims = jwk
+        .verify(proof)
+        .and_then(|claims| Ok(serde_json::from_value(claims)?))
+        .map_err(|_| DpopError::InvalidProof("Signature or claims are invalid"))?;
+
+    if claims.htm != htm {
+        return Err(DpopError::InvalidProof("htm does not match the request"));
+    }
+    // Query and fragment are ignored (RFC 9449 section 4.3)
+    if claims.htu.split(['?', '#']).next() != Some(htu) {
+        return Err(DpopError::InvalidProof("htu does not match the request"));
+    }
+    let now = Utc::now();
+    if (now.timestamp() - claims.iat).abs() > DPOP_PROOF_WINDOW {
+        return Err(DpopError::InvalidProof(
+            "iat is too far from the current time",
+        ));
+    }
+    if let Some(access_token) = access_token
+        && claims.ath.as_deref() != Some(&access_token_hash(access_token))
+    {
+        return Err(DpopError::InvalidProof(
+            "ath does not match the access token",
+        ));
+    }
+
+    let nonce_accepted = claims.nonce.is_some_and(|nonce| {
+        dpop_store
+            .read_nonces()
+            .iter()
+            .any(|accepted| accepted.nonce == nonce && accepted.expires > now)
+    });
+    if !nonce_accepted {
+        return Err(DpopError::UseNonce);
+    }
+
+    let jti_expires = now + Duration::seconds(2 * DPOP_PROOF_WINDOW);
+    match dpop_store.create_proof_jti(&claims.jti, jti_expires) {
+        Ok(true) => Ok(jwk.thumbprint()),
+        Ok(false) => Err(DpopError::InvalidProof("DPoP proof was used before")),
+        Err(_) => Err(DpopError::ServerError),
+    }
+}
+
+/// Nonce clients have to put into their next proofs. A fresh nonce replaces the current one once
+/// half of its lifetime passed, so nonces handed out recently stay usable for a while.
+pub fn dpop_nonce<D: DpopRepository>(dpop_store: &D) -> Result<String> {
+    let now = Utc::now();
+    let current = dpop_store
+        .read_nonces()
+        .into_iter()
+        .filter(|nonce| nonce.expires > now + Duration::seconds(DPOP_NONCE_TTL / 2))
+        .max_by_key(|nonce| nonce.expires);
+    if let Some(current) = current {
+        return Ok(current.nonce);
+    }
+
+    let nonce = DpopNonce {
+        nonce: generate_token(),
+        expires: now + Duration::seconds(DPOP_NONCE_TTL),
+    };
+    dpop_store.create_nonce(nonce.clone())?;
+
+    Ok(nonce.nonce)
+}
+
+/// Value of the `ath` claim for an access token
+fn access_token_hash(access_token: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(access_token.as_bytes()))
+}
+
+#[cfg(test)]
+pub mod tests {
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+
+    use crate::{
+        core::{
+            dpop::{
+                DpopError, DpopNonce, DpopRepository, access_token_hash, dpop_nonce,
+                verify_dpop_proof,
+            },
+            jose::{Algorithm, SigningKey},
+            token::generate_token,
+        },
+        repository::dpop::MapDpopRepository,
+    };
+
+    pub fn create_proof(
+        signing_key: &SigningKey,
+        htm: &str,
+        htu: &str,
+        nonce: Option<&str>,
+        access_token: Option<&str>,
+    ) -> String {
+        let mut claims = json!({
+            "jti": generate_token(),
+            "htm": htm,
+            "htu": htu,
+            "iat": Utc::now().timestamp(),
+        });
+        if let Some(nonce) = nonce {
+            claims["nonce"] = json!(nonce);
+        }
+        if let Some(access_token) = access_token {
+            claims["ath"] = json!(access_token_hash(access_token));
+        }
+
+        signing_key.sign_with_jwk("dpop+jwt", &claims).unwrap()
+    }
+
+    #[test]
+    fn test_verify_dpop_proof() {
+        let dpop_store = MapDpopRepository::default();
+        let signing_key = SigningKey::generate(Algorithm::ES256).unwrap();
+        let htu = "https://keyper.example.com/token";
+
+        let proof = create_proof(&signing_key, "POST", htu, None, None);
+        assert_eq!(
+            verify_dpop_proof(&proof, "POST", htu, None, &dpop_store),
+            Err(DpopError::UseNonce)
+        );
+
+        let nonce = dpop_nonce(&dpop_store).unwrap();
+        assert_eq!(dpop_nonce(&dpop_store).unwrap(), nonce);
+        let proof = create_proof(&signing_key, "POST", htu, Some(&nonce), None);
+        let jkt = verify_dpop_proof(&proof, "POST", htu, None, &dpop_store).unwrap();
+        assert_eq!(jkt, signing_key.public_jwk().thumbprint());
+        assert!(matches!(
+            verify_dpop_proof(&proof, "POST", htu, None, &dpop_store),
+            Err(DpopError::InvalidProof(_))
+        ));
+
+        let invalid_proofs = [
+            create_proof(&signing_key, "GET", htu, Some(&nonce), None),
+            create_proof(
+                &signing_key,
+                "POST",
+                "https://other.example.com/token",
+                Some(&nonce),
+                None,
+            ),
+            create_proof(&signing_key, "POST", htu, Some(&nonce), Some("other")),
+            signing_key.sign("dpop+jwt", &json!({})).unwrap(),
+        ];
+        for proof in invalid_proofs {
+            assert!(matches!(
+                verify_dpop_proof(
+                    &proof,
+                    "POST",
+                    htu,
+                    Some("2YotnFZFEjr1zMsicMWpAA"),
+                    &dpop_store
+                ),
+                Err(DpopError::InvalidProof(_))
+            ));
+        }
+
+        let proof = create_proof(
+            &signing_key,
+            "POST",
+            &format!("{htu}?foo=bar"),
+            Some(&nonce),
+            Some("2YotnFZFEjr1zMsicMWpAA"),
+        );
+        assert!(
+            verify_dpop_proof(
+                &proof,
+                "POST",
+                htu,
+                Some("2YotnFZFEjr1zMsicMWpAA"),
+                &dpop_store
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_dpop_nonce_rotation() {
+        let dpop_store = MapDpopRepository::default();
+        dpop_store
+            .create_nonce(DpopNonce {
+                nonce: "aging".to_string(),
+                expires: Utc::now() + Duration::seconds(60),
+            })
+            .unwrap();
+
+        let nonce = dpop_nonce(&dpop_store).unwrap();
+        assert_ne!(nonce, "aging");
+        assert_eq!(dpop_store.read_nonces().len(), 2);
+    }
+}
@@ -0,0 +1,241 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    authorization::{ClientRepository, ClientType},
+    dpop::Confirmation,
+    token::{
+        AccessTokenError, AccessTokenErrorResponse, AuthorizationRepository, ClientCredentials,
+        TokenType, authenticate_client,
+    },
+};
+
+#[derive(Deserialize, Default, Debug)]
+pub struct IntrospectionRequest {
+    pub token: String,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+/// Introspection response (RFC 7662 section 2.2). Only `active` is present for inactive tokens.
+#[derive(Serialize, Default, Debug)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<TokenType>,
+    /// Key the token is bound to, which the resource server checks DPoP proofs against (RFC
+    /// 9449 section 6.2)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cnf: Option<Confirmation>,
+}
+
+/// Looks up the state of an access token on behalf of a protected resource. Only confidential
+/// clients may introspect tokens, so token scanning requires valid client credentials.
+pub async fn introspect<C: ClientRepository, R: AuthorizationRepository>(
+    introspection_request: IntrospectionRequest,
+    client_credentials: ClientCredentials,
+    client_store: &C,
+    authorization_store: &R,
+) -> Result<IntrospectionResponse, AccessTokenErrorResponse> {
+    let client = authenticate_client(client_credentials, client_store)?;
+    if client.client_type != ClientType::Confidential {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidClient,
+        ));
+    }
+
+    let Some(authorization) = authorization_store
+        .read_authorization(&introspection_request.token)
+        .filter(|authorization| authorization.expires > Utc::now())
+    else {
+        return Ok(IntrospectionResponse::default());
+    };
+
+    Ok(IntrospectionResponse {
+        active: true,
+        token_type: Some(authorization.token_type()),
+        cnf: authorization.jkt.map(|jkt| Confirmation { jkt }),
+        scope: (!authorization.scopes.is_empty()).then(|| authorization.scopes.join(" ")),
+        client_id: Some(authorization.client_id),
+        sub: authorization.owner,
+        exp: Some(authorization.expires.timestamp()),
+        iat: Some(authorization.created.timestamp()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use argon2::{
+        Argon2, PasswordHasher,
+        password_hash::{SaltString, rand_core::OsRng},
+    };
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        core::{
+            dpop::Confirmation,
+            introspection::{IntrospectionRequest, introspect},
+            token::{
+                AccessTokenError, Authorization, AuthorizationRepository, ClientCredentials,
+                TokenType,
+            },
+        },
+        repository::{authorization::MapAuthorizationRepository, client::MapClientRepository},
+    };
+
+    fn create_client_store() -> MapClientRepository {
+        let salt = SaltString::generate(&mut OsRng);
+        let secret_hash = Argon2::default()
+            .hash_password(b"gX1fBat3bV", &salt)
+            .unwrap()
+            .to_string();
+
+        MapClientRepository::try_from_toml(&format!(
+            r#"
+            [resource_server]
+            name = "Resource Server"
+            client_type = "confidential"
+            redirect_uris = []
+            client_secret_hash = "{secret_hash}"
+
+            [public]
+            name = "Public Client"
+            client_type = "public"
+            redirect_uris = []
+        "#
+        ))
+        .unwrap()
+    }
+
+    fn create_authorization_store() -> MapAuthorizationRepository {
+        let authorization_store = MapAuthorizationRepository::default();
+        let now = Utc::now();
+        for (access_token, created, jkt) in [
+            ("active", now, None),
+            ("expired", now - Duration::seconds(7200), None),
+            (
+                "bound",
+                now,
+                Some("0ZcOCORZNYy-DWpqq30jZyJGHTN0d2HglBV3uiguA4I"),
+            ),
+        ] {
+            authorization_store
+                .create_authorization(Authorization {
+                    access_token: access_token.to_string(),
+                    client_id: "s6BhdRkqt3".to_string(),
+                    scopes: vec!["read".to_string(), "write".to_string()],
+                    owner: Some("alice".to_string()),
+                    created,
+                    expires: created + Duration::seconds(3600),
+                    refresh_token: None,
+                    jkt: jkt.map(str::to_string),
+                })
+                .unwrap();
+        }
+
+        authorization_store
+    }
+
+    fn client_credentials(client_id: &str, client_secret: &str) -> ClientCredentials {
+        ClientCredentials::from_request(
+            Some((client_id.to_string(), client_secret.to_string())),
+            None,
+            None,
+        )
+        .unwrap()
+    }
+
+    fn introspection_request(token: &str) -> IntrospectionRequest {
+        IntrospectionRequest {
+            token: token.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_introspect() {
+        let client_store = create_client_store();
+        let authorization_store = create_authorization_store();
+
+        let response = introspect(
+            introspection_request("active"),
+            client_credentials("resource_server", "gX1fBat3bV"),
+            &client_store,
+            &authorization_store,
+        )
+        .await
+        .unwrap();
+        assert!(response.active);
+        assert_eq!(response.scope, Some("read write".to_string()));
+        assert_eq!(response.client_id, Some("s6BhdRkqt3".to_string()));
+        assert_eq!(response.sub, Some("alice".to_string()));
+        assert_eq!(response.token_type, Some(TokenType::Bearer));
+        assert_eq!(response.exp.unwrap() - response.iat.unwrap(), 3600);
+        assert!(response.cnf.is_none());
+
+        let response = introspect(
+            introspection_request("bound"),
+            client_credentials("resource_server", "gX1fBat3bV"),
+            &client_store,
+            &authorization_store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.token_type, Some(TokenType::Dpop));
+        assert_eq!(
+            response.cnf,
+            Some(Confirmation {
+                jkt: "0ZcOCORZNYy-DWpqq30jZyJGHTN0d2HglBV3uiguA4I".to_string()
+            })
+        );
+
+        for token in ["expired", "unknown"] {
+            let response = introspect(
+                introspection_request(token),
+                client_credentials("resource_server", "gX1fBat3bV"),
+                &client_store,
+                &authorization_store,
+            )
+            .await
+            .unwrap();
+            assert!(!response.active);
+            assert!(response.client_id.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_introspect_client_authentication() {
+        let client_store = create_client_store();
+        let authorization_store = create_authorization_store();
+
+        let error = introspect(
+            introspection_request("active"),
+            client_credentials("resource_server", "wrong"),
+            &client_store,
+            &authorization_store,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.error, AccessTokenError::InvalidClient);
+
+        let error = introspect(
+            introspection_request("active"),
+            ClientCredentials::from_request(None, Some("public".to_string()), None).unwrap(),
+            &client_store,
+            &authorization_store,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.error, AccessTokenError::InvalidClient);
+    }
+}
@@ -0,0 +1,284 @@
+use anyhow::{Result, bail};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    jose::{Algorithm, Jwk, SigningKey},
+    oidc::ID_TOKEN_TTL,
+    token::ACCESS_TOKEN_TTL,
+};
+
+pub trait KeyRepository {
+    fn create_key(&self, key: StoredKey) -> Result<()>;
+    /// Returns the key newly issued tokens are signed with
+    fn read_signing_key(&self) -> Option<SigningKey>;
+    fn read_keys(&self) -> Vec<StoredKey>;
+    /// Atomically retires the active key, activates the next key and stages `next` in its place.
+    /// Retired keys are dropped once `expires` has passed.
+    fn rotate_keys(&self, next: SigningKey, retired_expires: DateTime<Utc>) -> Result<()>;
+}
+
+/// Keys are staged as `Next` so verifiers can pick them up before they sign anything, and stay
+/// published as `Retired` until every token they signed has expired.
+#[derive(Deserialize, Serialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyState {
+    Next,
+    Active,
+    Retired,
+}
+
+#[derive(Clone, Debug)]
+pub struct StoredKey {
+    pub signing_key: SigningKey,
+    pub state: KeyState,
+    pub created: DateTime<Utc>,
+    pub activated: Option<DateTime<Utc>>,
+    /// End of publication for retired keys
+    pub expires: Option<DateTime<Utc>>,
+}
+
+/// ID tokens are signed with the active key, so it has to use the only algorithm OpenID Connect
+/// relying parties are required to support (OpenID Connect Core section 15.1)
+pub const ID_TOKEN_SIGNING_ALGORITHM: Algorithm = Algorithm::RS256;
+
+#[derive(Clone, Debug)]
+pub struct KeySettings {
+    /// Seconds a key signs new tokens before it is rotated out
+    pub rotation_interval: i64,
+    /// Algorithm of generated keys if there is no active key to take it from
+    pub algorithm: Algorithm,
+}
+
+impl Default for KeySettings {
+    fn default() -> Self {
+        Self {
+            rotation_interval: 30 * 24 * 3600,
+            algorithm: ID_TOKEN_SIGNING_ALGORITHM,
+        }
+    }
+}
+
+/// JWK set (RFC 7517 section 5)
+#[derive(Deserialize, Serialize, Default, Clone, Debug)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+/// Makes sure there is an active key and a next key, generating whichever is missing
+pub fn initialize_keys<K: KeyRepository>(key_settings: &KeySettings, key_store: &K) -> Result<()> {
+    let keys = key_store.read_keys();
+    let now = Utc::now();
+
+    for state in [KeyState::Active, KeyState::Next] {
+        if !keys.iter().any(|key| key.state == state) {
+            key_store.create_key(StoredKey {
+                signing_key: SigningKey::generate(key_algorithm(key_settings, key_store))?,
+                state,
+                created: now,
+                activated: (state == KeyState::Active).then_some(now),
+                expires: None,
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the active key with the next one and stages a freshly generated next key
+pub fn rotate_keys<K: KeyRepository>(key_settings: &KeySettings, key_store: &K) -> Result<()> {
+    initialize_keys(key_settings, key_store)?;
+
+    let next = SigningKey::generate(key_algorithm(key_settings, key_store))?;
+    let token_ttl = ACCESS_TOKEN_TTL.max(ID_TOKEN_TTL);
+    key_store.rotate_keys(next, Utc::now() + Duration::seconds(token_ttl))
+}
+
+/// Rotates the keys once the active key has been signing for longer than the rotation interval.
+/// Returns whether a rotation took place.
+pub fn rotate_keys_if_due<K: KeyRepository>(
+    key_settings: &KeySettings,
+    key_store: &K,
+) -> Result<bool> {
+    let keys = key_store.read_keys();
+    let Some(active) = keys.iter().find(|key| key.state == KeyState::Active) else {
+        initialize_keys(key_settings, key_store)?;
+        return Ok(true);
+    };
+
+    let rotation_due = Utc::now() - Duration::seconds(key_settings.rotation_interval);
+    let due = active
+        .activated
+        .is_none_or(|activated| activated <= rotation_due);
+    if due {
+        rotate_keys(key_settings, key_store)?;
+    }
+
+    Ok(due)
+}
+
+/// Rejects active and next keys that cannot sign ID tokens. Retired keys only remain published
+/// for verification, so their algorithm does not matter.
+pub fn check_id_token_keys<K: KeyRepository>(key_store: &K) -> Result<()> {
+    for key in key_store.read_keys() {
+        let algorithm = key.signing_key.algorithm();
+        if key.state != KeyState::Retired && algorithm != ID_TOKEN_SIGNING_ALGORITHM {
+            bail!(
+                "Key {} uses {algorithm:?}, but ID tokens have to be signed with {ID_TOKEN_SIGNING_ALGORITHM:?}",
+                key.signing_key.kid
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Public keys verifiers have to accept, which includes staged and retired keys
+pub fn jwk_set<K: KeyRepository>(key_store: &K) -> JwkSet {
+    let now = Utc::now();
+    let keys = key_store
+        .read_keys()
+        .iter()
+        .filter(|key| key.expires.is_none_or(|expires| expires > now))
+        .map(|key| key.signing_key.public_jwk())
+        .collect();
+
+    JwkSet { keys }
+}
+
+fn key_algorithm<K: KeyRepository>(key_settings: &KeySettings, key_store: &K) -> Algorithm {
+    key_store
+        .read_signing_key()
+        .map(|signing_key| signing_key.algorithm())
+        .unwrap_or(key_settings.algorithm)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        core::{
+            jose::{Algorithm, SigningKey},
+            key::{
+                KeyRepository, KeySettings, KeyState, StoredKey, check_id_token_keys,
+                initialize_keys, jwk_set, rotate_keys, rotate_keys_if_due,
+            },
+        },
+        repository::key::MapKeyRepository,
+    };
+
+    fn key_ids(key_store: &MapKeyRepository, state: KeyState) -> Vec<String> {
+        key_store
+            .read_keys()
+            .into_iter()
+            .filter(|key| key.state == state)
+            .map(|key| key.signing_key.kid)
+            .collect()
+    }
+
+    #[test]
+    fn test_rotate_keys() {
+        let key_settings = KeySettings::default();
+        let key_store = MapKeyRepository::default();
+        initialize_keys(&key_settings, &key_store).unwrap();
+        assert_eq!(key_store.read_keys().len(), 2);
+
+        let active = key_ids(&key_store, KeyState::Active);
+        let next = key_ids(&key_store, KeyState::Next);
+        assert_eq!(
+            key_store.read_signing_key().unwrap().algorithm(),
+            Algorithm::RS256
+        );
+
+        rotate_keys(&key_settings, &key_store).unwrap();
+        assert_eq!(key_ids(&key_store, KeyState::Retired), active);
+        assert_eq!(key_ids(&key_store, KeyState::Active), next);
+        assert_eq!(key_store.read_signing_key().unwrap().kid, next[0]);
+        assert_eq!(key_ids(&key_store, KeyState::Next).len(), 1);
+
+        let kids: Vec<String> = jwk_set(&key_store)
+            .keys
+            .into_iter()
+            .filter_map(|jwk| jwk.kid)
+            .collect();
+        assert_eq!(kids.len(), 3);
+        assert!(kids.contains(&active[0]));
+    }
+
+    #[test]
+    fn test_rotate_keys_if_due() {
+        let key_settings = KeySettings {
+            rotation_interval: 3600,
+            algorithm: Algorithm::ES256,
+        };
+        let key_store = MapKeyRepository::default();
+        assert!(rotate_keys_if_due(&key_settings, &key_store).unwrap());
+        assert_eq!(
+            key_store.read_signing_key().unwrap().algorithm(),
+            Algorithm::ES256
+        );
+        assert!(!rotate_keys_if_due(&key_settings, &key_store).unwrap());
+
+        let now = Utc::now();
+        let key_store = MapKeyRepository::default();
+        key_store
+            .create_key(StoredKey {
+                signing_key: SigningKey::generate(Algorithm::EdDSA).unwrap(),
+                state: KeyState::Active,
+                created: now - Duration::seconds(7200),
+                activated: Some(now - Duration::seconds(7200)),
+                expires: None,
+            })
+            .unwrap();
+        assert!(rotate_keys_if_due(&key_settings, &key_store).unwrap());
+        assert_eq!(key_ids(&key_store, KeyState::Retired).len(), 1);
+    }
+
+    #[test]
+    fn test_check_id_token_keys() {
+        let key_store = MapKeyRepository::from_signing_keys(vec![
+            SigningKey::from_pem(include_str!("testdata/rsa.pem")).unwrap(),
+        ]);
+        assert!(check_id_token_keys(&key_store).is_ok());
+
+        for signing_keys in [
+            vec![SigningKey::from_pem(include_str!("testdata/ed25519.pem")).unwrap()],
+            vec![
+                SigningKey::from_pem(include_str!("testdata/rsa.pem")).unwrap(),
+                SigningKey::from_pem(include_str!("testdata/p256.pem")).unwrap(),
+            ],
+        ] {
+            let key_store = MapKeyRepository::from_signing_keys(signing_keys);
+            assert!(check_id_token_keys(&key_store).is_err());
+        }
+
+        let now = Utc::now();
+        key_store
+            .create_key(StoredKey {
+                signing_key: SigningKey::generate(Algorithm::EdDSA).unwrap(),
+                state: KeyState::Retired,
+                created: now - Duration::seconds(7200),
+                activated: Some(now - Duration::seconds(7200)),
+                expires: Some(now + Duration::seconds(3600)),
+            })
+            .unwrap();
+        assert!(check_id_token_keys(&key_store).is_ok());
+    }
+
+    #[test]
+    fn test_jwk_set_omits_expired_keys() {
+        let now = Utc::now();
+        let key_store = MapKeyRepository::default();
+        key_store
+            .create_key(StoredKey {
+                signing_key: SigningKey::generate(Algorithm::EdDSA).unwrap(),
+                state: KeyState::Retired,
+                created: now - Duration::seconds(7200),
+                activated: Some(now - Duration::seconds(7200)),
+                expires: Some(now - Duration::seconds(1)),
+            })
+            .unwrap();
+        assert!(jwk_set(&key_store).keys.is_empty());
+    }
+}
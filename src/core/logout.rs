@@ -0,0 +1,596 @@
+use std::time;
+
+use anyhow::{Result, anyhow};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tracing::{error, warn};
+use url::Url;
+
+use crate::core::{
+    authentication::{Session, SessionRepository},
+    authorization::{Client, ClientRepository},
+    key::KeyRepository,
+    oidc,
+    token::{TokenSettings, generate_token},
+};
+
+pub const LOGOUT_TOKEN_TTL: i64 = 120;
+pub const BACKCHANNEL_LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+pub const BACKCHANNEL_LOGOUT_ATTEMPTS: u32 = 5;
+/// Delay before the first retry, doubled for every further attempt
+pub const BACKCHANNEL_LOGOUT_RETRY_DELAY: time::Duration = time::Duration::from_secs(2);
+const BACKCHANNEL_LOGOUT_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+/// Logout request of a relying party (OpenID Connect RP-Initiated Logout section 2)
+#[derive(Deserialize, Serialize, Default, Clone, Debug)]
+pub struct LogoutRequest {
+    pub id_token_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub post_logout_redirect_uri: Option<String>,
+    pub state: Option<String>,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum LogoutError {
+    InvalidIdTokenHint,
+    UnknownClient,
+    ClientMismatch,
+    MissingClient,
+    InvalidPostLogoutRedirectUri,
+}
+
+impl LogoutError {
+    pub fn description(&self) -> &'static str {
+        match self {
+            LogoutError::InvalidIdTokenHint => "The ID token hint was not issued by this server",
+            LogoutError::UnknownClient => "The client is unknown",
+            LogoutError::ClientMismatch => "The ID token hint was issued to a different client",
+            LogoutError::MissingClient => {
+                "A post logout redirect URI requires an ID token hint or client ID"
+            }
+            LogoutError::InvalidPostLogoutRedirectUri => {
+                "The post logout redirect URI is not registered for the client"
+            }
+        }
+    }
+}
+
+/// Logout request after validation
+#[derive(Debug)]
+pub struct ValidatedLogout {
+    pub client: Option<Client>,
+    /// Post logout redirect URI with `state` appended
+    pub redirect_uri: Option<String>,
+}
+
+/// Validates a logout request. The ID token hint only has to carry a valid signature by one of
+/// our keys and our issuer, expired ID tokens are explicitly allowed by the specification.
+pub fn validate_logout_request<C: ClientRepository, K: KeyRepository>(
+    logout_request: &LogoutRequest,
+    token_settings: &TokenSettings,
+    client_store: &C,
+    key_store: &K,
+) -> Result<ValidatedLogout, LogoutError> {
+    let claims = logout_request
+        .id_token_hint
+        .as_deref()
+        .map(|id_token_hint| {
+            oidc::verify_id_token_hint(id_token_hint, token_settings, key_store)
+                .ok_or(LogoutError::InvalidIdTokenHint)
+        })
+        .transpose()?;
+
+    let audience = claims.as_ref().and_then(|claims| match &claims["aud"] {
+        Value::String(aud) => Some(aud.clone()),
+        Value::Array(aud) => aud.first().and_then(Value::as_str).map(str::to_string),
+        _ => None,
+    });
+    let client_id = match (&logout_request.client_id, audience) {
+        (Some(client_id), Some(audience)) if *client_id != audience => {
+            return Err(LogoutError::ClientMismatch);
+        }
+        (Some(client_id), _) => Some(client_id.clone()),
+        (None, audience) => audience,
+    };
+    let client = client_id
+        .map(|client_id| {
+            client_store
+                .read_client(&client_id)
+                .ok_or(LogoutError::UnknownClient)
+        })
+        .transpose()?;
+
+    let redirect_uri = match &logout_request.post_logout_redirect_uri {
+        Some(post_logout_redirect_uri) => {
+            let Some(client) = &client else {
+                return Err(LogoutError::MissingClient);
+            };
+            if !client
+                .post_logout_redirect_uris
+                .contains(post_logout_redirect_uri)
+            {
+                return Err(LogoutError::InvalidPostLogoutRedirectUri);
+            }
+
+            let mut url = Url::parse(post_logout_redirect_uri)
+                .map_err(|_| LogoutError::InvalidPostLogoutRedirectUri)?;
+            if let Some(state) = &logout_request.state {
+                url.query_pairs_mut().append_pair("state", state);
+            }
+            Some(url.to_string())
+        }
+        None => None,
+    };
+
+    Ok(ValidatedLogout {
+        client,
+        redirect_uri,
+    })
+}
+
+/// Ends the login session, returning it if it existed
+pub fn end_session<S: SessionRepository>(session_id: &str, session_store: &S) -> Option<Session> {
+    session_store.delete_session(session_id)
+}
+
+/// Logout token claims (OpenID Connect Back-Channel Logout section 2.4)
+#[derive(Serialize, Debug)]
+pub struct LogoutTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub iat: i64,
+    pub exp: i64,
+    pub jti: String,
+    pub sid: String,
+    pub events: Value,
+}
+
+/// Logout token on its way to the back-channel logout URI of a client
+#[derive(Clone, Debug)]
+pub struct LogoutNotification {
+    pub client_id: String,
+    pub backchannel_logout_uri: String,
+    pub logout_token: String,
+}
+
+/// Signs a logout token for every client of the ended session that registered a back-channel
+/// logout URI
+pub fn logout_notifications<C: ClientRepository, K: KeyRepository>(
+    session: &Session,
+    token_settings: &TokenSettings,
+    client_store: &C,
+    key_store: &K,
+) -> Result<Vec<LogoutNotification>> {
+    let signing_key = key_store
+        .read_signing_key()
+        .ok_or_else(|| anyhow!("No signing key available for logout tokens"))?;
+    let now = Utc::now();
+
+    session
+        .clients
+        .iter()
+        .filter_map(|client_id| client_store.read_client(client_id))
+        .filter_map(|client| Some((client.backchannel_logout_uri?, client.id)))
+        .map(|(backchannel_logout_uri, client_id)| {
+            let claims = LogoutTokenClaims {
+                iss: token_settings.issuer.clone(),
+                sub: session.owner.clone(),
+                aud: client_id.clone(),
+                iat: now.timestamp(),
+                exp: (now + Duration::seconds(LOGOUT_TOKEN_TTL)).timestamp(),
+                jti: generate_token(),
+                sid: session.sid.clone(),
+                events: json!({ BACKCHANNEL_LOGOUT_EVENT: {} }),
+            };
+
+            Ok(LogoutNotification {
+                client_id,
+                backchannel_logout_uri,
+                logout_token: signing_key.sign("logout+jwt", &claims)?,
+            })
+        })
+        .collect()
+}
+
+/// Posts the logout tokens to the clients, retrying with exponential backoff on network errors
+/// and server errors. Notifications that could not be delivered end up in the dead letter log
+/// under the `keyper::dead_letter` target.
+pub async fn deliver_logout_notifications(
+    notifications: Vec<LogoutNotification>,
+    attempts: u32,
+    retry_delay: time::Duration,
+) {
+    let http_client = match reqwest::Client::builder()
+        .timeout(BACKCHANNEL_LOGOUT_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+    {
+        Ok(http_client) => http_client,
+        Err(error) => {
+            error!("Could not create HTTP client for back-channel logout: {error}");
+            return;
+        }
+    };
+
+    for notification in notifications {
+        if let Err(error) =
+            deliver_logout_notification(&http_client, &notification, attempts, retry_delay).await
+        {
+            error!(
+                target: "keyper::dead_letter",
+                client_id = notification.client_id,
+                backchannel_logout_uri = notification.backchannel_logout_uri,
+                "Could not deliver logout token: {error}"
+            );
+        }
+    }
+}
+
+async fn deliver_logout_notification(
+    http_client: &reqwest::Client,
+    notification: &LogoutNotification,
+    attempts: u32,
+    retry_delay: time::Duration,
+) -> Result<()> {
+    let mut delay = retry_delay;
+    for attempt in 1..=attempts {
+        let result = http_client
+            .post(&notification.backchannel_logout_uri)
+            .form(&[("logout_token", &notification.logout_token)])
+            .send()
+            .await;
+
+        let error = match result {
+            Ok(response) if response.status().is_success() => return Ok(()),
+            // The client rejected the logout token, sending it again will not change that
+            Ok(response) if response.status().is_client_error() => {
+                return Err(anyhow!("Client responded with {}", response.status()));
+            }
+            Ok(response) => anyhow!("Client responded with {}", response.status()),
+            Err(error) => anyhow!(error),
+        };
+
+        if attempt == attempts {
+            return Err(error);
+        }
+        warn!(
+            "Back-channel logout of client {} failed at attempt {attempt}: {error}",
+            notification.client_id
+        );
+        tokio::time::sleep(delay).await;
+        delay *= 2;
+    }
+
+    Err(anyhow!("No delivery attempted"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time,
+    };
+
+    use axum::{Form, Router, extract::State, http::StatusCode, routing::post};
+    use chrono::{Duration, Utc};
+    use serde_json::json;
+    use tokio::net::TcpListener;
+
+    use crate::{
+        core::{
+            authentication::Session,
+            jose::{SigningKey, verify},
+            logout::{
+                BACKCHANNEL_LOGOUT_EVENT, LogoutError, LogoutNotification, LogoutRequest,
+                deliver_logout_notifications, logout_notifications, validate_logout_request,
+            },
+            token::TokenSettings,
+        },
+        repository::{client::MapClientRepository, key::MapKeyRepository},
+    };
+
+    /// Logout tokens received by the stand-in client, which fails the first `failures` requests
+    #[derive(Default)]
+    struct Receiver {
+        failures: Mutex<u32>,
+        status_code: Option<StatusCode>,
+        logout_tokens: Mutex<Vec<String>>,
+    }
+
+    async fn receive_logout_token(
+        State(receiver): State<Arc<Receiver>>,
+        Form(form): Form<Vec<(String, String)>>,
+    ) -> StatusCode {
+        {
+            let mut failures = receiver.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return receiver
+                    .status_code
+                    .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+            }
+        }
+
+        let mut logout_tokens = receiver.logout_tokens.lock().unwrap();
+        logout_tokens.extend(
+            form.into_iter()
+                .filter(|(name, _)| name == "logout_token")
+                .map(|(_, value)| value),
+        );
+        StatusCode::OK
+    }
+
+    async fn serve_receiver(receiver: Arc<Receiver>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Router::new()
+            .route("/backchannel_logout", post(receive_logout_token))
+            .with_state(receiver);
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        format!("http://{addr}/backchannel_logout")
+    }
+
+    fn create_notification(backchannel_logout_uri: &str) -> LogoutNotification {
+        LogoutNotification {
+            client_id: "s6BhdRkqt3".to_string(),
+            backchannel_logout_uri: backchannel_logout_uri.to_string(),
+            logout_token: "eyJhbGciOiJFZERTQSJ9.e30.c2lnbmF0dXJl".to_string(),
+        }
+    }
+
+    fn create_client_store() -> MapClientRepository {
+        MapClientRepository::try_from_toml(
+            r#"
+            [s6BhdRkqt3]
+            client_type = "public"
+            redirect_uris = ["https://client.example.com/cb"]
+            post_logout_redirect_uris = ["https://client.example.com/logged-out?lang=en"]
+            name = "Example Client"
+
+            [other]
+            client_type = "public"
+            redirect_uris = []
+            name = "Other Client"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn create_token_settings() -> TokenSettings {
+        TokenSettings {
+            issuer: "https://keyper.example.com".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn create_id_token(signing_key: &SigningKey, issuer: &str) -> String {
+        let now = Utc::now().timestamp();
+        signing_key
+            .sign(
+                "JWT",
+                &json!({
+                    "iss": issuer,
+                    "sub": "alice",
+                    "aud": "s6BhdRkqt3",
+                    "iat": now - 7200,
+                    "exp": now - 3600,
+                }),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_validate_logout_request() {
+        let signing_key = SigningKey::from_pem(include_str!("testdata/ed25519.pem")).unwrap();
+        let key_store = MapKeyRepository::from_signing_keys(vec![signing_key.clone()]);
+        let client_store = create_client_store();
+        let token_settings = create_token_settings();
+
+        let logout = validate_logout_request(
+            &LogoutRequest {
+                id_token_hint: Some(create_id_token(&signing_key, "https://keyper.example.com")),
+                post_logout_redirect_uri: Some(
+                    "https://client.example.com/logged-out?lang=en".to_string(),
+                ),
+                state: Some("af0ifjsldkj".to_string()),
+                ..Default::default()
+            },
+            &token_settings,
+            &client_store,
+            &key_store,
+        )
+        .unwrap();
+        assert_eq!(logout.client.unwrap().id, "s6BhdRkqt3");
+        assert_eq!(
+            logout.redirect_uri,
+            Some("https://client.example.com/logged-out?lang=en&state=af0ifjsldkj".to_string())
+        );
+
+        let logout = validate_logout_request(
+            &LogoutRequest::default(),
+            &token_settings,
+            &client_store,
+            &key_store,
+        )
+        .unwrap();
+        assert!(logout.client.is_none());
+        assert!(logout.redirect_uri.is_none());
+    }
+
+    #[test]
+    fn test_validate_logout_request_errors() {
+        let signing_key = SigningKey::from_pem(include_str!("testdata/ed25519.pem")).unwrap();
+        let key_store = MapKeyRepository::from_signing_keys(vec![signing_key.clone()]);
+        let other_key = SigningKey::from_pem(include_str!("testdata/p256.pem")).unwrap();
+        let id_token = create_id_token(&signing_key, "https://keyper.example.com");
+
+        for (logout_request, expected) in [
+            (
+                LogoutRequest {
+                    id_token_hint: Some(create_id_token(&other_key, "https://keyper.example.com")),
+                    ..Default::default()
+                },
+                LogoutError::InvalidIdTokenHint,
+            ),
+            (
+                LogoutRequest {
+                    id_token_hint: Some(create_id_token(&signing_key, "https://evil.example.com")),
+                    ..Default::default()
+                },
+                LogoutError::InvalidIdTokenHint,
+            ),
+            (
+                LogoutRequest {
+                    id_token_hint: Some(id_token.clone()),
+                    client_id: Some("other".to_string()),
+                    ..Default::default()
+                },
+                LogoutError::ClientMismatch,
+            ),
+            (
+                LogoutRequest {
+                    client_id: Some("unknown".to_string()),
+                    ..Default::default()
+                },
+                LogoutError::UnknownClient,
+            ),
+            (
+                LogoutRequest {
+                    post_logout_redirect_uri: Some(
+                        "https://client.example.com/logged-out?lang=en".to_string(),
+                    ),
+                    ..Default::default()
+                },
+                LogoutError::MissingClient,
+            ),
+            (
+                LogoutRequest {
+                    id_token_hint: Some(id_token),
+                    post_logout_redirect_uri: Some("https://evil.example.com".to_string()),
+                    ..Default::default()
+                },
+                LogoutError::InvalidPostLogoutRedirectUri,
+            ),
+        ] {
+            let error = validate_logout_request(
+                &logout_request,
+                &create_token_settings(),
+                &create_client_store(),
+                &key_store,
+            )
+            .unwrap_err();
+            assert_eq!(error, expected);
+        }
+    }
+
+    #[test]
+    fn test_logout_notifications() {
+        let signing_key = SigningKey::from_pem(include_str!("testdata/ed25519.pem")).unwrap();
+        let key_store = MapKeyRepository::from_signing_keys(vec![signing_key.clone()]);
+        let client_store = MapClientRepository::try_from_toml(
+            r#"
+            [s6BhdRkqt3]
+            client_type = "public"
+            redirect_uris = []
+            backchannel_logout_uri = "https://client.example.com/backchannel_logout"
+            name = "Example Client"
+
+            [other]
+            client_type = "public"
+            redirect_uris = []
+            name = "Other Client"
+            "#,
+        )
+        .unwrap();
+        let authenticated = Utc::now();
+        let session = Session {
+            id: "foobar".to_string(),
+            sid: "08a5019c".to_string(),
+            csrf_token: "Ks7mT2pQ".to_string(),
+            failed_user_codes: 0,
+            owner: "alice".to_string(),
+            clients: vec!["s6BhdRkqt3".to_string(), "other".to_string()],
+            authenticated,
+            expires: authenticated + Duration::seconds(3600),
+        };
+
+        let notifications = logout_notifications(
+            &session,
+            &create_token_settings(),
+            &client_store,
+            &key_store,
+        )
+        .unwrap();
+        assert_eq!(notifications.len(), 1);
+        assert_eq!(
+            notifications[0].backchannel_logout_uri,
+            "https://client.example.com/backchannel_logout"
+        );
+
+        let (header, claims) = verify(&signing_key, &notifications[0].logout_token);
+        assert_eq!(header["typ"], "logout+jwt");
+        assert_eq!(claims["iss"], "https://keyper.example.com");
+        assert_eq!(claims["aud"], "s6BhdRkqt3");
+        assert_eq!(claims["sub"], "alice");
+        assert_eq!(claims["sid"], "08a5019c");
+        assert_eq!(claims["events"][BACKCHANNEL_LOGOUT_EVENT], json!({}));
+        assert!(claims.get("nonce").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_deliver_logout_notifications() {
+        let receiver = Arc::new(Receiver {
+            failures: Mutex::new(2),
+            ..Default::default()
+        });
+        let backchannel_logout_uri = serve_receiver(receiver.clone()).await;
+
+        deliver_logout_notifications(
+            vec![create_notification(&backchannel_logout_uri)],
+            3,
+            time::Duration::from_millis(10),
+        )
+        .await;
+        assert_eq!(
+            *receiver.logout_tokens.lock().unwrap(),
+            vec!["eyJhbGciOiJFZERTQSJ9.e30.c2lnbmF0dXJl"]
+        );
+        assert_eq!(*receiver.failures.lock().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_deliver_logout_notifications_gives_up() {
+        let receiver = Arc::new(Receiver {
+            failures: Mutex::new(5),
+            ..Default::default()
+        });
+        let backchannel_logout_uri = serve_receiver(receiver.clone()).await;
+
+        deliver_logout_notifications(
+            vec![create_notification(&backchannel_logout_uri)],
+            3,
+            time::Duration::from_millis(10),
+        )
+        .await;
+        assert!(receiver.logout_tokens.lock().unwrap().is_empty());
+        assert_eq!(*receiver.failures.lock().unwrap(), 2);
+
+        let receiver = Arc::new(Receiver {
+            failures: Mutex::new(5),
+            status_code: Some(StatusCode::BAD_REQUEST),
+            ..Default::default()
+        });
+        let backchannel_logout_uri = serve_receiver(receiver.clone()).await;
+
+        deliver_logout_notifications(
+            vec![create_notification(&backchannel_logout_uri)],
+            3,
+            time::Duration::from_millis(10),
+        )
+        .await;
+        assert_eq!(*receiver.failures.lock().unwrap(), 4);
+    }
+}
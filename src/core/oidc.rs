@@ -0,0 +1,369 @@
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use tracing::error;
+
+use crate::core::{
+    authorization::AuthorizationCode,
+    jose::ReceivedJwsHeader,
+    key::KeyRepository,
+    token::{
+        AccessTokenError, AccessTokenErrorResponse, AuthorizationRepository, Owner,
+        OwnerRepository, TokenSettings,
+    },
+};
+
+pub const OPENID_SCOPE: &str = "openid";
+/// Scopes that only release claims about the authenticated owner, which any client may request
+/// without registering them
+pub const OWNER_CLAIM_SCOPES: [&str; 3] = [OPENID_SCOPE, "profile", "email"];
+pub const ID_TOKEN_TTL: i64 = 3600;
+/// Authentication context class of a password login, the only way owners authenticate
+pub const PASSWORD_ACR: &str = "urn:keyper:acr:password";
+/// Authentication method reference of a password login (RFC 8176 section 2)
+pub const PASSWORD_AMR: &str = "pwd";
+
+/// Claims about the resource owner, released according to the granted scopes
+/// (OpenID Connect Core section 5.4)
+#[derive(Serialize, Default, Debug)]
+pub struct OwnerClaims {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+impl OwnerClaims {
+    pub fn new(owner: &Owner, scopes: &[String]) -> Self {
+        let granted = |scope: &str| scopes.iter().any(|granted| granted == scope);
+
+        Self {
+            preferred_username: granted("profile").then(|| owner.name.clone()),
+            email: granted("email").then(|| owner.email.clone()),
+        }
+    }
+}
+
+/// ID token claims (OpenID Connect Core section 2)
+#[derive(Serialize, Debug)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub aud: String,
+    pub exp: i64,
+    pub iat: i64,
+    pub auth_time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonce: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    pub at_hash: String,
+    pub acr: String,
+    pub amr: Vec<String>,
+    #[serde(flatten)]
+    pub owner_claims: OwnerClaims,
+}
+
+/// UserInfo response (OpenID Connect Core section 5.3.2)
+#[derive(Serialize, Debug)]
+pub struct UserInfoResponse {
+    pub sub: String,
+    #[serde(flatten)]
+    pub owner_claims: OwnerClaims,
+}
+
+/// Errors of requests authenticated with a bearer token (RFC 6750 section 3.1) or a DPoP-bound
+/// token (RFC 9449 section 7.1)
+#[derive(Serialize, PartialEq, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BearerTokenError {
+    InvalidRequest,
+    InvalidToken,
+    InsufficientScope,
+    InvalidDpopProof,
+    /// Carries the nonce the client has to put into its next proof
+    UseDpopNonce(String),
+}
+
+/// Returns the claims about the owner an access token was issued for. The token must have been
+/// granted the `openid` scope, further claims are released according to the other scopes.
+/// DPoP-bound tokens are only accepted along with a proof for their key, given as `jkt`.
+pub fn userinfo<R: AuthorizationRepository, O: OwnerRepository>(
+    access_token: &str,
+    jkt: Option<&str>,
+    authorization_store: &R,
+    owner_store: &O,
+) -> Result<UserInfoResponse, BearerTokenError> {
+    let Some(authorization) =
+        authorization_store
+            .read_authorization(access_token)
+            .filter(|authorization| {
+                authorization.expires > Utc::now() && authorization.jkt.as_deref() == jkt
+            })
+    else {
+        return Err(BearerTokenError::InvalidToken);
+    };
+
+    if !authorization
+        .scopes
+        .iter()
+        .any(|scope| scope == OPENID_SCOPE)
+    {
+        return Err(BearerTokenError::InsufficientScope);
+    }
+
+    let Some(owner) = authorization
+        .owner
+        .and_then(|owner| owner_store.read_owner(&owner))
+    else {
+        return Err(BearerTokenError::InvalidToken);
+    };
+
+    Ok(UserInfoResponse {
+        sub: owner.name.clone(),
+        owner_claims: OwnerClaims::new(&owner, &authorization.scopes),
+    })
+}
+
+/// Issues the ID token returned alongside the access token for an authorization code that was
+/// granted with the `openid` scope
+pub fn id_token<O: OwnerRepository, K: KeyRepository>(
+    authorization_code: &AuthorizationCode,
+    access_token: &str,
+    token_settings: &TokenSettings,
+    owner_store: &O,
+    key_store: &K,
+) -> Result<String, AccessTokenErrorResponse> {
+    let Some(owner) = owner_store.read_owner(&authorization_code.owner) else {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidGrant,
+        ));
+    };
+
+    let Some(signing_key) = key_store.read_signing_key() else {
+        error!("No signing key available for ID tokens");
+        return Err(AccessTokenErrorResponse::new(AccessTokenError::ServerError));
+    };
+
+    let now = Utc::now();
+    let claims = IdTokenClaims {
+        iss: token_settings.issuer.clone(),
+        sub: owner.name.clone(),
+        aud: authorization_code.client_id.clone(),
+        exp: (now + Duration::seconds(ID_TOKEN_TTL)).timestamp(),
+        iat: now.timestamp(),
+        auth_time: authorization_code.auth_time.timestamp(),
+        nonce: authorization_code.nonce.clone(),
+        sid: authorization_code.sid.clone(),
+        at_hash: signing_key.left_hash(access_token),
+        acr: PASSWORD_ACR.to_string(),
+        amr: vec![PASSWORD_AMR.to_string()],
+        owner_claims: OwnerClaims::new(&owner, &authorization_code.scopes),
+    };
+
+    signing_key.sign("JWT", &claims).map_err(|error| {
+        error!("Could not sign ID token: {error}");
+        AccessTokenErrorResponse::new(AccessTokenError::ServerError)
+    })
+}
+
+/// Verifies an ID token this server issued earlier and returns its claims. Clients pass them back
+/// as hints about the owner, so expired ID tokens are accepted.
+pub fn verify_id_token_hint<K: KeyRepository>(
+    id_token_hint: &str,
+    token_settings: &TokenSettings,
+    key_store: &K,
+) -> Option<Value> {
+    let header = ReceivedJwsHeader::decode(id_token_hint).ok()?;
+    let claims = key_store
+        .read_keys()
+        .into_iter()
+        .find(|key| header.kid.as_deref() == Some(key.signing_key.kid.as_str()))
+        .and_then(|key| key.signing_key.verify(id_token_hint).ok())?;
+
+    (claims["iss"] == token_settings.issuer.as_str()).then_some(claims)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        core::{
+            authorization::{AuthorizationCode, CodeChallengeMethod},
+            jose::{SigningKey, verify},
+            oidc::{BearerTokenError, id_token, userinfo},
+            token::{AccessTokenError, Authorization, AuthorizationRepository, TokenSettings},
+        },
+        repository::{
+            authorization::MapAuthorizationRepository, key::MapKeyRepository,
+            owner::MapOwnerRepository,
+        },
+    };
+
+    fn create_authorization_code(owner: &str, scopes: &[&str]) -> AuthorizationCode {
+        let auth_time = Utc::now() - Duration::seconds(60);
+        AuthorizationCode {
+            code: "foobar".to_string(),
+            client_id: "s6BhdRkqt3".to_string(),
+            redirect_uri: Some("https://client.example.com/cb".to_string()),
+            scopes: scopes.iter().map(|scope| scope.to_string()).collect(),
+            owner: owner.to_string(),
+            code_challenge: None,
+            code_challenge_method: CodeChallengeMethod::Plain,
+            nonce: Some("n-0S6_WzA2Mj".to_string()),
+            auth_time,
+            sid: Some("08a5019c".to_string()),
+            expires: auth_time + Duration::seconds(600),
+        }
+    }
+
+    fn create_owner_store() -> MapOwnerRepository {
+        MapOwnerRepository::try_from_toml(
+            r#"
+            [alice]
+            email = "alice@example.com"
+            hash = "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaA"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_id_token() {
+        let signing_key = SigningKey::from_pem(include_str!("testdata/rsa.pem")).unwrap();
+        let key_store = MapKeyRepository::from_signing_keys(vec![signing_key.clone()]);
+        let token_settings = TokenSettings {
+            issuer: "https://keyper.example.com".to_string(),
+            ..Default::default()
+        };
+        let authorization_code = create_authorization_code("alice", &["openid", "email"]);
+
+        let id_token = id_token(
+            &authorization_code,
+            "jHkWEdUXMU1BwAsC4vtUsZwnNvTIxEl0z9K3vx5KF0Y",
+            &token_settings,
+            &create_owner_store(),
+            &key_store,
+        )
+        .unwrap();
+
+        let (header, claims) = verify(&signing_key, &id_token);
+        assert_eq!(header["alg"], "RS256");
+        assert_eq!(claims["iss"], "https://keyper.example.com");
+        assert_eq!(claims["sub"], "alice");
+        assert_eq!(claims["aud"], "s6BhdRkqt3");
+        assert_eq!(claims["nonce"], "n-0S6_WzA2Mj");
+        assert_eq!(claims["sid"], "08a5019c");
+        assert_eq!(claims["at_hash"], "77QmUPtjPfzWtF2AnpK9RQ");
+        assert_eq!(
+            claims["auth_time"],
+            authorization_code.auth_time.timestamp()
+        );
+        assert_eq!(claims["amr"][0], "pwd");
+        assert!(claims["acr"].is_string());
+        assert_eq!(claims["email"], "alice@example.com");
+        assert!(claims.get("preferred_username").is_none());
+    }
+
+    #[test]
+    fn test_userinfo() {
+        let authorization_store = MapAuthorizationRepository::default();
+        let now = Utc::now();
+        for (access_token, scopes, owner, created) in [
+            ("profile", vec!["openid", "profile"], Some("alice"), now),
+            ("email", vec!["openid", "email"], Some("alice"), now),
+            ("api", vec!["read"], Some("alice"), now),
+            ("client", vec!["openid"], None, now),
+            (
+                "expired",
+                vec!["openid"],
+                Some("alice"),
+                now - Duration::seconds(7200),
+            ),
+        ] {
+            authorization_store
+                .create_authorization(Authorization {
+                    access_token: access_token.to_string(),
+                    client_id: "s6BhdRkqt3".to_string(),
+                    scopes: scopes.into_iter().map(str::to_string).collect(),
+                    owner: owner.map(str::to_string),
+                    created,
+                    expires: created + Duration::seconds(3600),
+                    refresh_token: None,
+                    jkt: None,
+                })
+                .unwrap();
+        }
+        let owner_store = create_owner_store();
+
+        let response = userinfo("profile", None, &authorization_store, &owner_store).unwrap();
+        assert_eq!(response.sub, "alice");
+        assert_eq!(
+            response.owner_claims.preferred_username,
+            Some("alice".to_string())
+        );
+        assert_eq!(response.owner_claims.email, None);
+
+        let response = userinfo("email", None, &authorization_store, &owner_store).unwrap();
+        assert_eq!(
+            response.owner_claims.email,
+            Some("alice@example.com".to_string())
+        );
+        assert_eq!(response.owner_claims.preferred_username, None);
+
+        for (access_token, expected) in [
+            ("api", BearerTokenError::InsufficientScope),
+            ("client", BearerTokenError::InvalidToken),
+            ("expired", BearerTokenError::InvalidToken),
+            ("unknown", BearerTokenError::InvalidToken),
+        ] {
+            let error =
+                userinfo(access_token, None, &authorization_store, &owner_store).unwrap_err();
+            assert_eq!(error, expected, "{access_token}");
+        }
+
+        authorization_store
+            .create_authorization(Authorization {
+                access_token: "bound".to_string(),
+                client_id: "s6BhdRkqt3".to_string(),
+                scopes: vec!["openid".to_string()],
+                owner: Some("alice".to_string()),
+                created: now,
+                expires: now + Duration::seconds(3600),
+                refresh_token: None,
+                jkt: Some("0ZcOCORZNYy-DWpqq30jZyJGHTN0d2HglBV3uiguA4I".to_string()),
+            })
+            .unwrap();
+        assert_eq!(
+            userinfo("bound", None, &authorization_store, &owner_store).unwrap_err(),
+            BearerTokenError::InvalidToken
+        );
+        assert!(
+            userinfo(
+                "bound",
+                Some("0ZcOCORZNYy-DWpqq30jZyJGHTN0d2HglBV3uiguA4I"),
+                &authorization_store,
+                &owner_store
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_id_token_unknown_owner() {
+        let key_store = MapKeyRepository::from_signing_keys(vec![
+            SigningKey::from_pem(include_str!("testdata/ed25519.pem")).unwrap(),
+        ]);
+
+        let error = id_token(
+            &create_authorization_code("bob", &["openid"]),
+            "foobar",
+            &TokenSettings::default(),
+            &create_owner_store(),
+            &key_store,
+        )
+        .unwrap_err();
+        assert_eq!(error.error, AccessTokenError::InvalidGrant);
+    }
+}
@@ -0,0 +1,275 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    authorization::{
+        AuthorizationError, AuthorizationErrorResponse, AuthorizationFailureResponse,
+        AuthorizationRequest, ClientRepository, validate_authorization_request,
+    },
+    token::{AccessTokenErrorResponse, ClientCredentials, authenticate_client, generate_token},
+};
+
+/// Long enough for the owner to log in between the redirect to the authorization endpoint and
+/// the resumed request
+pub const PUSHED_REQUEST_TTL: i64 = 300;
+pub const REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+pub trait PushedRequestRepository {
+    fn create_pushed_request(&self, pushed_request: PushedRequest) -> Result<()>;
+    fn read_pushed_request(&self, request_uri: &str) -> Option<PushedRequest>;
+    fn delete_pushed_request(&self, request_uri: &str) -> Option<PushedRequest>;
+}
+
+#[derive(Clone, Debug)]
+pub struct PushedRequest {
+    pub request_uri: String,
+    pub auth_request: AuthorizationRequest,
+    pub expires: DateTime<Utc>,
+}
+
+/// Parameters referencing a pushed request at the authorization endpoint (RFC 9126 section 4)
+#[derive(Deserialize, Debug)]
+pub struct RequestUriReference {
+    pub client_id: String,
+    pub request_uri: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PushedAuthorizationResponse {
+    pub request_uri: String,
+    pub expires_in: i64,
+}
+
+/// The PAR endpoint either fails to authenticate the client like the token endpoint does, or
+/// rejects the request with the error the client would otherwise receive at its redirect URI
+/// (RFC 9126 section 2.3)
+#[derive(Debug)]
+pub enum PushedAuthorizationErrorResponse {
+    Client(AccessTokenErrorResponse),
+    Request(AuthorizationErrorResponse),
+}
+
+/// Validates the authorization request of the authenticated client and stores it under a fresh
+/// request URI (RFC 9126 section 2)
+pub fn push_authorization_request<C: ClientRepository, P: PushedRequestRepository>(
+    mut auth_request: AuthorizationRequest,
+    client_credentials: ClientCredentials,
+    client_store: &C,
+    pushed_request_store: &P,
+) -> Result<PushedAuthorizationResponse, PushedAuthorizationErrorResponse> {
+    authenticate_client(client_credentials, client_store)
+        .map_err(PushedAuthorizationErrorResponse::Client)?;
+
+    auth_request.pushed = true;
+    validate_authorization_request(&auth_request, client_store).map_err(
+        |AuthorizationFailureResponse(mut auth_error_response, _)| {
+            auth_error_response.state = None;
+            PushedAuthorizationErrorResponse::Request(auth_error_response)
+        },
+    )?;
+
+    let request_uri = store_pushed_request(auth_request, pushed_request_store).map_err(|_| {
+        PushedAuthorizationErrorResponse::Request(AuthorizationErrorResponse::new(
+            AuthorizationError::ServerError,
+            None,
+        ))
+    })?;
+
+    Ok(PushedAuthorizationResponse {
+        request_uri,
+        expires_in: PUSHED_REQUEST_TTL,
+    })
+}
+
+/// Stores the authorization request under a fresh request URI. Besides pushed requests, this
+/// keeps any request around while the owner logs in.
+pub fn store_pushed_request<P: PushedRequestRepository>(
+    auth_request: AuthorizationRequest,
+    pushed_request_store: &P,
+) -> Result<String> {
+    let request_uri = format!("{REQUEST_URI_PREFIX}{}", generate_token());
+    pushed_request_store.create_pushed_request(PushedRequest {
+        request_uri: request_uri.clone(),
+        auth_request,
+        expires: Utc::now() + Duration::seconds(PUSHED_REQUEST_TTL),
+    })?;
+
+    Ok(request_uri)
+}
+
+/// Looks up the request the client pushed before. Request URIs of other clients are treated like
+/// unknown ones.
+pub fn pushed_authorization_request<P: PushedRequestRepository>(
+    request_uri_reference: &RequestUriReference,
+    pushed_request_store: &P,
+) -> Result<AuthorizationRequest, AuthorizationErrorResponse> {
+    pushed_request_store
+        .read_pushed_request(&request_uri_reference.request_uri)
+        .filter(|pushed_request| {
+            pushed_request.expires > Utc::now()
+                && pushed_request.auth_request.client_id == request_uri_reference.client_id
+        })
+        .map(|pushed_request| pushed_request.auth_request)
+        .ok_or_else(|| {
+            let mut auth_error_response =
+                AuthorizationErrorResponse::new(AuthorizationError::InvalidRequestUri, None);
+            auth_error_response.error_description =
+                Some("request_uri is unknown or expired".to_string());
+            auth_error_response
+        })
+}
+
+/// Records that the owner was sent to log in for the pushed request, see
+/// `AuthorizationRequest::login_requested`
+pub fn request_login_for_pushed_request<P: PushedRequestRepository>(
+    request_uri: &str,
+    pushed_request_store: &P,
+) -> Result<()> {
+    let pushed_request = pushed_request_store
+        .read_pushed_request(request_uri)
+        .ok_or_else(|| anyhow!("Pushed request does not exist"))?;
+
+    pushed_request_store.create_pushed_request(PushedRequest {
+        auth_request: AuthorizationRequest {
+            login_requested: Some(Utc::now()),
+            ..pushed_request.auth_request
+        },
+        ..pushed_request
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{
+            authorization::{
+                AuthorizationError, AuthorizationRequest, ResponseType, TokenEndpointAuthMethod,
+            },
+            pushed_authorization::{
+                PushedAuthorizationErrorResponse, PushedRequestRepository, RequestUriReference,
+                push_authorization_request, pushed_authorization_request,
+                request_login_for_pushed_request,
+            },
+            token::{AccessTokenError, ClientCredentials},
+        },
+        repository::{client::TestClientRepository, pushed_request::MapPushedRequestRepository},
+    };
+
+    fn create_auth_request() -> AuthorizationRequest {
+        AuthorizationRequest {
+            response_type: ResponseType::Code,
+            client_id: "s6BhdRkqt3".to_string(),
+            redirect_uri: Some("https://client.example.com/cb".to_string()),
+            state: Some("xyz".to_string()),
+            prompt: Some("login consent".to_string()),
+            max_age: Some(0),
+            ..Default::default()
+        }
+    }
+
+    fn create_client_credentials(client_id: &str) -> ClientCredentials {
+        ClientCredentials {
+            client_id: client_id.to_string(),
+            client_secret: None,
+            method: TokenEndpointAuthMethod::None,
+        }
+    }
+
+    #[test]
+    fn test_push_authorization_request() {
+        let client_store = TestClientRepository {
+            client_ids: vec!["s6BhdRkqt3".to_string()],
+        };
+        let pushed_request_store = MapPushedRequestRepository::default();
+
+        let response = push_authorization_request(
+            create_auth_request(),
+            create_client_credentials("s6BhdRkqt3"),
+            &client_store,
+            &pushed_request_store,
+        )
+        .unwrap();
+        assert!(
+            response
+                .request_uri
+                .starts_with("urn:ietf:params:oauth:request_uri:")
+        );
+
+        let reference = RequestUriReference {
+            client_id: "s6BhdRkqt3".to_string(),
+            request_uri: response.request_uri.clone(),
+        };
+        let auth_request = pushed_authorization_request(&reference, &pushed_request_store).unwrap();
+        assert!(auth_request.pushed);
+        assert_eq!(auth_request.state, Some("xyz".to_string()));
+
+        request_login_for_pushed_request(&response.request_uri, &pushed_request_store).unwrap();
+        let auth_request = pushed_authorization_request(&reference, &pushed_request_store).unwrap();
+        assert!(auth_request.login_requested.is_some());
+        assert_eq!(auth_request.prompt, Some("login consent".to_string()));
+        assert_eq!(auth_request.max_age, Some(0));
+
+        let error_response = pushed_authorization_request(
+            &RequestUriReference {
+                client_id: "other".to_string(),
+                ..reference
+            },
+            &pushed_request_store,
+        )
+        .unwrap_err();
+        assert_eq!(error_response.error, AuthorizationError::InvalidRequestUri);
+
+        pushed_request_store.delete_pushed_request(&response.request_uri);
+        assert!(
+            pushed_authorization_request(
+                &RequestUriReference {
+                    client_id: "s6BhdRkqt3".to_string(),
+                    request_uri: response.request_uri,
+                },
+                &pushed_request_store
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_push_authorization_request_invalid() {
+        let client_store = TestClientRepository {
+            client_ids: vec!["s6BhdRkqt3".to_string()],
+        };
+        let pushed_request_store = MapPushedRequestRepository::default();
+
+        let Err(PushedAuthorizationErrorResponse::Client(error_response)) =
+            push_authorization_request(
+                create_auth_request(),
+                create_client_credentials("unknown"),
+                &client_store,
+                &pushed_request_store,
+            )
+        else {
+            panic!("Unknown client was accepted");
+        };
+        assert_eq!(error_response.error, AccessTokenError::InvalidClient);
+
+        let Err(PushedAuthorizationErrorResponse::Request(error_response)) =
+            push_authorization_request(
+                AuthorizationRequest {
+                    response_type: ResponseType::Token,
+                    ..create_auth_request()
+                },
+                create_client_credentials("s6BhdRkqt3"),
+                &client_store,
+                &pushed_request_store,
+            )
+        else {
+            panic!("Unsupported response type was accepted");
+        };
+        assert_eq!(
+            error_response.error,
+            AuthorizationError::UnsupportedResponseType
+        );
+        assert!(error_response.state.is_none());
+        assert!(pushed_request_store.data.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,864 @@
+use std::net::Ipv4Addr;
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use url::{Host, Url};
+
+use crate::core::{
+    authorization::{
+        AccessTokenFormat, Client, ClientRepository, ClientType, TokenEndpointAuthMethod,
+        is_web_redirect_uri,
+    },
+    key::JwkSet,
+    token::{GrantType, generate_token, hash_secret, verify_hash},
+};
+
+/// Client metadata a client registers with (RFC 7591 section 2). Unknown fields are ignored.
+#[derive(Deserialize, Serialize, Default, Clone, Debug)]
+pub struct ClientMetadata {
+    #[serde(default)]
+    pub redirect_uris: Vec<String>,
+    /// Grants the client is going to use, `authorization_code` if omitted. Only used to decide
+    /// whether the client needs redirect URIs, so it is not stored.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grant_types: Option<Vec<GrantType>>,
+    pub token_endpoint_auth_method: Option<TokenEndpointAuthMethod>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub post_logout_redirect_uris: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backchannel_logout_uri: Option<String>,
+    #[serde(default)]
+    pub require_pushed_authorization_requests: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwks: Option<JwkSet>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jwks_uri: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub request_uris: Vec<String>,
+    #[serde(default)]
+    pub dpop_bound_access_tokens: bool,
+}
+
+/// Replacement of the metadata of a registered client (RFC 7592 section 2.2)
+#[derive(Deserialize, Default, Debug)]
+pub struct ClientUpdateRequest {
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    #[serde(flatten)]
+    pub client_metadata: ClientMetadata,
+}
+
+/// Client information response (RFC 7591 section 3.2.1, RFC 7592 section 3). Only secret hashes
+/// are stored, so the client secret is returned once at registration, while the registration
+/// access token is echoed back on every request authorized with it.
+#[derive(Serialize, Debug)]
+pub struct ClientInformationResponse {
+    pub client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_secret_expires_at: Option<i64>,
+    pub registration_access_token: String,
+    pub registration_client_uri: String,
+    #[serde(flatten)]
+    pub client_metadata: ClientMetadata,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ClientRegistrationErrorResponse {
+    pub error: ClientRegistrationError,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_description: Option<String>,
+}
+
+impl ClientRegistrationErrorResponse {
+    pub fn new(error: ClientRegistrationError) -> Self {
+        Self {
+            error,
+            error_description: None,
+        }
+    }
+
+    fn with_description(error: ClientRegistrationError, error_description: String) -> Self {
+        Self {
+            error,
+            error_description: Some(error_description),
+        }
+    }
+}
+
+#[derive(Serialize, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ClientRegistrationError {
+    InvalidRedirectUri,
+    InvalidClientMetadata,
+    /// Missing or wrong initial or registration access token
+    InvalidToken,
+    ServerError,
+}
+
+/// Registers a new client (RFC 7591 section 3). Scopes are limited to the ones the server
+/// supports, since registered scopes are granted with the client credentials grant. Clients
+/// registered without initial access token are `self_registered` and cannot register scopes or
+/// the client credentials grant at all.
+pub fn register_client<C: ClientRepository>(
+    client_metadata: ClientMetadata,
+    self_registered: bool,
+    scopes_supported: &[String],
+    registration_endpoint: &str,
+    client_store: &C,
+) -> Result<ClientInformationResponse, ClientRegistrationErrorResponse> {
+    validate_client_metadata(&client_metadata, self_registered, scopes_supported)?;
+
+    let client_id = generate_token();
+    let registration_access_token = generate_token();
+    let token_endpoint_auth_method = client_metadata
+        .token_endpoint_auth_method
+        .unwrap_or(TokenEndpointAuthMethod::ClientSecretBasic);
+    let client_secret =
+        (token_endpoint_auth_method != TokenEndpointAuthMethod::None).then(generate_token);
+
+    let client = Client {
+        secret_hash: client_secret
+            .as_deref()
+            .map(hash_secret)
+            .transpose()
+            .map_err(server_error)?,
+        registration_access_token_hash: Some(
+            hash_secret(&registration_access_token).map_err(server_error)?,
+        ),
+        ..create_client(
+            &client_id,
+            &client_metadata,
+            token_endpoint_auth_method,
+            self_registered,
+        )
+    };
+    client_store.create_client(client).map_err(server_error)?;
+
+    Ok(ClientInformationResponse {
+        registration_client_uri: format!("{registration_endpoint}/{client_id}"),
+        client_id,
+        client_secret_expires_at: client_secret.as_ref().map(|_| 0),
+        client_secret,
+        registration_access_token,
+        client_metadata: ClientMetadata {
+            token_endpoint_auth_method: Some(token_endpoint_auth_method),
+            ..client_metadata
+        },
+    })
+}
+
+/// Returns the current registration of the client (RFC 7592 section 2.1)
+pub fn read_client_registration<C: ClientRepository>(
+    client_id: &str,
+    registration_access_token: &str,
+    registration_endpoint: &str,
+    client_store: &C,
+) -> Result<ClientInformationResponse, ClientRegistrationErrorResponse> {
+    let client = authorize_registration(client_id, registration_access_token, client_store)?;
+
+    Ok(client_information(
+        &client,
+        registration_access_token,
+        registration_endpoint,
+    ))
+}
+
+/// Replaces the metadata of the client (RFC 7592 section 2.2). The client keeps its secret and
+/// token endpoint authentication method, as changing either would require issuing new secrets.
+pub fn update_client_registration<C: ClientRepository>(
+    client_id: &str,
+    registration_access_token: &str,
+    update_request: ClientUpdateRequest,
+    scopes_supported: &[String],
+    registration_endpoint: &str,
+    client_store: &C,
+) -> Result<ClientInformationResponse, ClientRegistrationErrorResponse> {
+    let client = authorize_registration(client_id, registration_access_token, client_store)?;
+
+    if update_request.client_id != client.id {
+        return Err(ClientRegistrationErrorResponse::with_description(
+            ClientRegistrationError::InvalidClientMetadata,
+            "client_id does not match the registration".to_string(),
+        ));
+    }
+    let secret_matches = match (&update_request.client_secret, &client.secret_hash) {
+        (None, _) => true,
+        (Some(client_secret), Some(secret_hash)) => verify_hash(client_secret, secret_hash),
+        (Some(_), None) => false,
+    };
+    if !secret_matches {
+        return Err(ClientRegistrationErrorResponse::with_description(
+            ClientRegistrationError::InvalidClientMetadata,
+            "client_secret does not match the registration".to_string(),
+        ));
+    }
+    let client_metadata = update_request.client_metadata;
+    if client_metadata
+        .token_endpoint_auth_method
+        .is_some_and(|method| method != client.token_endpoint_auth_method)
+    {
+        return Err(ClientRegistrationErrorResponse::with_description(
+            ClientRegistrationError::InvalidClientMetadata,
+            "token_endpoint_auth_method cannot be changed".to_string(),
+        ));
+    }
+    validate_client_metadata(&client_metadata, client.self_registered, scopes_supported)?;
+
+    let client = Client {
+        secret_hash: client.secret_hash,
+        registration_access_token_hash: client.registration_access_token_hash,
+        ..create_client(
+            &client.id,
+            &client_metadata,
+            client.token_endpoint_auth_method,
+            client.self_registered,
+        )
+    };
+    client_store.update_client(client).map_err(server_error)?;
+
+    read_client_registration(
+        client_id,
+        registration_access_token,
+        registration_endpoint,
+        client_store,
+    )
+}
+
+/// Deregisters the client (RFC 7592 section 2.3)
+pub fn delete_client_registration<C: ClientRepository>(
+    client_id: &str,
+    registration_access_token: &str,
+    client_store: &C,
+) -> Result<(), ClientRegistrationErrorResponse> {
+    authorize_registration(client_id, registration_access_token, client_store)?;
+
+    client_store
+        .delete_client(client_id)
+        .map(|_| ())
+        .ok_or_else(|| ClientRegistrationErrorResponse::new(ClientRegistrationError::ServerError))
+}
+
+/// Reads the client the registration access token was issued for. Unknown clients are reported
+/// like invalid tokens to not reveal which clients exist (RFC 7592 section 3).
+fn authorize_registration<C: ClientRepository>(
+    client_id: &str,
+    registration_access_token: &str,
+    client_store: &C,
+) -> Result<Client, ClientRegistrationErrorResponse> {
+    client_store
+        .read_client(client_id)
+        .filter(|client| {
+            client
+                .registration_access_token_hash
+                .as_deref()
+                .is_some_and(|hash| verify_hash(registration_access_token, hash))
+        })
+        .ok_or_else(|| ClientRegistrationErrorResponse::new(ClientRegistrationError::InvalidToken))
+}
+
+fn validate_client_metadata(
+    client_metadata: &ClientMetadata,
+    self_registered: bool,
+    scopes_supported: &[String],
+) -> Result<(), ClientRegistrationErrorResponse> {
+    // Anyone may register without initial access token, so such clients must not obtain tokens
+    // without a resource owner or hold scopes beyond the OpenID Connect ones every client gets
+    if self_registered {
+        if client_metadata
+            .grant_types
+            .as_ref()
+            .is_some_and(|grant_types| grant_types.contains(&GrantType::ClientCredentials))
+        {
+            return Err(ClientRegistrationErrorResponse::with_description(
+                ClientRegistrationError::InvalidClientMetadata,
+                "client_credentials requires an initial access token".to_string(),
+            ));
+        }
+        if client_metadata
+            .scope
+            .as_ref()
+            .is_some_and(|scope| !scope.trim().is_empty())
+        {
+            return Err(ClientRegistrationErrorResponse::with_description(
+                ClientRegistrationError::InvalidClientMetadata,
+                "Registering scopes requires an initial access token".to_string(),
+            ));
+        }
+    }
+
+    // Clients without redirect URIs could otherwise send authorization responses anywhere
+    let redirect_based = client_metadata
+        .grant_types
+        .as_ref()
+        .is_none_or(|grant_types| grant_types.contains(&GrantType::AuthorizationCode));
+    if redirect_based && client_metadata.redirect_uris.is_empty() {
+        return Err(ClientRegistrationErrorResponse::with_description(
+            ClientRegistrationError::InvalidRedirectUri,
+            "redirect_uris is required for the authorization_code grant".to_string(),
+        ));
+    }
+
+    if let Some(redirect_uri) = client_metadata
+        .redirect_uris
+        .iter()
+        .find(|redirect_uri| !is_redirect_uri(redirect_uri))
+    {
+        return Err(ClientRegistrationErrorResponse::with_description(
+            ClientRegistrationError::InvalidRedirectUri,
+            format!("Invalid redirect URI {redirect_uri}"),
+        ));
+    }
+
+    if client_metadata.jwks.is_some() && client_metadata.jwks_uri.is_some() {
+        return Err(ClientRegistrationErrorResponse::with_description(
+            ClientRegistrationError::InvalidClientMetadata,
+            "jwks and jwks_uri must not be used together".to_string(),
+        ));
+    }
+
+    if let Some(uri) = client_metadata
+        .post_logout_redirect_uris
+        .iter()
+        .find(|uri| !is_redirect_uri(uri))
+    {
+        return Err(ClientRegistrationErrorResponse::with_description(
+            ClientRegistrationError::InvalidClientMetadata,
+            format!("Invalid URI {uri}"),
+        ));
+    }
+
+    if let Some(uri) = client_metadata
+        .backchannel_logout_uri
+        .iter()
+        .chain(&client_metadata.jwks_uri)
+        .chain(&client_metadata.request_uris)
+        .find(|uri| !is_public_https_uri(uri))
+    {
+        return Err(ClientRegistrationErrorResponse::with_description(
+            ClientRegistrationError::InvalidClientMetadata,
+            format!("Invalid URI {uri}, keyper only contacts public https URIs"),
+        ));
+    }
+
+    if let Some(scope) = client_metadata
+        .scope
+        .iter()
+        .flat_map(|scope| scope.split_whitespace())
+        .find(|scope| !scopes_supported.iter().any(|supported| supported == scope))
+    {
+        return Err(ClientRegistrationErrorResponse::with_description(
+            ClientRegistrationError::InvalidClientMetadata,
+            format!("Unsupported scope {scope}"),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Registered URIs are compared verbatim, so they have to be absolute and must not carry a
+/// fragment (RFC 6749 section 3.1.2). The user agent is sent there, which is only safe for web
+/// URIs.
+fn is_redirect_uri(uri: &str) -> bool {
+    is_web_redirect_uri(uri) && Url::parse(uri).is_ok_and(|url| url.fragment().is_none())
+}
+
+/// keyper fetches or posts to these URIs itself. Anyone may register them, so they must not
+/// reach hosts on the loopback interface or in private networks. Names are not resolved, which
+/// leaves operators to keep internal names out of the DNS keyper uses.
+fn is_public_https_uri(uri: &str) -> bool {
+    let Ok(url) = Url::parse(uri) else {
+        return false;
+    };
+    if url.scheme() != "https" || url.fragment().is_some() {
+        return false;
+    }
+
+    match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        Some(Host::Ipv4(address)) => is_public_ipv4(address),
+        Some(Host::Ipv6(address)) => match address.to_ipv4_mapped() {
+            Some(address) => is_public_ipv4(address),
+            None => {
+                !(address.is_loopback()
+                    || address.is_unspecified()
+                    || address.is_unique_local()
+                    || address.is_unicast_link_local())
+            }
+        },
+        None => false,
+    }
+}
+
+fn is_public_ipv4(address: Ipv4Addr) -> bool {
+    !(address.is_loopback()
+        || address.is_private()
+        || address.is_link_local()
+        || address.is_unspecified()
+        || address.is_broadcast()
+        // Carrier-grade NAT (RFC 6598) is private as well
+        || (address.octets()[0] == 100 && address.octets()[1] & 0xc0 == 64))
+}
+
+/// Public clients cannot keep a secret, so dynamically registered ones are required to use PKCE
+fn create_client(
+    client_id: &str,
+    client_metadata: &ClientMetadata,
+    token_endpoint_auth_method: TokenEndpointAuthMethod,
+    self_registered: bool,
+) -> Client {
+    let client_type = match token_endpoint_auth_method {
+        TokenEndpointAuthMethod::None => ClientType::Public,
+        _ => ClientType::Confidential,
+    };
+
+    Client {
+        id: client_id.to_string(),
+        require_pkce: client_type == ClientType::Public,
+        client_type,
+        redirect_uris: client_metadata.redirect_uris.clone(),
+        name: client_metadata
+            .client_name
+            .clone()
+            .unwrap_or_else(|| client_id.to_string()),
+        scopes: client_metadata
+            .scope
+            .iter()
+            .flat_map(|scope| scope.split_whitespace())
+            .map(str::to_string)
+            .collect(),
+        secret_hash: None,
+        token_endpoint_auth_method,
+        access_token_format: AccessTokenFormat::default(),
+        post_logout_redirect_uris: client_metadata.post_logout_redirect_uris.clone(),
+        backchannel_logout_uri: client_metadata.backchannel_logout_uri.clone(),
+        require_pushed_authorization_requests: client_metadata
+            .require_pushed_authorization_requests,
+        jwks: client_metadata.jwks.clone(),
+        jwks_uri: client_metadata.jwks_uri.clone(),
+        request_uris: client_metadata.request_uris.clone(),
+        allow_unsigned_request_objects: false,
+        dpop_bound_access_tokens: client_metadata.dpop_bound_access_tokens,
+        registration_access_token_hash: None,
+        self_registered,
+    }
+}
+
+fn client_information(
+    client: &Client,
+    registration_access_token: &str,
+    registration_endpoint: &str,
+) -> ClientInformationResponse {
+    ClientInformationResponse {
+        client_id: client.id.clone(),
+        client_secret: None,
+        client_secret_expires_at: None,
+        registration_access_token: registration_access_token.to_string(),
+        registration_client_uri: format!("{registration_endpoint}/{}", client.id),
+        client_metadata: ClientMetadata {
+            redirect_uris: client.redirect_uris.clone(),
+            grant_types: None,
+            token_endpoint_auth_method: Some(client.token_endpoint_auth_method),
+            client_name: Some(client.name.clone()),
+            scope: (!client.scopes.is_empty()).then(|| client.scopes.join(" ")),
+            post_logout_redirect_uris: client.post_logout_redirect_uris.clone(),
+            backchannel_logout_uri: client.backchannel_logout_uri.clone(),
+            require_pushed_authorization_requests: client.require_pushed_authorization_requests,
+            jwks: client.jwks.clone(),
+            jwks_uri: client.jwks_uri.clone(),
+            request_uris: client.request_uris.clone(),
+            dpop_bound_access_tokens: client.dpop_bound_access_tokens,
+        },
+    }
+}
+
+fn server_error(error: anyhow::Error) -> ClientRegistrationErrorResponse {
+    error!("Could not store client registration: {error}");
+    ClientRegistrationErrorResponse::new(ClientRegistrationError::ServerError)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        core::{
+            authorization::{ClientRepository, ClientType, TokenEndpointAuthMethod},
+            registration::{
+                ClientMetadata, ClientRegistrationError, ClientUpdateRequest,
+                delete_client_registration, read_client_registration, register_client,
+                update_client_registration,
+            },
+            token::{GrantType, verify_hash},
+        },
+        repository::client::MapClientRepository,
+    };
+
+    const REGISTRATION_ENDPOINT: &str = "https://keyper.example.com/register";
+
+    fn create_client_metadata() -> ClientMetadata {
+        ClientMetadata {
+            redirect_uris: vec!["https://client.example.com/cb".to_string()],
+            client_name: Some("Example Client".to_string()),
+            scope: Some("read".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_register_client() {
+        let client_store = MapClientRepository::default();
+        let scopes_supported = vec!["read".to_string(), "write".to_string()];
+
+        let response = register_client(
+            create_client_metadata(),
+            false,
+            &scopes_supported,
+            REGISTRATION_ENDPOINT,
+            &client_store,
+        )
+        .unwrap();
+        assert_eq!(
+            response.registration_client_uri,
+            format!("{REGISTRATION_ENDPOINT}/{}", response.client_id)
+        );
+        assert_eq!(response.client_secret_expires_at, Some(0));
+        assert_eq!(
+            response.client_metadata.token_endpoint_auth_method,
+            Some(TokenEndpointAuthMethod::ClientSecretBasic)
+        );
+
+        let client = client_store.read_client(&response.client_id).unwrap();
+        assert_eq!(client.client_type, ClientType::Confidential);
+        assert_eq!(client.name, "Example Client");
+        assert_eq!(client.scopes, vec!["read"]);
+        assert!(verify_hash(
+            &response.client_secret.unwrap(),
+            &client.secret_hash.unwrap()
+        ));
+
+        let response = register_client(
+            ClientMetadata {
+                token_endpoint_auth_method: Some(TokenEndpointAuthMethod::None),
+                ..create_client_metadata()
+            },
+            false,
+            &scopes_supported,
+            REGISTRATION_ENDPOINT,
+            &client_store,
+        )
+        .unwrap();
+        assert!(response.client_secret.is_none());
+
+        let client = client_store.read_client(&response.client_id).unwrap();
+        assert_eq!(client.client_type, ClientType::Public);
+        assert!(client.require_pkce);
+
+        let response = register_client(
+            ClientMetadata {
+                redirect_uris: Vec::new(),
+                grant_types: Some(vec![GrantType::ClientCredentials]),
+                ..create_client_metadata()
+            },
+            false,
+            &scopes_supported,
+            REGISTRATION_ENDPOINT,
+            &client_store,
+        )
+        .unwrap();
+        assert!(response.client_metadata.redirect_uris.is_empty());
+
+        let response = register_client(
+            ClientMetadata {
+                redirect_uris: vec!["http://127.0.0.1:8080/cb".to_string()],
+                backchannel_logout_uri: Some("https://client.example.com/logout".to_string()),
+                jwks_uri: Some("https://client.example.com/jwks.json".to_string()),
+                ..create_client_metadata()
+            },
+            false,
+            &scopes_supported,
+            REGISTRATION_ENDPOINT,
+            &client_store,
+        )
+        .unwrap();
+        assert_eq!(
+            response.client_metadata.redirect_uris,
+            vec!["http://127.0.0.1:8080/cb"]
+        );
+    }
+
+    #[test]
+    fn test_register_client_invalid_metadata() {
+        let client_store = MapClientRepository::default();
+        let scopes_supported = vec!["read".to_string()];
+
+        for (client_metadata, expected) in [
+            (
+                ClientMetadata {
+                    redirect_uris: vec!["/cb".to_string()],
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidRedirectUri,
+            ),
+            (
+                ClientMetadata {
+                    redirect_uris: vec!["https://client.example.com/cb#top".to_string()],
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidRedirectUri,
+            ),
+            (
+                ClientMetadata {
+                    redirect_uris: Vec::new(),
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidRedirectUri,
+            ),
+            (
+                ClientMetadata {
+                    redirect_uris: Vec::new(),
+                    grant_types: Some(vec![GrantType::AuthorizationCode, GrantType::RefreshToken]),
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidRedirectUri,
+            ),
+            (
+                ClientMetadata {
+                    backchannel_logout_uri: Some("logout".to_string()),
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidClientMetadata,
+            ),
+            (
+                ClientMetadata {
+                    scope: Some("read admin".to_string()),
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidClientMetadata,
+            ),
+            (
+                ClientMetadata {
+                    redirect_uris: vec!["javascript:alert(document.cookie)".to_string()],
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidRedirectUri,
+            ),
+            (
+                ClientMetadata {
+                    redirect_uris: vec!["http://client.example.com/cb".to_string()],
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidRedirectUri,
+            ),
+            (
+                ClientMetadata {
+                    post_logout_redirect_uris: vec!["data:text/html,logout".to_string()],
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidClientMetadata,
+            ),
+            (
+                ClientMetadata {
+                    jwks_uri: Some("http://client.example.com/jwks.json".to_string()),
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidClientMetadata,
+            ),
+            (
+                ClientMetadata {
+                    jwks_uri: Some("https://169.254.169.254/latest/meta-data".to_string()),
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidClientMetadata,
+            ),
+            (
+                ClientMetadata {
+                    request_uris: vec!["https://10.0.0.1/request.jwt".to_string()],
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidClientMetadata,
+            ),
+            (
+                ClientMetadata {
+                    request_uris: vec!["https://[::ffff:127.0.0.1]/request.jwt".to_string()],
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidClientMetadata,
+            ),
+            (
+                ClientMetadata {
+                    backchannel_logout_uri: Some("https://localhost:8080/logout".to_string()),
+                    ..create_client_metadata()
+                },
+                ClientRegistrationError::InvalidClientMetadata,
+            ),
+        ] {
+            let error_response = register_client(
+                client_metadata,
+                false,
+                &scopes_supported,
+                REGISTRATION_ENDPOINT,
+                &client_store,
+            )
+            .unwrap_err();
+            assert_eq!(error_response.error, expected);
+        }
+
+        assert!(client_store.data.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_register_client_self_registered() {
+        let client_store = MapClientRepository::default();
+        let scopes_supported = vec!["read".to_string()];
+
+        for client_metadata in [
+            create_client_metadata(),
+            ClientMetadata {
+                scope: None,
+                grant_types: Some(vec![GrantType::ClientCredentials]),
+                ..create_client_metadata()
+            },
+        ] {
+            let error_response = register_client(
+                client_metadata,
+                true,
+                &scopes_supported,
+                REGISTRATION_ENDPOINT,
+                &client_store,
+            )
+            .unwrap_err();
+            assert_eq!(
+                error_response.error,
+                ClientRegistrationError::InvalidClientMetadata
+            );
+        }
+        assert!(client_store.data.lock().unwrap().is_empty());
+
+        let registration = register_client(
+            ClientMetadata {
+                scope: None,
+                ..create_client_metadata()
+            },
+            true,
+            &scopes_supported,
+            REGISTRATION_ENDPOINT,
+            &client_store,
+        )
+        .unwrap();
+        let client = client_store.read_client(&registration.client_id).unwrap();
+        assert!(client.self_registered);
+        assert!(client.scopes.is_empty());
+
+        let error_response = update_client_registration(
+            &registration.client_id,
+            &registration.registration_access_token,
+            ClientUpdateRequest {
+                client_id: registration.client_id.clone(),
+                client_secret: None,
+                client_metadata: create_client_metadata(),
+            },
+            &scopes_supported,
+            REGISTRATION_ENDPOINT,
+            &client_store,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error_response.error,
+            ClientRegistrationError::InvalidClientMetadata
+        );
+    }
+
+    #[test]
+    fn test_manage_client_registration() {
+        let client_store = MapClientRepository::default();
+        let scopes_supported = vec!["read".to_string(), "write".to_string()];
+        let registration = register_client(
+            create_client_metadata(),
+            false,
+            &scopes_supported,
+            REGISTRATION_ENDPOINT,
+            &client_store,
+        )
+        .unwrap();
+        let client_id = &registration.client_id;
+        let token = &registration.registration_access_token;
+
+        let response =
+            read_client_registration(client_id, token, REGISTRATION_ENDPOINT, &client_store)
+                .unwrap();
+        assert_eq!(response.client_metadata.scope, Some("read".to_string()));
+        assert!(response.client_secret.is_none());
+
+        let error_response =
+            read_client_registration(client_id, "wrong", REGISTRATION_ENDPOINT, &client_store)
+                .unwrap_err();
+        assert_eq!(error_response.error, ClientRegistrationError::InvalidToken);
+
+        let response = update_client_registration(
+            client_id,
+            token,
+            ClientUpdateRequest {
+                client_id: client_id.clone(),
+                client_secret: registration.client_secret.clone(),
+                client_metadata: ClientMetadata {
+                    scope: Some("read write".to_string()),
+                    ..create_client_metadata()
+                },
+            },
+            &scopes_supported,
+            REGISTRATION_ENDPOINT,
+            &client_store,
+        )
+        .unwrap();
+        assert_eq!(
+            response.client_metadata.scope,
+            Some("read write".to_string())
+        );
+        let client = client_store.read_client(client_id).unwrap();
+        assert_eq!(client.scopes, vec!["read", "write"]);
+        assert!(client.secret_hash.is_some());
+
+        let error_response = update_client_registration(
+            client_id,
+            token,
+            ClientUpdateRequest {
+                client_id: client_id.clone(),
+                client_secret: None,
+                client_metadata: ClientMetadata {
+                    token_endpoint_auth_method: Some(TokenEndpointAuthMethod::None),
+                    ..create_client_metadata()
+                },
+            },
+            &scopes_supported,
+            REGISTRATION_ENDPOINT,
+            &client_store,
+        )
+        .unwrap_err();
+        assert_eq!(
+            error_response.error,
+            ClientRegistrationError::InvalidClientMetadata
+        );
+
+        assert_eq!(
+            delete_client_registration(client_id, "wrong", &client_store)
+                .unwrap_err()
+                .error,
+            ClientRegistrationError::InvalidToken
+        );
+        delete_client_registration(client_id, token, &client_store).unwrap();
+        assert!(client_store.read_client(client_id).is_none());
+        assert_eq!(
+            delete_client_registration(client_id, token, &client_store)
+                .unwrap_err()
+                .error,
+            ClientRegistrationError::InvalidToken
+        );
+    }
+}
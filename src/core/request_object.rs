@@ -0,0 +1,453 @@
+use std::time;
+
+use anyhow::{Result, anyhow, bail};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::warn;
+
+use crate::core::{
+    authorization::{
+        AuthorizationError, AuthorizationErrorResponse, AuthorizationRequest, Client,
+        ClientRepository,
+    },
+    jose::{self, ReceivedJwsHeader},
+    key::JwkSet,
+    token::TokenSettings,
+};
+
+const REQUEST_OBJECT_FETCH_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+/// Request objects and key sets are small, so larger responses are not read any further
+const REQUEST_OBJECT_FETCH_LIMIT: usize = 64 * 1024;
+
+/// Parameters passing the authorization request as request object, either by value or by
+/// reference (RFC 9101 section 5)
+#[derive(Deserialize, Debug)]
+pub struct RequestObjectReference {
+    pub client_id: String,
+    pub request: Option<String>,
+    pub request_uri: Option<String>,
+}
+
+/// Resolves the request object of the client and verifies it against the client's keys. Only
+/// the claims of the request object count, any other query parameters are ignored (RFC 9101
+/// section 6.3).
+pub async fn request_object_authorization_request<C: ClientRepository>(
+    reference: &RequestObjectReference,
+    token_settings: &TokenSettings,
+    client_store: &C,
+) -> Result<AuthorizationRequest, AuthorizationErrorResponse> {
+    let error_response = |error: AuthorizationError, error_description: String| {
+        let mut auth_error_response = AuthorizationErrorResponse::new(error, None);
+        auth_error_response.error_description = Some(error_description);
+        auth_error_response
+    };
+
+    let client = client_store
+        .read_client(&reference.client_id)
+        .ok_or_else(|| {
+            error_response(
+                AuthorizationError::UnauthorizedClient,
+                format!("Unknown client {}", reference.client_id),
+            )
+        })?;
+
+    let request_object = match (&reference.request, &reference.request_uri) {
+        (Some(request), None) => request.clone(),
+        (None, Some(request_uri)) if client.request_uris.contains(request_uri) => {
+            fetch(request_uri).await.map_err(|error| {
+                warn!(
+                    "Could not fetch request object of client {} from {request_uri}: {error}",
+                    client.id
+                );
+                error_response(
+                    AuthorizationError::InvalidRequestUri,
+                    "request_uri could not be fetched".to_string(),
+                )
+            })?
+        }
+        (None, Some(request_uri)) => {
+            return Err(error_response(
+                AuthorizationError::InvalidRequestUri,
+                format!("request_uri {request_uri} is not registered"),
+            ));
+        }
+        _ => {
+            return Err(error_response(
+                AuthorizationError::InvalidRequest,
+                "Either request or request_uri is required".to_string(),
+            ));
+        }
+    };
+
+    verify_request_object(&request_object, &client, token_settings)
+        .await
+        .map_err(|error| {
+            error_response(AuthorizationError::InvalidRequestObject, error.to_string())
+        })
+}
+
+/// Verifies the signature and the claims RFC 9101 section 4 requires, then reads the
+/// authorization request from the claims
+async fn verify_request_object(
+    request_object: &str,
+    client: &Client,
+    token_settings: &TokenSettings,
+) -> Result<AuthorizationRequest> {
+    let header = ReceivedJwsHeader::decode(request_object)?;
+    let mut claims = if header.alg == "none" {
+        if !client.allow_unsigned_request_objects {
+            return Err(anyhow!("Unsigned request objects are not allowed"));
+        }
+        jose::decode_unsecured(request_object)?
+    } else {
+        client_keys(client)
+            .await?
+            .keys
+            .iter()
+            .filter(|key| header.kid.is_none() || key.kid == header.kid)
+            .find_map(|key| key.verify(request_object).ok())
+            .ok_or_else(|| anyhow!("Signature does not match any key of the client"))?
+    };
+
+    if claims["iss"] != client.id.as_str() {
+        return Err(anyhow!("iss has to be the client ID"));
+    }
+    let audience_matches = match &claims["aud"] {
+        Value::String(aud) => *aud == token_settings.issuer,
+        Value::Array(aud) => aud.iter().any(|aud| *aud == token_settings.issuer.as_str()),
+        _ => false,
+    };
+    if !audience_matches {
+        return Err(anyhow!(
+            "aud has to be the issuer {}",
+            token_settings.issuer
+        ));
+    }
+    match claims["exp"].as_i64() {
+        Some(exp) if exp > Utc::now().timestamp() => (),
+        Some(_) => return Err(anyhow!("Request object is expired")),
+        None => return Err(anyhow!("exp is required")),
+    }
+    if claims["nbf"]
+        .as_i64()
+        .is_some_and(|nbf| nbf > Utc::now().timestamp())
+    {
+        return Err(anyhow!("Request object is not valid yet"));
+    }
+
+    let claims = claims
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Claims are no JSON object"))?;
+    if claims.contains_key("request") || claims.contains_key("request_uri") {
+        return Err(anyhow!("Request objects must not be nested"));
+    }
+    match claims.get("client_id") {
+        Some(client_id) if *client_id != client.id.as_str() => {
+            return Err(anyhow!("client_id does not match the query"));
+        }
+        Some(_) => (),
+        None => {
+            claims.insert("client_id".to_string(), Value::String(client.id.clone()));
+        }
+    }
+
+    Ok(serde_json::from_value(Value::Object(claims.clone()))?)
+}
+
+/// Keys of the client, either registered inline or published at its `jwks_uri`
+async fn client_keys(client: &Client) -> Result<JwkSet> {
+    match (&client.jwks, &client.jwks_uri) {
+        (Some(jwks), _) => Ok(jwks.clone()),
+        (None, Some(jwks_uri)) => {
+            let jwks = fetch(jwks_uri)
+                .await
+                .and_then(|jwks| Ok(serde_json::from_str(&jwks)?));
+            jwks.map_err(|error| {
+                warn!(
+                    "Could not fetch keys of client {} from {jwks_uri}: {error}",
+                    client.id
+                );
+                anyhow!("Keys of the client could not be fetched")
+            })
+        }
+        (None, None) => Err(anyhow!("Client has no keys registered")),
+    }
+}
+
+/// Fetches a client controlled URI. Errors describe the response for the log and are not meant
+/// to be passed on to the user agent.
+async fn fetch(uri: &str) -> Result<String> {
+    let http_client = reqwest::Client::builder()
+        .timeout(REQUEST_OBJECT_FETCH_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+
+    let mut response = http_client.get(uri).send().await?;
+    if !response.status().is_success() {
+        bail!("{uri} responded with {}", response.status());
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > REQUEST_OBJECT_FETCH_LIMIT {
+            bail!("{uri} responded with more than {REQUEST_OBJECT_FETCH_LIMIT} bytes");
+        }
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(String::from_utf8(body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, routing::get};
+    use chrono::{Duration, Utc};
+    use serde_json::{Value, json};
+    use tokio::net::TcpListener;
+
+    use crate::{
+        core::{
+            authorization::{AuthorizationError, ClientRepository, ResponseType},
+            jose::{Algorithm, SigningKey},
+            key::JwkSet,
+            request_object::{RequestObjectReference, request_object_authorization_request},
+            token::TokenSettings,
+        },
+        repository::client::MapClientRepository,
+    };
+
+    /// Serves the JWKS of the client and a request object like a client would
+    async fn serve_client(jwks: JwkSet, request_object: String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Router::new()
+            .route(
+                "/jwks",
+                get(move || async move { serde_json::to_string(&jwks).unwrap() }),
+            )
+            .route("/request.jwt", get(move || async move { request_object }))
+            .route("/large.jwt", get(|| async { "a".repeat(64 * 1024 + 1) }));
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        format!("http://{addr}")
+    }
+
+    fn create_claims() -> Value {
+        json!({
+            "iss": "s6BhdRkqt3",
+            "aud": "https://keyper.example.com",
+            "exp": (Utc::now() + Duration::seconds(60)).timestamp(),
+            "response_type": "code",
+            "client_id": "s6BhdRkqt3",
+            "redirect_uri": "https://client.example.com/cb",
+            "scope": "read",
+            "state": "af0ifjsldkj",
+            "max_age": 300,
+        })
+    }
+
+    fn create_token_settings() -> TokenSettings {
+        TokenSettings {
+            issuer: "https://keyper.example.com".to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn create_reference(
+        request: Option<&str>,
+        request_uri: Option<&str>,
+    ) -> RequestObjectReference {
+        RequestObjectReference {
+            client_id: "s6BhdRkqt3".to_string(),
+            request: request.map(str::to_string),
+            request_uri: request_uri.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_object_by_value() {
+        let signing_key = SigningKey::generate(Algorithm::ES256).unwrap();
+        let jwks = JwkSet {
+            keys: vec![signing_key.public_jwk()],
+        };
+        let client_store = MapClientRepository::try_from_toml(
+            r#"
+            [s6BhdRkqt3]
+            name = "Example Client"
+            client_type = "public"
+            redirect_uris = ["https://client.example.com/cb"]
+            "#,
+        )
+        .unwrap();
+        let mut client = client_store.read_client("s6BhdRkqt3").unwrap();
+        client.jwks = Some(jwks);
+        client_store.update_client(client).unwrap();
+        let token_settings = create_token_settings();
+
+        let request_object = signing_key
+            .sign("oauth-authz-req+jwt", &create_claims())
+            .unwrap();
+        let auth_request = request_object_authorization_request(
+            &create_reference(Some(&request_object), None),
+            &token_settings,
+            &client_store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(auth_request.response_type, ResponseType::Code);
+        assert_eq!(auth_request.state, Some("af0ifjsldkj".to_string()));
+        assert_eq!(auth_request.max_age, Some(300));
+
+        let other_key = SigningKey::generate(Algorithm::ES256).unwrap();
+        let invalid_claims = [
+            ("iss", json!("other")),
+            ("aud", json!("https://other.example.com")),
+            (
+                "exp",
+                json!((Utc::now() - Duration::seconds(1)).timestamp()),
+            ),
+            ("exp", Value::Null),
+            ("client_id", json!("other")),
+            (
+                "request_uri",
+                json!("https://client.example.com/request.jwt"),
+            ),
+        ];
+        let mut request_objects: Vec<String> = invalid_claims
+            .into_iter()
+            .map(|(name, value)| {
+                let mut claims = create_claims();
+                claims[name] = value;
+                signing_key.sign("oauth-authz-req+jwt", &claims).unwrap()
+            })
+            .collect();
+        request_objects.push(
+            other_key
+                .sign("oauth-authz-req+jwt", &create_claims())
+                .unwrap(),
+        );
+        request_objects.push(unsigned(&create_claims()));
+
+        for request_object in request_objects {
+            let error_response = request_object_authorization_request(
+                &create_reference(Some(&request_object), None),
+                &token_settings,
+                &client_store,
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(
+                error_response.error,
+                AuthorizationError::InvalidRequestObject
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_object_by_reference() {
+        let signing_key = SigningKey::generate(Algorithm::EdDSA).unwrap();
+        let request_object = signing_key
+            .sign("oauth-authz-req+jwt", &create_claims())
+            .unwrap();
+        let base_uri = serve_client(
+            JwkSet {
+                keys: vec![signing_key.public_jwk()],
+            },
+            request_object,
+        )
+        .await;
+
+        let client_store = MapClientRepository::try_from_toml(&format!(
+            r#"
+            [s6BhdRkqt3]
+            name = "Example Client"
+            client_type = "public"
+            redirect_uris = ["https://client.example.com/cb"]
+            jwks_uri = "{base_uri}/jwks"
+            request_uris = [
+                "{base_uri}/request.jwt",
+                "{base_uri}/large.jwt",
+                "{base_uri}/missing.jwt",
+            ]
+            "#
+        ))
+        .unwrap();
+        let token_settings = create_token_settings();
+
+        let auth_request = request_object_authorization_request(
+            &create_reference(None, Some(&format!("{base_uri}/request.jwt"))),
+            &token_settings,
+            &client_store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(auth_request.scope, Some("read".to_string()));
+
+        let error_response = request_object_authorization_request(
+            &create_reference(None, Some(&format!("{base_uri}/jwks"))),
+            &token_settings,
+            &client_store,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error_response.error, AuthorizationError::InvalidRequestUri);
+
+        for path in ["large.jwt", "missing.jwt"] {
+            let error_response = request_object_authorization_request(
+                &create_reference(None, Some(&format!("{base_uri}/{path}"))),
+                &token_settings,
+                &client_store,
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(error_response.error, AuthorizationError::InvalidRequestUri);
+            assert_eq!(
+                error_response.error_description.as_deref(),
+                Some("request_uri could not be fetched")
+            );
+        }
+
+        let error_response = request_object_authorization_request(
+            &create_reference(None, None),
+            &token_settings,
+            &client_store,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error_response.error, AuthorizationError::InvalidRequest);
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_request_object() {
+        let client_store = MapClientRepository::try_from_toml(
+            r#"
+            [s6BhdRkqt3]
+            name = "Example Client"
+            client_type = "public"
+            redirect_uris = ["https://client.example.com/cb"]
+            allow_unsigned_request_objects = true
+            "#,
+        )
+        .unwrap();
+
+        let auth_request = request_object_authorization_request(
+            &create_reference(Some(&unsigned(&create_claims())), None),
+            &create_token_settings(),
+            &client_store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(auth_request.client_id, "s6BhdRkqt3");
+    }
+
+    fn unsigned(claims: &Value) -> String {
+        use base64::prelude::*;
+
+        format!(
+            "{}.{}.",
+            BASE64_URL_SAFE_NO_PAD.encode(r#"{"alg":"none"}"#),
+            BASE64_URL_SAFE_NO_PAD.encode(claims.to_string())
+        )
+    }
+}
@@ -0,0 +1,243 @@
+use serde::Deserialize;
+
+use crate::core::{
+    authorization::ClientRepository,
+    token::{
+        AccessTokenError, AccessTokenErrorResponse, AuthorizationRepository, ClientCredentials,
+        RefreshTokenRepository, authenticate_client, revoke_refresh_token_family,
+    },
+};
+
+#[derive(Deserialize, Default, Debug)]
+pub struct RevocationRequest {
+    pub token: String,
+    pub token_type_hint: Option<TokenTypeHint>,
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+}
+
+#[derive(Deserialize, PartialEq, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenTypeHint {
+    AccessToken,
+    RefreshToken,
+    #[serde(other)]
+    Unknown,
+}
+
+/// Revokes an access or refresh token issued to the authenticated client (RFC 7009 section 2.1).
+/// Revoking a refresh token revokes its whole family and every access token derived from it.
+/// Unknown tokens and tokens of other clients are ignored, so the response does not reveal
+/// whether a token exists.
+pub async fn revoke<C: ClientRepository, R: AuthorizationRepository, F: RefreshTokenRepository>(
+    revocation_request: RevocationRequest,
+    client_credentials: ClientCredentials,
+    client_store: &C,
+    authorization_store: &R,
+    refresh_token_store: &F,
+) -> Result<(), AccessTokenErrorResponse> {
+    let client = authenticate_client(client_credentials, client_store)?;
+    let token = &revocation_request.token;
+
+    let revoke_access_token = || {
+        let authorization = authorization_store
+            .read_authorization(token)
+            .filter(|authorization| authorization.client_id == client.id)?;
+        authorization_store.delete_authorization(&authorization.access_token)
+    };
+
+    let revoke_refresh_token = || {
+        let refresh_token = refresh_token_store
+            .read_refresh_token(token)
+            .filter(|refresh_token| refresh_token.client_id == client.id)?;
+        Some(revoke_refresh_token_family(
+            &refresh_token.family,
+            authorization_store,
+            refresh_token_store,
+        ))
+    };
+
+    // The hint only determines the lookup order (RFC 7009 section 2.1)
+    let revoked = if revocation_request.token_type_hint == Some(TokenTypeHint::RefreshToken) {
+        match revoke_refresh_token() {
+            Some(result) => result,
+            None => {
+                revoke_access_token();
+                Ok(())
+            }
+        }
+    } else {
+        match revoke_access_token() {
+            Some(_) => Ok(()),
+            None => revoke_refresh_token().unwrap_or(Ok(())),
+        }
+    };
+
+    if revoked.is_err() {
+        return Err(AccessTokenErrorResponse::new(AccessTokenError::ServerError));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        core::{
+            revocation::{RevocationRequest, TokenTypeHint, revoke},
+            token::{
+                Authorization, AuthorizationRepository, ClientCredentials, RefreshToken,
+                RefreshTokenRepository,
+            },
+        },
+        repository::{
+            authorization::MapAuthorizationRepository, client::TestClientRepository,
+            refresh_token::MapRefreshTokenRepository,
+        },
+    };
+
+    fn create_client_store() -> TestClientRepository {
+        TestClientRepository {
+            client_ids: vec!["s6BhdRkqt3".to_string(), "other".to_string()],
+        }
+    }
+
+    /// Creates a refresh token family with two rotations and the access tokens issued alongside
+    fn create_stores() -> (MapAuthorizationRepository, MapRefreshTokenRepository) {
+        let authorization_store = MapAuthorizationRepository::default();
+        let refresh_token_store = MapRefreshTokenRepository::default();
+        let created = Utc::now();
+
+        for (access_token, refresh_token) in [("first", "refresh1"), ("second", "refresh2")] {
+            refresh_token_store
+                .create_refresh_token(RefreshToken {
+                    token: refresh_token.to_string(),
+                    family: "family".to_string(),
+                    client_id: "s6BhdRkqt3".to_string(),
+                    scopes: Vec::new(),
+                    owner: "alice".to_string(),
+                    expires: created + Duration::seconds(3600),
+                    family_expires: created + Duration::seconds(7200),
+                    rotated: refresh_token == "refresh1",
+                    jkt: None,
+                })
+                .unwrap();
+            authorization_store
+                .create_authorization(Authorization {
+                    access_token: access_token.to_string(),
+                    client_id: "s6BhdRkqt3".to_string(),
+                    scopes: Vec::new(),
+                    owner: Some("alice".to_string()),
+                    created,
+                    expires: created + Duration::seconds(3600),
+                    refresh_token: Some(refresh_token.to_string()),
+                    jkt: None,
+                })
+                .unwrap();
+        }
+
+        (authorization_store, refresh_token_store)
+    }
+
+    fn client_credentials(client_id: &str) -> ClientCredentials {
+        ClientCredentials::from_request(None, Some(client_id.to_string()), None).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_revoke_access_token() {
+        let (authorization_store, refresh_token_store) = create_stores();
+        let revocation_request = RevocationRequest {
+            token: "first".to_string(),
+            ..Default::default()
+        };
+
+        revoke(
+            revocation_request,
+            client_credentials("s6BhdRkqt3"),
+            &create_client_store(),
+            &authorization_store,
+            &refresh_token_store,
+        )
+        .await
+        .unwrap();
+
+        assert!(authorization_store.read_authorization("first").is_none());
+        assert!(authorization_store.read_authorization("second").is_some());
+        assert!(refresh_token_store.read_refresh_token("refresh2").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_revoke_refresh_token() {
+        for token_type_hint in [
+            None,
+            Some(TokenTypeHint::RefreshToken),
+            Some(TokenTypeHint::AccessToken),
+        ] {
+            let (authorization_store, refresh_token_store) = create_stores();
+            let revocation_request = RevocationRequest {
+                token: "refresh2".to_string(),
+                token_type_hint,
+                ..Default::default()
+            };
+
+            revoke(
+                revocation_request,
+                client_credentials("s6BhdRkqt3"),
+                &create_client_store(),
+                &authorization_store,
+                &refresh_token_store,
+            )
+            .await
+            .unwrap();
+
+            assert!(refresh_token_store.read_refresh_token("refresh1").is_none());
+            assert!(refresh_token_store.read_refresh_token("refresh2").is_none());
+            assert!(authorization_store.read_authorization("first").is_none());
+            assert!(authorization_store.read_authorization("second").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_revoke_other_client() {
+        let (authorization_store, refresh_token_store) = create_stores();
+
+        for token in ["first", "refresh2", "unknown"] {
+            let revocation_request = RevocationRequest {
+                token: token.to_string(),
+                ..Default::default()
+            };
+
+            revoke(
+                revocation_request,
+                client_credentials("other"),
+                &create_client_store(),
+                &authorization_store,
+                &refresh_token_store,
+            )
+            .await
+            .unwrap();
+        }
+
+        assert!(authorization_store.read_authorization("first").is_some());
+        assert!(refresh_token_store.read_refresh_token("refresh2").is_some());
+    }
+
+    #[test]
+    fn test_deserialize_token_type_hint() {
+        let revocation_request: RevocationRequest =
+            serde_urlencoded::from_str("token=foobar&token_type_hint=refresh_token").unwrap();
+        assert_eq!(
+            revocation_request.token_type_hint,
+            Some(TokenTypeHint::RefreshToken)
+        );
+
+        let revocation_request: RevocationRequest =
+            serde_urlencoded::from_str("token=foobar&token_type_hint=id_token").unwrap();
+        assert_eq!(
+            revocation_request.token_type_hint,
+            Some(TokenTypeHint::Unknown)
+        );
+    }
+}
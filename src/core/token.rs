@@ -1,20 +1,56 @@
-use std::error::Error;
-
-use chrono::{DateTime, Utc};
+use anyhow::Result;
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{SaltString, rand_core::OsRng},
+};
+use base64::prelude::*;
+use chrono::{DateTime, Duration, Utc};
+use rand::{distributions, prelude::*};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-#[derive(Deserialize, Debug)]
+use tracing::{error, warn};
+
+use crate::core::{
+    authorization::{
+        AccessTokenFormat, AuthorizationCode, AuthorizationCodeRepository, Client,
+        ClientRepository, ClientType, CodeChallengeMethod, TokenEndpointAuthMethod, is_pkce_value,
+    },
+    device::{DeviceCodeRepository, redeem_device_code},
+    dpop::Confirmation,
+    key::KeyRepository,
+    oidc::{self, OPENID_SCOPE},
+};
+
+pub const ACCESS_TOKEN_TTL: i64 = 3600;
+
+#[derive(Deserialize, Default, Debug)]
 pub struct AccessTokenRequest {
     pub grant_type: GrantType,
-    pub code: String,
+    pub code: Option<String>,
+    pub refresh_token: Option<String>,
+    pub scope: Option<String>,
     pub redirect_uri: Option<String>,
     pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub code_verifier: Option<String>,
+    pub device_code: Option<String>,
+    /// JWK thumbprint of the key the client proved possession of with a DPoP proof
+    #[serde(skip)]
+    pub dpop_jkt: Option<String>,
 }
 
-#[derive(Deserialize, PartialEq, Debug)]
+#[derive(Deserialize, Serialize, Default, PartialEq, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum GrantType {
+    #[default]
     AuthorizationCode,
+    RefreshToken,
+    ClientCredentials,
+    #[serde(rename = "urn:ietf:params:oauth:grant-type:device_code")]
+    DeviceCode,
+    #[serde(other)]
+    Unsupported,
 }
 
 #[derive(Serialize, Debug)]
@@ -22,24 +58,43 @@ pub struct AccessTokenResponse {
     pub access_token: String,
     pub token_type: TokenType,
     pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
 }
 
 #[derive(Serialize, PartialEq, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum TokenType {
     Bearer,
+    /// Access token bound to the key of a DPoP proof (RFC 9449 section 5)
+    #[serde(rename = "DPoP")]
+    Dpop,
 }
 
 #[derive(Serialize, Debug)]
 pub struct AccessTokenErrorResponse {
     pub error: AccessTokenError,
-    pub error_descripton: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub error_uri: Option<String>,
 }
 
-#[derive(Serialize, Debug)]
+impl AccessTokenErrorResponse {
+    pub fn new(error: AccessTokenError) -> Self {
+        Self {
+            error,
+            error_description: None,
+            error_uri: None,
+        }
+    }
+}
+
+#[derive(Serialize, PartialEq, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum AccessTokenError {
     InvalidRequest,
@@ -48,6 +103,114 @@ pub enum AccessTokenError {
     UnauthorizedClient,
     UnsupportedGrantType,
     InvalidScope,
+    ServerError,
+    AuthorizationPending,
+    SlowDown,
+    ExpiredToken,
+    AccessDenied,
+    /// RFC 9449 sections 5 and 8
+    InvalidDpopProof,
+    UseDpopNonce,
+}
+
+/// Client credentials as presented at the token endpoint
+#[derive(Debug)]
+pub struct ClientCredentials {
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub method: TokenEndpointAuthMethod,
+}
+
+impl ClientCredentials {
+    /// Determines the authentication method from the HTTP basic credentials and the `client_id`
+    /// and `client_secret` request parameters. Clients must not use more than one method at once
+    /// (RFC 6749 section 2.3).
+    pub fn from_request(
+        basic_credentials: Option<(String, String)>,
+        client_id: Option<String>,
+        client_secret: Option<String>,
+    ) -> Result<Self, AccessTokenErrorResponse> {
+        match (basic_credentials, client_id, client_secret) {
+            (Some(_), _, Some(_)) => Err(AccessTokenErrorResponse::new(
+                AccessTokenError::InvalidRequest,
+            )),
+            (Some((basic_client_id, _)), Some(client_id), None) if basic_client_id != client_id => {
+                Err(AccessTokenErrorResponse::new(
+                    AccessTokenError::InvalidRequest,
+                ))
+            }
+            (Some((client_id, client_secret)), _, None) => Ok(Self {
+                client_id,
+                client_secret: Some(client_secret),
+                method: TokenEndpointAuthMethod::ClientSecretBasic,
+            }),
+            (None, Some(client_id), Some(client_secret)) => Ok(Self {
+                client_id,
+                client_secret: Some(client_secret),
+                method: TokenEndpointAuthMethod::ClientSecretPost,
+            }),
+            (None, Some(client_id), None) => Ok(Self {
+                client_id,
+                client_secret: None,
+                method: TokenEndpointAuthMethod::None,
+            }),
+            (None, None, _) => Err(AccessTokenErrorResponse::new(
+                AccessTokenError::InvalidClient,
+            )),
+        }
+    }
+}
+
+/// Authenticates a client with the method it was registered for. Public clients only identify
+/// themselves and are rejected if they present a secret.
+pub fn authenticate_client<C: ClientRepository>(
+    client_credentials: ClientCredentials,
+    client_store: &C,
+) -> Result<Client, AccessTokenErrorResponse> {
+    let Some(client) = client_store.read_client(&client_credentials.client_id) else {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidClient,
+        ));
+    };
+
+    if client_credentials.method != client.token_endpoint_auth_method {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidClient,
+        ));
+    }
+
+    let authenticated = match (&client_credentials.client_secret, &client.secret_hash) {
+        (None, _) => client_credentials.method == TokenEndpointAuthMethod::None,
+        (Some(client_secret), Some(secret_hash)) => verify_hash(client_secret, secret_hash),
+        (Some(_), None) => false,
+    };
+
+    if !authenticated {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidClient,
+        ));
+    }
+
+    Ok(client)
+}
+
+/// Hashes a secret with Argon2 and a random salt into PHC string format
+pub fn hash_secret(secret: &str) -> Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(secret.as_bytes(), &salt)
+        .map_err(|error| anyhow::anyhow!("Could not hash secret: {error}"))?;
+
+    Ok(hash.to_string())
+}
+
+/// Verifies a secret against an Argon2 hash in PHC string format
+pub fn verify_hash(secret: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(secret.as_bytes(), &hash)
+            .is_ok()
+    })
 }
 
 pub trait OwnerRepository {
@@ -62,58 +225,1532 @@ pub struct Owner {
 }
 
 pub trait AuthorizationRepository {
-    fn create_authorization<E: Error>(&self, authorization: &Authorization) -> Result<(), E>;
+    fn create_authorization(&self, authorization: Authorization) -> Result<()>;
     fn read_authorization(&self, token: &str) -> Option<Authorization>;
+    fn delete_authorization(&self, token: &str) -> Option<Authorization>;
+    /// Deletes every authorization whose access token was issued together with one of the given
+    /// refresh tokens
+    fn delete_authorizations_by_refresh_token(&self, refresh_tokens: &[String]) -> Result<()>;
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Clone, Debug)]
 pub struct Authorization {
-    access_token: String,
-    scopes: Vec<String>,
-    owner_id: i32,
-    created: DateTime<Utc>,
-    expires: DateTime<Utc>,
-    refresh_token: Option<String>,
+    pub access_token: String,
+    pub client_id: String,
+    pub scopes: Vec<String>,
+    pub owner: Option<String>,
+    pub created: DateTime<Utc>,
+    pub expires: DateTime<Utc>,
+    pub refresh_token: Option<String>,
+    /// JWK thumbprint of the DPoP key the access token is bound to
+    pub jkt: Option<String>,
+}
+
+impl Authorization {
+    pub fn token_type(&self) -> TokenType {
+        match self.jkt {
+            Some(_) => TokenType::Dpop,
+            None => TokenType::Bearer,
+        }
+    }
+}
+
+pub trait RefreshTokenRepository {
+    fn create_refresh_token(&self, refresh_token: RefreshToken) -> Result<()>;
+    fn read_refresh_token(&self, token: &str) -> Option<RefreshToken>;
+    /// Atomically marks a refresh token as rotated and returns its previous state
+    fn rotate_refresh_token(&self, token: &str) -> Option<RefreshToken>;
+    /// Removes all refresh tokens of a family and returns the revoked tokens
+    fn revoke_refresh_token_family(&self, family: &str) -> Result<Vec<String>>;
 }
 
-pub async fn access_token(
+/// Refresh tokens are rotated on every use. All tokens descending from the same authorization
+/// grant share a family, which is revoked as a whole once a rotated token is presented again.
+#[derive(Clone, Debug)]
+pub struct RefreshToken {
+    pub token: String,
+    pub family: String,
+    pub client_id: String,
+    pub scopes: Vec<String>,
+    pub owner: String,
+    pub expires: DateTime<Utc>,
+    pub family_expires: DateTime<Utc>,
+    pub rotated: bool,
+    /// JWK thumbprint of the DPoP key refresh tokens of public clients are bound to
+    pub jkt: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TokenSettings {
+    /// Base URL keyper is reachable at without trailing slash, used as the issuer identifier
+    /// in tokens and in the server metadata
+    pub issuer: String,
+    /// Resource server identifier put into the `aud` claim of JWT access tokens
+    pub audience: String,
+    /// Seconds a refresh token stays valid without being used
+    pub refresh_token_idle_ttl: i64,
+    /// Seconds a refresh token family stays valid after the initial grant
+    pub refresh_token_absolute_ttl: i64,
+}
+
+impl Default for TokenSettings {
+    fn default() -> Self {
+        Self {
+            issuer: "http://localhost:3000".to_string(),
+            audience: "http://localhost:3000".to_string(),
+            refresh_token_idle_ttl: 14 * 24 * 3600,
+            refresh_token_absolute_ttl: 90 * 24 * 3600,
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn access_token<
+    C: ClientRepository,
+    A: AuthorizationCodeRepository,
+    R: AuthorizationRepository,
+    F: RefreshTokenRepository,
+    D: DeviceCodeRepository,
+    O: OwnerRepository,
+    K: KeyRepository,
+>(
     access_token_request: AccessTokenRequest,
+    client_credentials: ClientCredentials,
+    token_settings: &TokenSettings,
+    client_store: &C,
+    authorization_code_store: &A,
+    authorization_store: &R,
+    refresh_token_store: &F,
+    device_code_store: &D,
+    owner_store: &O,
+    key_store: &K,
 ) -> Result<AccessTokenResponse, AccessTokenErrorResponse> {
-    if access_token_request.grant_type != GrantType::AuthorizationCode {
-        let access_token_error_response = AccessTokenErrorResponse {
-            error: AccessTokenError::UnsupportedGrantType,
-            error_descripton: None,
-            error_uri: None,
-        };
+    let client = authenticate_client(client_credentials, client_store)?;
+    if client.dpop_bound_access_tokens && access_token_request.dpop_jkt.is_none() {
+        let mut access_token_error_response =
+            AccessTokenErrorResponse::new(AccessTokenError::InvalidDpopProof);
+        access_token_error_response.error_description =
+            Some("Client has to present a DPoP proof".to_string());
+        return Err(access_token_error_response);
+    }
+    // Refresh tokens of confidential clients are bound to the client authentication instead
+    // (RFC 9449 section 5)
+    let refresh_token_jkt = match client.client_type {
+        ClientType::Public => access_token_request.dpop_jkt.clone(),
+        ClientType::Confidential => None,
+    };
+
+    let (scopes, owner, refresh_token, authorization_code) = match access_token_request.grant_type {
+        GrantType::AuthorizationCode => {
+            let authorization_code = redeem_authorization_code(
+                &access_token_request,
+                &client,
+                authorization_code_store,
+            )?;
+            let refresh_token = new_refresh_token(
+                generate_token(),
+                &client,
+                &authorization_code.scopes,
+                &authorization_code.owner,
+                Utc::now() + Duration::seconds(token_settings.refresh_token_absolute_ttl),
+                refresh_token_jkt,
+                token_settings,
+            );
+
+            (
+                authorization_code.scopes.clone(),
+                Some(authorization_code.owner.clone()),
+                Some(refresh_token),
+                Some(authorization_code),
+            )
+        }
+        GrantType::RefreshToken => {
+            let (scopes, refresh_token) = rotate_refresh_token(
+                &access_token_request,
+                &client,
+                token_settings,
+                authorization_store,
+                refresh_token_store,
+            )?;
+
+            (
+                scopes,
+                Some(refresh_token.owner.clone()),
+                Some(refresh_token),
+                None,
+            )
+        }
+        GrantType::ClientCredentials => {
+            let scopes = client_credentials_scopes(&access_token_request, &client)?;
+
+            (scopes, None, None, None)
+        }
+        GrantType::DeviceCode => {
+            let (device_code, owner) =
+                redeem_device_code(&access_token_request, &client, device_code_store)?;
+            let refresh_token = new_refresh_token(
+                generate_token(),
+                &client,
+                &device_code.scopes,
+                &owner,
+                Utc::now() + Duration::seconds(token_settings.refresh_token_absolute_ttl),
+                refresh_token_jkt,
+                token_settings,
+            );
 
+            (device_code.scopes, Some(owner), Some(refresh_token), None)
+        }
+        GrantType::Unsupported => {
+            return Err(AccessTokenErrorResponse::new(
+                AccessTokenError::UnsupportedGrantType,
+            ));
+        }
+    };
+
+    let mut access_token_response = issue_access_token(
+        &client,
+        scopes,
+        owner,
+        refresh_token
+            .as_ref()
+            .map(|refresh_token| refresh_token.token.clone()),
+        access_token_request.dpop_jkt.clone(),
+        token_settings,
+        authorization_store,
+        key_store,
+    )?;
+
+    // The refresh token only becomes usable once the access token issued alongside it exists
+    if let Some(refresh_token) = refresh_token {
+        let token = refresh_token.token.clone();
+        if let Err(error) = refresh_token_store.create_refresh_token(refresh_token) {
+            error!("Could not store refresh token: {error}");
+            if let Err(error) = authorization_store.delete_authorizations_by_refresh_token(&[token])
+            {
+                error!("Could not revoke access token of unstored refresh token: {error}");
+            }
+            return Err(AccessTokenErrorResponse::new(AccessTokenError::ServerError));
+        }
+    }
+
+    // OpenID Connect authentication requests are answered with an ID token on top of the access
+    // token (OpenID Connect Core section 3.1.3.3)
+    if let Some(authorization_code) = authorization_code.filter(|authorization_code| {
+        authorization_code
+            .scopes
+            .iter()
+            .any(|scope| scope == OPENID_SCOPE)
+    }) {
+        access_token_response.id_token = Some(oidc::id_token(
+            &authorization_code,
+            &access_token_response.access_token,
+            token_settings,
+            owner_store,
+            key_store,
+        )?);
+    }
+
+    Ok(access_token_response)
+}
+
+fn redeem_authorization_code<A: AuthorizationCodeRepository>(
+    access_token_request: &AccessTokenRequest,
+    client: &Client,
+    authorization_code_store: &A,
+) -> Result<AuthorizationCode, AccessTokenErrorResponse> {
+    let Some(code) = &access_token_request.code else {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidRequest,
+        ));
+    };
+
+    let Some(authorization_code) = authorization_code_store.consume_authorization_code(code) else {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidGrant,
+        ));
+    };
+
+    if authorization_code.expires <= Utc::now()
+        || authorization_code.client_id != client.id
+        || authorization_code.redirect_uri != access_token_request.redirect_uri
+    {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidGrant,
+        ));
+    }
+
+    if !verify_code_verifier(
+        authorization_code.code_challenge.as_deref(),
+        authorization_code.code_challenge_method,
+        access_token_request.code_verifier.as_deref(),
+    ) {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidGrant,
+        ));
+    }
+
+    Ok(authorization_code)
+}
+
+/// Rotates the presented refresh token (RFC 6749 section 6) and returns the scopes granted to the
+/// new access token together with the refresh token replacing it, which the caller stores once
+/// the access token has been issued.
+fn rotate_refresh_token<R: AuthorizationRepository, F: RefreshTokenRepository>(
+    access_token_request: &AccessTokenRequest,
+    client: &Client,
+    token_settings: &TokenSettings,
+    authorization_store: &R,
+    refresh_token_store: &F,
+) -> Result<(Vec<String>, RefreshToken), AccessTokenErrorResponse> {
+    let Some(token) = &access_token_request.refresh_token else {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidRequest,
+        ));
+    };
+
+    let Some(refresh_token) = refresh_token_store.read_refresh_token(token) else {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidGrant,
+        ));
+    };
+
+    if refresh_token.client_id != client.id {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidGrant,
+        ));
+    }
+
+    // A rotated token is answered by revoking its family before anything else about the request
+    // is looked at, including whether the token has expired in the meantime
+    let reuse_detected = || {
+        warn!(
+            "Refresh token reuse detected for client {}, revoking token family",
+            client.id
+        );
+        match revoke_refresh_token_family(
+            &refresh_token.family,
+            authorization_store,
+            refresh_token_store,
+        ) {
+            Ok(()) => AccessTokenErrorResponse::new(AccessTokenError::InvalidGrant),
+            Err(_) => AccessTokenErrorResponse::new(AccessTokenError::ServerError),
+        }
+    };
+
+    if refresh_token.rotated {
+        return Err(reuse_detected());
+    }
+
+    if refresh_token.jkt.is_some() && refresh_token.jkt != access_token_request.dpop_jkt {
+        let mut access_token_error_response =
+            AccessTokenErrorResponse::new(AccessTokenError::InvalidDpopProof);
+        access_token_error_response.error_description =
+            Some("Refresh token is bound to another DPoP key".to_string());
         return Err(access_token_error_response);
     }
 
+    let now = Utc::now();
+    if refresh_token.expires <= now || refresh_token.family_expires <= now {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidGrant,
+        ));
+    }
+
+    let scopes = match &access_token_request.scope {
+        None => refresh_token.scopes.clone(),
+        Some(scope) => {
+            let scopes: Vec<String> = scope.split_whitespace().map(str::to_string).collect();
+            if !scopes
+                .iter()
+                .all(|scope| refresh_token.scopes.contains(scope))
+            {
+                return Err(AccessTokenErrorResponse::new(
+                    AccessTokenError::InvalidScope,
+                ));
+            }
+
+            scopes
+        }
+    };
+
+    if refresh_token_store
+        .rotate_refresh_token(token)
+        .is_none_or(|previous| previous.rotated)
+    {
+        return Err(reuse_detected());
+    }
+
+    let refresh_token = new_refresh_token(
+        refresh_token.family,
+        client,
+        &refresh_token.scopes,
+        &refresh_token.owner,
+        refresh_token.family_expires,
+        refresh_token.jkt.clone(),
+        token_settings,
+    );
+
+    Ok((scopes, refresh_token))
+}
+
+/// Revokes all refresh tokens of a family together with the access tokens issued alongside them
+pub fn revoke_refresh_token_family<R: AuthorizationRepository, F: RefreshTokenRepository>(
+    family: &str,
+    authorization_store: &R,
+    refresh_token_store: &F,
+) -> Result<()> {
+    let revoked = refresh_token_store.revoke_refresh_token_family(family)?;
+    authorization_store.delete_authorizations_by_refresh_token(&revoked)
+}
+
+/// Restricts the scopes of a client credentials grant (RFC 6749 section 4.4) to those the client
+/// is allowed to request. Only confidential clients registered by a trusted party may use this
+/// grant.
+fn client_credentials_scopes(
+    access_token_request: &AccessTokenRequest,
+    client: &Client,
+) -> Result<Vec<String>, AccessTokenErrorResponse> {
+    if client.client_type != ClientType::Confidential || client.self_registered {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::UnauthorizedClient,
+        ));
+    }
+
+    let Some(scope) = &access_token_request.scope else {
+        return Ok(client.scopes.clone());
+    };
+
+    let scopes: Vec<String> = scope.split_whitespace().map(str::to_string).collect();
+    if !scopes.iter().all(|scope| client.scopes.contains(scope)) {
+        return Err(AccessTokenErrorResponse::new(
+            AccessTokenError::InvalidScope,
+        ));
+    }
+
+    Ok(scopes)
+}
+
+fn new_refresh_token(
+    family: String,
+    client: &Client,
+    scopes: &[String],
+    owner: &str,
+    family_expires: DateTime<Utc>,
+    jkt: Option<String>,
+    token_settings: &TokenSettings,
+) -> RefreshToken {
+    let now = Utc::now();
+    RefreshToken {
+        token: generate_token(),
+        family,
+        client_id: client.id.clone(),
+        scopes: scopes.to_vec(),
+        owner: owner.to_string(),
+        expires: family_expires.min(now + Duration::seconds(token_settings.refresh_token_idle_ttl)),
+        family_expires,
+        rotated: false,
+        jkt,
+    }
+}
+
+/// Claims of a JWT access token (RFC 9068 section 2.2). Tokens issued without a resource owner
+/// use the client as subject.
+#[derive(Serialize, Debug)]
+struct AccessTokenClaims<'a> {
+    iss: &'a str,
+    sub: &'a str,
+    aud: &'a str,
+    client_id: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    jti: String,
+    iat: i64,
+    exp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cnf: Option<Confirmation>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn issue_access_token<R: AuthorizationRepository, K: KeyRepository>(
+    client: &Client,
+    scopes: Vec<String>,
+    owner: Option<String>,
+    refresh_token: Option<String>,
+    jkt: Option<String>,
+    token_settings: &TokenSettings,
+    authorization_store: &R,
+    key_store: &K,
+) -> Result<AccessTokenResponse, AccessTokenErrorResponse> {
+    let now = Utc::now();
+    let expires = now + Duration::seconds(ACCESS_TOKEN_TTL);
+    let access_token = match client.access_token_format {
+        AccessTokenFormat::Opaque => generate_token(),
+        AccessTokenFormat::Jwt => {
+            let claims = AccessTokenClaims {
+                iss: &token_settings.issuer,
+                sub: owner.as_deref().unwrap_or(&client.id),
+                aud: &token_settings.audience,
+                client_id: &client.id,
+                scope: (!scopes.is_empty()).then(|| scopes.join(" ")),
+                jti: generate_token(),
+                iat: now.timestamp(),
+                exp: expires.timestamp(),
+                cnf: jkt.clone().map(|jkt| Confirmation { jkt }),
+            };
+
+            let Some(signing_key) = key_store.read_signing_key() else {
+                error!("No signing key available for JWT access tokens");
+                return Err(AccessTokenErrorResponse::new(AccessTokenError::ServerError));
+            };
+
+            signing_key.sign("at+jwt", &claims).map_err(|error| {
+                error!("Could not sign access token: {error}");
+                AccessTokenErrorResponse::new(AccessTokenError::ServerError)
+            })?
+        }
+    };
+
+    let authorization = Authorization {
+        access_token,
+        client_id: client.id.clone(),
+        scopes,
+        owner,
+        created: now,
+        expires,
+        refresh_token,
+        jkt,
+    };
+
     let access_token_reponse = AccessTokenResponse {
-        access_token: "foobarbaz".to_string(),
-        token_type: TokenType::Bearer,
-        expires_in: 3600,
-        refresh_token: None,
-        scope: None,
+        access_token: authorization.access_token.clone(),
+        token_type: authorization.token_type(),
+        expires_in: ACCESS_TOKEN_TTL,
+        refresh_token: authorization.refresh_token.clone(),
+        scope: (!authorization.scopes.is_empty()).then(|| authorization.scopes.join(" ")),
+        id_token: None,
     };
 
+    if authorization_store
+        .create_authorization(authorization)
+        .is_err()
+    {
+        return Err(AccessTokenErrorResponse::new(AccessTokenError::ServerError));
+    }
+
     Ok(access_token_reponse)
 }
 
+/// Verifies a `code_verifier` against the challenge stored with the authorization code
+/// (RFC 7636 section 4.6). A verifier without a stored challenge is rejected as well to prevent
+/// PKCE downgrade attacks.
+fn verify_code_verifier(
+    code_challenge: Option<&str>,
+    code_challenge_method: CodeChallengeMethod,
+    code_verifier: Option<&str>,
+) -> bool {
+    match (code_challenge, code_verifier) {
+        (None, None) => true,
+        (Some(code_challenge), Some(code_verifier)) if is_pkce_value(code_verifier) => {
+            match code_challenge_method {
+                CodeChallengeMethod::Plain => code_verifier == code_challenge,
+                CodeChallengeMethod::S256 => {
+                    let digest = Sha256::digest(code_verifier.as_bytes());
+                    BASE64_URL_SAFE_NO_PAD.encode(digest) == code_challenge
+                }
+            }
+        }
+        _ => false,
+    }
+}
+
+pub fn generate_token() -> String {
+    let rng = thread_rng();
+    rng.sample_iter(distributions::Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::core::token::{access_token, AccessTokenRequest, GrantType, TokenType};
+    use argon2::{
+        Argon2, PasswordHasher,
+        password_hash::{SaltString, rand_core::OsRng},
+    };
+    use chrono::{Duration, Utc};
+
+    use crate::{
+        core::{
+            authorization::{
+                AuthorizationCode, AuthorizationCodeRepository, ClientRepository,
+                CodeChallengeMethod, TokenEndpointAuthMethod,
+            },
+            device::{DeviceAuthorizationRequest, decide_device_code, device_authorization},
+            jose::{SigningKey, verify},
+            token::{
+                AccessTokenError, AccessTokenErrorResponse, AccessTokenRequest,
+                AccessTokenResponse, AuthorizationRepository, ClientCredentials, GrantType,
+                RefreshTokenRepository, TokenSettings, TokenType, access_token,
+                authenticate_client, verify_code_verifier,
+            },
+        },
+        repository::{
+            authorization::MapAuthorizationRepository,
+            authorization_code::MapAuthorizationCodeRepository,
+            client::{MapClientRepository, TestClientRepository},
+            device_code::MapDeviceCodeRepository,
+            key::MapKeyRepository,
+            owner::MapOwnerRepository,
+            refresh_token::MapRefreshTokenRepository,
+        },
+    };
+
+    fn create_client_store() -> TestClientRepository {
+        TestClientRepository {
+            client_ids: vec!["s6BhdRkqt3".to_string(), "other".to_string()],
+        }
+    }
+
+    fn create_code_store(expires_in: i64) -> MapAuthorizationCodeRepository {
+        let code_store = MapAuthorizationCodeRepository::default();
+        let created = Utc::now();
+        code_store
+            .create_authorization_code(AuthorizationCode {
+                code: "foobar".to_string(),
+                client_id: "s6BhdRkqt3".to_string(),
+                redirect_uri: Some("https://client.example.com/cb".to_string()),
+                scopes: vec!["read".to_string(), "write".to_string()],
+                owner: "alice".to_string(),
+                code_challenge: Some("3rVY5lb1B1U6wNRRVjUQviq87vkocpnjj0LyG6p3vu0".to_string()),
+                code_challenge_method: CodeChallengeMethod::S256,
+                nonce: None,
+                auth_time: created,
+                sid: None,
+                expires: created + Duration::seconds(expires_in),
+            })
+            .unwrap();
+
+        code_store
+    }
+
+    fn client_credentials(access_token_request: &AccessTokenRequest) -> ClientCredentials {
+        ClientCredentials::from_request(None, access_token_request.client_id.clone(), None).unwrap()
+    }
+
+    fn client_credentials_for(client_id: &str) -> ClientCredentials {
+        ClientCredentials::from_request(None, Some(client_id.to_string()), None).unwrap()
+    }
+
+    fn create_request(client_id: &str, redirect_uri: &str) -> AccessTokenRequest {
+        AccessTokenRequest {
+            grant_type: GrantType::AuthorizationCode,
+            code: Some("foobar".to_string()),
+            redirect_uri: Some(redirect_uri.to_string()),
+            client_id: Some(client_id.to_string()),
+            code_verifier: Some("dBjftJeZ4CK-pfYr1EYnhK4ZZZ7vpeBZqPAiM7ZwqAk".to_string()),
+            ..Default::default()
+        }
+    }
 
     #[tokio::test]
     async fn test_access_token() {
+        let access_token_request = create_request("s6BhdRkqt3", "https://client.example.com/cb");
+        let code_store = create_code_store(600);
+        let authorization_store = MapAuthorizationRepository::default();
+
+        let client_credentials = client_credentials(&access_token_request);
+        let response = access_token(
+            access_token_request,
+            client_credentials,
+            &TokenSettings::default(),
+            &create_client_store(),
+            &code_store,
+            &authorization_store,
+            &MapRefreshTokenRepository::default(),
+            &MapDeviceCodeRepository::default(),
+            &MapOwnerRepository::default(),
+            &MapKeyRepository::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.token_type, TokenType::Bearer);
+        assert_eq!(response.scope, Some("read write".to_string()));
+        assert_ne!(response.access_token, "foobarbaz");
+
+        let authorization = authorization_store
+            .read_authorization(&response.access_token)
+            .unwrap();
+        assert_eq!(authorization.client_id, "s6BhdRkqt3");
+        assert_eq!(authorization.owner, Some("alice".to_string()));
+        assert!(response.refresh_token.is_some());
+        assert_eq!(authorization.refresh_token, response.refresh_token);
+        assert_eq!(
+            (authorization.expires - authorization.created).num_seconds(),
+            response.expires_in
+        );
+    }
+
+    async fn refresh(
+        refresh_token: &str,
+        scope: Option<&str>,
+        token_settings: &TokenSettings,
+        refresh_token_store: &MapRefreshTokenRepository,
+    ) -> Result<AccessTokenResponse, AccessTokenErrorResponse> {
         let access_token_request = AccessTokenRequest {
-            grant_type: GrantType::AuthorizationCode,
-            code: "foobar".to_string(),
-            redirect_uri: None,
-            client_id: None,
+            grant_type: GrantType::RefreshToken,
+            refresh_token: Some(refresh_token.to_string()),
+            scope: scope.map(str::to_string),
+            client_id: Some("s6BhdRkqt3".to_string()),
+            ..Default::default()
         };
 
-        let response = access_token(access_token_request).await.unwrap();
-        assert_eq!(response.token_type, TokenType::Bearer);
+        let client_credentials = client_credentials(&access_token_request);
+        access_token(
+            access_token_request,
+            client_credentials,
+            token_settings,
+            &create_client_store(),
+            &MapAuthorizationCodeRepository::default(),
+            &MapAuthorizationRepository::default(),
+            refresh_token_store,
+            &MapDeviceCodeRepository::default(),
+            &MapOwnerRepository::default(),
+            &MapKeyRepository::default(),
+        )
+        .await
+    }
+
+    async fn initial_refresh_token(
+        token_settings: &TokenSettings,
+        refresh_token_store: &MapRefreshTokenRepository,
+    ) -> String {
+        let access_token_request = create_request("s6BhdRkqt3", "https://client.example.com/cb");
+        let client_credentials = client_credentials(&access_token_request);
+        let response = access_token(
+            access_token_request,
+            client_credentials,
+            token_settings,
+            &create_client_store(),
+            &create_code_store(600),
+            &MapAuthorizationRepository::default(),
+            refresh_token_store,
+            &MapDeviceCodeRepository::default(),
+            &MapOwnerRepository::default(),
+            &MapKeyRepository::default(),
+        )
+        .await
+        .unwrap();
+
+        response.refresh_token.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_rotation() {
+        let token_settings = TokenSettings::default();
+        let refresh_token_store = MapRefreshTokenRepository::default();
+        let first_token = initial_refresh_token(&token_settings, &refresh_token_store).await;
+
+        let response = refresh(
+            &first_token,
+            Some("read"),
+            &token_settings,
+            &refresh_token_store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.scope, Some("read".to_string()));
+        let second_token = response.refresh_token.unwrap();
+        assert_ne!(second_token, first_token);
+
+        let stored_token = refresh_token_store
+            .read_refresh_token(&second_token)
+            .unwrap();
+        assert_eq!(stored_token.scopes, vec!["read", "write"]);
+
+        let response = refresh(&second_token, None, &token_settings, &refresh_token_store)
+            .await
+            .unwrap();
+        assert_eq!(response.scope, Some("read write".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_reuse_revokes_family() {
+        let token_settings = TokenSettings::default();
+        let refresh_token_store = MapRefreshTokenRepository::default();
+        let first_token = initial_refresh_token(&token_settings, &refresh_token_store).await;
+
+        let second_token = refresh(&first_token, None, &token_settings, &refresh_token_store)
+            .await
+            .unwrap()
+            .refresh_token
+            .unwrap();
+
+        // Reuse is detected before the requested scope is checked
+        let error_response = refresh(
+            &first_token,
+            Some("admin"),
+            &token_settings,
+            &refresh_token_store,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error_response.error, AccessTokenError::InvalidGrant);
+
+        let error_response = refresh(&second_token, None, &token_settings, &refresh_token_store)
+            .await
+            .unwrap_err();
+        assert_eq!(error_response.error, AccessTokenError::InvalidGrant);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_expired_reuse_revokes_family() {
+        let token_settings = TokenSettings::default();
+        let refresh_token_store = MapRefreshTokenRepository::default();
+        let first_token = initial_refresh_token(&token_settings, &refresh_token_store).await;
+
+        let second_token = refresh(&first_token, None, &token_settings, &refresh_token_store)
+            .await
+            .unwrap()
+            .refresh_token
+            .unwrap();
+
+        refresh_token_store
+            .data
+            .lock()
+            .unwrap()
+            .get_mut(&first_token)
+            .unwrap()
+            .expires = Utc::now() - Duration::seconds(1);
+
+        let error_response = refresh(&first_token, None, &token_settings, &refresh_token_store)
+            .await
+            .unwrap_err();
+        assert_eq!(error_response.error, AccessTokenError::InvalidGrant);
+        assert!(
+            refresh_token_store
+                .read_refresh_token(&second_token)
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_invalid_scope() {
+        let token_settings = TokenSettings::default();
+        let refresh_token_store = MapRefreshTokenRepository::default();
+        let refresh_token = initial_refresh_token(&token_settings, &refresh_token_store).await;
+
+        let error_response = refresh(
+            &refresh_token,
+            Some("read admin"),
+            &token_settings,
+            &refresh_token_store,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error_response.error, AccessTokenError::InvalidScope);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_token_lifetimes() {
+        for token_settings in [
+            TokenSettings {
+                refresh_token_idle_ttl: 0,
+                ..Default::default()
+            },
+            TokenSettings {
+                refresh_token_absolute_ttl: 0,
+                ..Default::default()
+            },
+        ] {
+            let refresh_token_store = MapRefreshTokenRepository::default();
+            let refresh_token = initial_refresh_token(&token_settings, &refresh_token_store).await;
+
+            let error_response =
+                refresh(&refresh_token, None, &token_settings, &refresh_token_store)
+                    .await
+                    .unwrap_err();
+            assert_eq!(error_response.error, AccessTokenError::InvalidGrant);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_access_token_code_reuse() {
+        let code_store = create_code_store(600);
+        let authorization_store = MapAuthorizationRepository::default();
+
+        for expected_ok in [true, false] {
+            let access_token_request =
+                create_request("s6BhdRkqt3", "https://client.example.com/cb");
+
+            let client_credentials = client_credentials(&access_token_request);
+            let response = access_token(
+                access_token_request,
+                client_credentials,
+                &TokenSettings::default(),
+                &create_client_store(),
+                &code_store,
+                &authorization_store,
+                &MapRefreshTokenRepository::default(),
+                &MapDeviceCodeRepository::default(),
+                &MapOwnerRepository::default(),
+                &MapKeyRepository::default(),
+            )
+            .await;
+            assert_eq!(response.is_ok(), expected_ok);
+            if let Err(error_response) = response {
+                assert_eq!(error_response.error, AccessTokenError::InvalidGrant);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_access_token_invalid_grant() {
+        let cases = [
+            (600, "other", "https://client.example.com/cb"),
+            (600, "s6BhdRkqt3", "https://attacker.example.com/cb"),
+            (-1, "s6BhdRkqt3", "https://client.example.com/cb"),
+        ];
+
+        for (expires_in, client_id, redirect_uri) in cases {
+            let access_token_request = create_request(client_id, redirect_uri);
+            let authorization_store = MapAuthorizationRepository::default();
+
+            let client_credentials = client_credentials(&access_token_request);
+            let response = access_token(
+                access_token_request,
+                client_credentials,
+                &TokenSettings::default(),
+                &create_client_store(),
+                &create_code_store(expires_in),
+                &authorization_store,
+                &MapRefreshTokenRepository::default(),
+                &MapDeviceCodeRepository::default(),
+                &MapOwnerRepository::default(),
+                &MapKeyRepository::default(),
+            )
+            .await;
+            assert_eq!(response.unwrap_err().error, AccessTokenError::InvalidGrant);
+            assert!(authorization_store.data.lock().unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_access_token_unknown_client() {
+        let access_token_request = create_request("unknown", "https://client.example.com/cb");
+
+        let client_credentials = client_credentials(&access_token_request);
+        let response = access_token(
+            access_token_request,
+            client_credentials,
+            &TokenSettings::default(),
+            &create_client_store(),
+            &create_code_store(600),
+            &MapAuthorizationRepository::default(),
+            &MapRefreshTokenRepository::default(),
+            &MapDeviceCodeRepository::default(),
+            &MapOwnerRepository::default(),
+            &MapKeyRepository::default(),
+        )
+        .await;
+        assert_eq!(response.unwrap_err().error, AccessTokenError::InvalidClient);
+    }
+
+    #[tokio::test]
+    async fn test_access_token_invalid_code_verifier() {
+        for code_verifier in [None, Some("dBjftJeZ4CK-pfYr1EYnhK4ZZZ7vpeBZqPAiM7ZwqAX")] {
+            let access_token_request = AccessTokenRequest {
+                code_verifier: code_verifier.map(str::to_string),
+                ..create_request("s6BhdRkqt3", "https://client.example.com/cb")
+            };
+
+            let client_credentials = client_credentials(&access_token_request);
+            let response = access_token(
+                access_token_request,
+                client_credentials,
+                &TokenSettings::default(),
+                &create_client_store(),
+                &create_code_store(600),
+                &MapAuthorizationRepository::default(),
+                &MapRefreshTokenRepository::default(),
+                &MapDeviceCodeRepository::default(),
+                &MapOwnerRepository::default(),
+                &MapKeyRepository::default(),
+            )
+            .await;
+            assert_eq!(response.unwrap_err().error, AccessTokenError::InvalidGrant);
+        }
+    }
+
+    #[test]
+    fn test_client_credentials_from_request() {
+        let basic_credentials = Some(("s6BhdRkqt3".to_string(), "gX1fBat3bV".to_string()));
+
+        let client_credentials =
+            ClientCredentials::from_request(basic_credentials.clone(), None, None).unwrap();
+        assert_eq!(
+            client_credentials.method,
+            TokenEndpointAuthMethod::ClientSecretBasic
+        );
+
+        let client_credentials = ClientCredentials::from_request(
+            None,
+            Some("s6BhdRkqt3".to_string()),
+            Some("gX1fBat3bV".to_string()),
+        )
+        .unwrap();
+        assert_eq!(
+            client_credentials.method,
+            TokenEndpointAuthMethod::ClientSecretPost
+        );
+
+        let error_response = ClientCredentials::from_request(
+            basic_credentials,
+            Some("s6BhdRkqt3".to_string()),
+            Some("gX1fBat3bV".to_string()),
+        )
+        .unwrap_err();
+        assert_eq!(error_response.error, AccessTokenError::InvalidRequest);
+
+        let error_response = ClientCredentials::from_request(None, None, None).unwrap_err();
+        assert_eq!(error_response.error, AccessTokenError::InvalidClient);
+    }
+
+    fn create_confidential_client_store() -> MapClientRepository {
+        let salt = SaltString::generate(&mut OsRng);
+        let secret_hash = Argon2::default()
+            .hash_password(b"gX1fBat3bV", &salt)
+            .unwrap()
+            .to_string();
+        let input = format!(
+            r#"
+            [s6BhdRkqt3]
+            name = "Confidential Client"
+            client_type = "confidential"
+            redirect_uris = []
+            client_secret_hash = "{secret_hash}"
+            scopes = ["read", "write"]
+
+            [public]
+            name = "Public Client"
+            client_type = "public"
+            redirect_uris = []
+
+            [registered]
+            name = "Self-registered Client"
+            client_type = "confidential"
+            redirect_uris = ["https://client.example.com/cb"]
+            client_secret_hash = "{secret_hash}"
+            self_registered = true
+        "#
+        );
+
+        MapClientRepository::try_from_toml(&input).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_client_credentials() {
+        let client_store = create_confidential_client_store();
+        let cases = [
+            ("s6BhdRkqt3", Some("gX1fBat3bV"), None, Ok("read write")),
+            ("s6BhdRkqt3", Some("gX1fBat3bV"), Some("read"), Ok("read")),
+            (
+                "s6BhdRkqt3",
+                Some("gX1fBat3bV"),
+                Some("read admin"),
+                Err(AccessTokenError::InvalidScope),
+            ),
+            (
+                "public",
+                None,
+                None,
+                Err(AccessTokenError::UnauthorizedClient),
+            ),
+            (
+                "registered",
+                Some("gX1fBat3bV"),
+                None,
+                Err(AccessTokenError::UnauthorizedClient),
+            ),
+        ];
+
+        for (client_id, client_secret, scope, expected) in cases {
+            let access_token_request = AccessTokenRequest {
+                grant_type: GrantType::ClientCredentials,
+                scope: scope.map(str::to_string),
+                ..Default::default()
+            };
+            let client_credentials = ClientCredentials::from_request(
+                client_secret
+                    .map(|client_secret| (client_id.to_string(), client_secret.to_string())),
+                Some(client_id.to_string()),
+                None,
+            )
+            .unwrap();
+            let authorization_store = MapAuthorizationRepository::default();
+
+            let response = access_token(
+                access_token_request,
+                client_credentials,
+                &TokenSettings::default(),
+                &client_store,
+                &MapAuthorizationCodeRepository::default(),
+                &authorization_store,
+                &MapRefreshTokenRepository::default(),
+                &MapDeviceCodeRepository::default(),
+                &MapOwnerRepository::default(),
+                &MapKeyRepository::default(),
+            )
+            .await;
+
+            match expected {
+                Ok(expected_scope) => {
+                    let response = response.unwrap();
+                    assert_eq!(response.scope.as_deref(), Some(expected_scope));
+                    assert_eq!(response.refresh_token, None);
+
+                    let authorization = authorization_store
+                        .read_authorization(&response.access_token)
+                        .unwrap();
+                    assert_eq!(authorization.owner, None);
+                    assert_eq!(authorization.client_id, client_id);
+                }
+                Err(expected_error) => {
+                    assert_eq!(response.unwrap_err().error, expected_error);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_authenticate_client() {
+        let client_store = create_confidential_client_store();
+
+        let credentials =
+            |client_id: &str, client_secret: Option<&str>, method| ClientCredentials {
+                client_id: client_id.to_string(),
+                client_secret: client_secret.map(str::to_string),
+                method,
+            };
+
+        let client = authenticate_client(
+            credentials(
+                "s6BhdRkqt3",
+                Some("gX1fBat3bV"),
+                TokenEndpointAuthMethod::ClientSecretBasic,
+            ),
+            &client_store,
+        )
+        .unwrap();
+        assert_eq!(client.id, "s6BhdRkqt3");
+
+        assert!(
+            authenticate_client(
+                credentials("public", None, TokenEndpointAuthMethod::None),
+                &client_store
+            )
+            .is_ok()
+        );
+
+        let failures = [
+            credentials(
+                "s6BhdRkqt3",
+                Some("wrong"),
+                TokenEndpointAuthMethod::ClientSecretBasic,
+            ),
+            credentials(
+                "s6BhdRkqt3",
+                Some("gX1fBat3bV"),
+                TokenEndpointAuthMethod::ClientSecretPost,
+            ),
+            credentials("s6BhdRkqt3", None, TokenEndpointAuthMethod::None),
+            credentials(
+                "public",
+                Some("gX1fBat3bV"),
+                TokenEndpointAuthMethod::ClientSecretBasic,
+            ),
+            credentials("unknown", None, TokenEndpointAuthMethod::None),
+        ];
+
+        for client_credentials in failures {
+            let error_response =
+                authenticate_client(client_credentials, &client_store).unwrap_err();
+            assert_eq!(error_response.error, AccessTokenError::InvalidClient);
+        }
+    }
+
+    #[test]
+    fn test_verify_code_verifier() {
+        let code_verifier = "dBjftJeZ4CK-pfYr1EYnhK4ZZZ7vpeBZqPAiM7ZwqAk";
+        let code_challenge = "3rVY5lb1B1U6wNRRVjUQviq87vkocpnjj0LyG6p3vu0";
+
+        assert!(verify_code_verifier(
+            Some(code_challenge),
+            CodeChallengeMethod::S256,
+            Some(code_verifier)
+        ));
+        assert!(verify_code_verifier(
+            Some(code_verifier),
+            CodeChallengeMethod::Plain,
+            Some(code_verifier)
+        ));
+        assert!(!verify_code_verifier(
+            Some(code_challenge),
+            CodeChallengeMethod::Plain,
+            Some(code_verifier)
+        ));
+        assert!(!verify_code_verifier(
+            None,
+            CodeChallengeMethod::Plain,
+            Some(code_verifier)
+        ));
+        assert!(verify_code_verifier(None, CodeChallengeMethod::Plain, None));
+    }
+
+    #[test]
+    fn test_deserialize_unsupported_grant_type() {
+        let access_token_request: AccessTokenRequest =
+            serde_urlencoded::from_str("grant_type=password&code=foobar").unwrap();
+        assert_eq!(access_token_request.grant_type, GrantType::Unsupported);
+    }
+
+    #[test]
+    fn test_deserialize_device_code_grant_type() {
+        let access_token_request: AccessTokenRequest = serde_urlencoded::from_str(
+            "grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Adevice_code&device_code=foobar",
+        )
+        .unwrap();
+        assert_eq!(access_token_request.grant_type, GrantType::DeviceCode);
+        assert_eq!(access_token_request.device_code, Some("foobar".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_device_code() {
+        let device_code_store = MapDeviceCodeRepository::default();
+        let authorization_store = MapAuthorizationRepository::default();
+        let client = create_client_store().read_client("s6BhdRkqt3").unwrap();
+        let device_authorization_response = device_authorization(
+            &DeviceAuthorizationRequest {
+                scope: Some("read".to_string()),
+                ..Default::default()
+            },
+            &client,
+            "https://keyper.example.com/device",
+            &device_code_store,
+        )
+        .unwrap();
+        decide_device_code(
+            &device_authorization_response.user_code,
+            "alice",
+            true,
+            &device_code_store,
+        )
+        .unwrap();
+
+        let access_token_request = AccessTokenRequest {
+            grant_type: GrantType::DeviceCode,
+            device_code: Some(device_authorization_response.device_code),
+            client_id: Some("s6BhdRkqt3".to_string()),
+            ..Default::default()
+        };
+        let client_credentials = client_credentials(&access_token_request);
+        let response = access_token(
+            access_token_request,
+            client_credentials,
+            &TokenSettings::default(),
+            &create_client_store(),
+            &MapAuthorizationCodeRepository::default(),
+            &authorization_store,
+            &MapRefreshTokenRepository::default(),
+            &device_code_store,
+            &MapOwnerRepository::default(),
+            &MapKeyRepository::default(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.scope, Some("read".to_string()));
+        assert!(response.refresh_token.is_some());
+
+        let authorization = authorization_store
+            .read_authorization(&response.access_token)
+            .unwrap();
+        assert_eq!(authorization.owner, Some("alice".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_jwt_access_token() {
+        let client_store = MapClientRepository::try_from_toml(
+            r#"
+            [s6BhdRkqt3]
+            name = "Example Client"
+            client_type = "public"
+            redirect_uris = ["https://client.example.com/cb"]
+            access_token_format = "jwt"
+        "#,
+        )
+        .unwrap();
+        let signing_key = SigningKey::from_pem(include_str!("testdata/ed25519.pem")).unwrap();
+        let key_store = MapKeyRepository::from_signing_keys(vec![signing_key.clone()]);
+        let token_settings = TokenSettings {
+            issuer: "https://keyper.example.com".to_string(),
+            audience: "https://api.example.com".to_string(),
+            ..Default::default()
+        };
+        let authorization_store = MapAuthorizationRepository::default();
+
+        let access_token_request = create_request("s6BhdRkqt3", "https://client.example.com/cb");
+        let credentials = client_credentials(&access_token_request);
+        let response = access_token(
+            access_token_request,
+            credentials,
+            &token_settings,
+            &client_store,
+            &create_code_store(600),
+            &authorization_store,
+            &MapRefreshTokenRepository::default(),
+            &MapDeviceCodeRepository::default(),
+            &MapOwnerRepository::default(),
+            &key_store,
+        )
+        .await
+        .unwrap();
+
+        let (header, claims) = verify(&signing_key, &response.access_token);
+        assert_eq!(header["typ"], "at+jwt");
+        assert_eq!(header["kid"], signing_key.kid);
+        assert_eq!(claims["iss"], "https://keyper.example.com");
+        assert_eq!(claims["sub"], "alice");
+        assert_eq!(claims["aud"], "https://api.example.com");
+        assert_eq!(claims["client_id"], "s6BhdRkqt3");
+        assert_eq!(claims["scope"], "read write");
+        assert!(claims["jti"].is_string());
+        assert_eq!(
+            claims["exp"].as_i64().unwrap() - claims["iat"].as_i64().unwrap(),
+            response.expires_in
+        );
+        assert!(
+            authorization_store
+                .read_authorization(&response.access_token)
+                .is_some()
+        );
+
+        let access_token_request = create_request("s6BhdRkqt3", "https://client.example.com/cb");
+        let credentials = client_credentials(&access_token_request);
+        let response = access_token(
+            access_token_request,
+            credentials,
+            &token_settings,
+            &client_store,
+            &create_code_store(600),
+            &authorization_store,
+            &MapRefreshTokenRepository::default(),
+            &MapDeviceCodeRepository::default(),
+            &MapOwnerRepository::default(),
+            &MapKeyRepository::default(),
+        )
+        .await;
+        assert_eq!(response.unwrap_err().error, AccessTokenError::ServerError);
+    }
+
+    #[tokio::test]
+    async fn test_dpop_bound_access_token() {
+        let client_store = MapClientRepository::try_from_toml(
+            r#"
+            [s6BhdRkqt3]
+            name = "Example Client"
+            client_type = "public"
+            redirect_uris = ["https://client.example.com/cb"]
+            access_token_format = "jwt"
+            dpop_bound_access_tokens = true
+        "#,
+        )
+        .unwrap();
+        let signing_key = SigningKey::from_pem(include_str!("testdata/ed25519.pem")).unwrap();
+        let key_store = MapKeyRepository::from_signing_keys(vec![signing_key.clone()]);
+        let token_settings = TokenSettings::default();
+        let authorization_store = MapAuthorizationRepository::default();
+        let refresh_token_store = MapRefreshTokenRepository::default();
+        let jkt = "0ZcOCORZNYy-DWpqq30jZyJGHTN0d2HglBV3uiguA4I";
+
+        let access_token_request = create_request("s6BhdRkqt3", "https://client.example.com/cb");
+        let credentials = client_credentials(&access_token_request);
+        let error_response = access_token(
+            access_token_request,
+            credentials,
+            &token_settings,
+            &client_store,
+            &create_code_store(600),
+            &authorization_store,
+            &refresh_token_store,
+            &MapDeviceCodeRepository::default(),
+            &MapOwnerRepository::default(),
+            &key_store,
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error_response.error, AccessTokenError::InvalidDpopProof);
+
+        let access_token_request = AccessTokenRequest {
+            dpop_jkt: Some(jkt.to_string()),
+            ..create_request("s6BhdRkqt3", "https://client.example.com/cb")
+        };
+        let credentials = client_credentials(&access_token_request);
+        let response = access_token(
+            access_token_request,
+            credentials,
+            &token_settings,
+            &client_store,
+            &create_code_store(600),
+            &authorization_store,
+            &refresh_token_store,
+            &MapDeviceCodeRepository::default(),
+            &MapOwnerRepository::default(),
+            &key_store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.token_type, TokenType::Dpop);
+        let (_, claims) = verify(&signing_key, &response.access_token);
+        assert_eq!(claims["cnf"]["jkt"], jkt);
+        let authorization = authorization_store
+            .read_authorization(&response.access_token)
+            .unwrap();
+        assert_eq!(authorization.jkt, Some(jkt.to_string()));
+
+        let refresh_token = response.refresh_token.unwrap();
+        let stored_token = refresh_token_store
+            .read_refresh_token(&refresh_token)
+            .unwrap();
+        assert_eq!(stored_token.jkt, Some(jkt.to_string()));
+
+        for dpop_jkt in [None, Some("other".to_string())] {
+            let access_token_request = AccessTokenRequest {
+                grant_type: GrantType::RefreshToken,
+                refresh_token: Some(refresh_token.clone()),
+                client_id: Some("s6BhdRkqt3".to_string()),
+                dpop_jkt,
+                ..Default::default()
+            };
+            let credentials = client_credentials(&access_token_request);
+            let error_response = access_token(
+                access_token_request,
+                credentials,
+                &token_settings,
+                &client_store,
+                &MapAuthorizationCodeRepository::default(),
+                &authorization_store,
+                &refresh_token_store,
+                &MapDeviceCodeRepository::default(),
+                &MapOwnerRepository::default(),
+                &key_store,
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(error_response.error, AccessTokenError::InvalidDpopProof);
+        }
+
+        let access_token_request = AccessTokenRequest {
+            grant_type: GrantType::RefreshToken,
+            refresh_token: Some(refresh_token.clone()),
+            client_id: Some("s6BhdRkqt3".to_string()),
+            dpop_jkt: Some(jkt.to_string()),
+            ..Default::default()
+        };
+        let credentials = client_credentials(&access_token_request);
+        let response = access_token(
+            access_token_request,
+            credentials,
+            &token_settings,
+            &client_store,
+            &MapAuthorizationCodeRepository::default(),
+            &authorization_store,
+            &refresh_token_store,
+            &MapDeviceCodeRepository::default(),
+            &MapOwnerRepository::default(),
+            &key_store,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.token_type, TokenType::Dpop);
+        let stored_token = refresh_token_store
+            .read_refresh_token(&response.refresh_token.unwrap())
+            .unwrap();
+        assert_eq!(stored_token.jkt, Some(jkt.to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_access_token_openid() {
+        let code_store = MapAuthorizationCodeRepository::default();
+        let auth_time = Utc::now();
+        code_store
+            .create_authorization_code(AuthorizationCode {
+                code: "foobar".to_string(),
+                client_id: "s6BhdRkqt3".to_string(),
+                redirect_uri: Some("https://client.example.com/cb".to_string()),
+                scopes: vec!["openid".to_string(), "profile".to_string()],
+                owner: "alice".to_string(),
+                code_challenge: None,
+                code_challenge_method: CodeChallengeMethod::Plain,
+                nonce: Some("n-0S6_WzA2Mj".to_string()),
+                auth_time,
+                sid: None,
+                expires: auth_time + Duration::seconds(600),
+            })
+            .unwrap();
+        let owner_store = MapOwnerRepository::try_from_toml(
+            r#"
+            [alice]
+            email = "alice@example.com"
+            hash = "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaA"
+            "#,
+        )
+        .unwrap();
+        let signing_key = SigningKey::from_pem(include_str!("testdata/p256.pem")).unwrap();
+
+        let access_token_request = AccessTokenRequest {
+            code_verifier: None,
+            ..create_request("s6BhdRkqt3", "https://client.example.com/cb")
+        };
+        let client_credentials = client_credentials(&access_token_request);
+        let response = access_token(
+            access_token_request,
+            client_credentials,
+            &TokenSettings::default(),
+            &create_client_store(),
+            &code_store,
+            &MapAuthorizationRepository::default(),
+            &MapRefreshTokenRepository::default(),
+            &MapDeviceCodeRepository::default(),
+            &owner_store,
+            &MapKeyRepository::from_signing_keys(vec![signing_key.clone()]),
+        )
+        .await
+        .unwrap();
+
+        let (_, claims) = verify(&signing_key, &response.id_token.unwrap());
+        assert_eq!(claims["sub"], "alice");
+        assert_eq!(claims["aud"], "s6BhdRkqt3");
+        assert_eq!(claims["nonce"], "n-0S6_WzA2Mj");
+        assert_eq!(claims["auth_time"], auth_time.timestamp());
+        assert_eq!(claims["preferred_username"], "alice");
+        assert_eq!(
+            claims["at_hash"],
+            signing_key.left_hash(&response.access_token)
+        );
+
+        let response = access_token(
+            create_request("s6BhdRkqt3", "https://client.example.com/cb"),
+            client_credentials_for("s6BhdRkqt3"),
+            &TokenSettings::default(),
+            &create_client_store(),
+            &create_code_store(600),
+            &MapAuthorizationRepository::default(),
+            &MapRefreshTokenRepository::default(),
+            &MapDeviceCodeRepository::default(),
+            &owner_store,
+            &MapKeyRepository::default(),
+        )
+        .await
+        .unwrap();
+        assert!(response.id_token.is_none());
     }
 }
@@ -3,11 +3,29 @@ mod cli;
 mod core;
 mod repository;
 
-use anyhow::Result;
-use api::RouterState;
-use repository::client::TestClientRepository;
-use std::{env, process::ExitCode};
-use tracing::{error, info};
+use anyhow::{Context, Result};
+use api::{RouterState, ServerSettings};
+use core::jose::SigningKey;
+use core::key::{self, KeySettings};
+use core::token::TokenSettings;
+use repository::authorization::MapAuthorizationRepository;
+use repository::authorization_code::MapAuthorizationCodeRepository;
+use repository::client::MapClientRepository;
+use repository::device_code::MapDeviceCodeRepository;
+use repository::dpop::MapDpopRepository;
+use repository::key::MapKeyRepository;
+use repository::owner::MapOwnerRepository;
+use repository::pushed_request::MapPushedRequestRepository;
+use repository::refresh_token::MapRefreshTokenRepository;
+use repository::session::MapSessionRepository;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::{env, path::Path, process::ExitCode, sync::Arc, time::Duration};
+use tracing::{error, info, warn};
+
+/// How often the key set is reloaded and checked for a due rotation
+const KEY_ROTATION_CHECK_INTERVAL: Duration = Duration::from_secs(60);
 
 #[tokio::main]
 async fn main() -> ExitCode {
@@ -35,9 +53,107 @@ async fn run() -> Result<()> {
         return Ok(());
     }
 
-    info!("Creating client factory");
-    let client_factory = TestClientRepository {
-        client_ids: vec!["foobar".to_string()],
+    let default_key_settings = KeySettings::default();
+    let key_settings = KeySettings {
+        rotation_interval: params
+            .key_rotation_interval
+            .unwrap_or(default_key_settings.rotation_interval),
+        ..default_key_settings
+    };
+
+    if params.rotate_keys {
+        let key_set = params
+            .key_set
+            .context("Rotating keys requires a key set file")?;
+        let key_store = load_key_set(&key_set)?;
+        key::rotate_keys(&key_settings, &key_store)?;
+        save_key_set(&key_set, &key_store)?;
+        info!("Rotated signing keys in {key_set}");
+        return Ok(());
+    }
+
+    let client_store = if let Some(clients) = params.clients {
+        info!("Loading clients from {clients}");
+        let input = fs::read_to_string(&clients)
+            .with_context(|| format!("Could not read clients from {clients}"))?;
+        MapClientRepository::try_from_toml(&input)
+            .with_context(|| format!("Could not parse clients from {clients}"))?
+    } else {
+        warn!("No clients file given, starting without registered clients");
+        MapClientRepository::default()
+    };
+
+    let owner_store = if let Some(owners) = params.owners {
+        info!("Loading resource owners from {owners}");
+        let input = fs::read_to_string(&owners)
+            .with_context(|| format!("Could not read resource owners from {owners}"))?;
+        MapOwnerRepository::try_from_toml(&input)
+            .with_context(|| format!("Could not parse resource owners from {owners}"))?
+    } else {
+        warn!("No owners file given, starting without resource owners");
+        MapOwnerRepository::default()
+    };
+
+    let key_store = match &params.key_set {
+        Some(key_set) if Path::new(key_set).exists() => {
+            if !params.signing_keys.is_empty() {
+                warn!("Ignoring signing keys given on the command line in favor of {key_set}");
+            }
+            load_key_set(key_set)?
+        }
+        _ => {
+            let mut signing_keys = Vec::new();
+            for signing_key in &params.signing_keys {
+                info!("Loading signing key from {signing_key}");
+                let input = fs::read_to_string(signing_key)
+                    .with_context(|| format!("Could not read signing key from {signing_key}"))?;
+                signing_keys.push(
+                    SigningKey::from_pem(&input).with_context(|| {
+                        format!("Could not parse signing key from {signing_key}")
+                    })?,
+                );
+            }
+            MapKeyRepository::from_signing_keys(signing_keys)
+        }
+    };
+    key::initialize_keys(&key_settings, &key_store)?;
+    key::rotate_keys_if_due(&key_settings, &key_store)?;
+    key::check_id_token_keys(&key_store)?;
+    if let Some(key_set) = &params.key_set {
+        save_key_set(key_set, &key_store)?;
+    } else {
+        warn!("No key set file given, generated signing keys are lost on restart");
+    }
+
+    let issuer = params
+        .issuer
+        .map(|issuer| issuer.trim_end_matches('/').to_string())
+        .unwrap_or_else(|| format!("http://localhost:{}", params.port));
+
+    let default_token_settings = TokenSettings::default();
+    let token_settings = TokenSettings {
+        audience: params.audience.unwrap_or_else(|| issuer.clone()),
+        issuer,
+        refresh_token_idle_ttl: params
+            .refresh_token_idle_ttl
+            .unwrap_or(default_token_settings.refresh_token_idle_ttl),
+        refresh_token_absolute_ttl: params
+            .refresh_token_absolute_ttl
+            .unwrap_or(default_token_settings.refresh_token_absolute_ttl),
+    };
+
+    let initial_access_token = match params.initial_access_token {
+        Some(initial_access_token) => {
+            info!("Loading initial access token from {initial_access_token}");
+            let input = fs::read_to_string(&initial_access_token).with_context(|| {
+                format!("Could not read initial access token from {initial_access_token}")
+            })?;
+            Some(input.trim().to_string())
+        }
+        None => {
+            warn!("No initial access token given, anyone may register clients without scopes");
+            None
+        }
     };
 
     info!("Creating template engine");
@@ -45,9 +161,25 @@ async fn run() -> Result<()> {
 
     info!("Creating router");
     let router_state = RouterState {
-        client_store: client_factory,
+        client_store,
+        authorization_code_store: MapAuthorizationCodeRepository::default(),
+        authorization_store: MapAuthorizationRepository::default(),
+        refresh_token_store: MapRefreshTokenRepository::default(),
+        device_code_store: MapDeviceCodeRepository::default(),
+        pushed_request_store: MapPushedRequestRepository::default(),
+        dpop_store: MapDpopRepository::default(),
+        key_store,
+        owner_store,
+        session_store: MapSessionRepository::default(),
+        token_settings,
+        server_settings: ServerSettings {
+            scopes_supported: params.scopes,
+            initial_access_token,
+        },
         template_engine,
     };
+    let router_state = Arc::new(router_state);
+    schedule_key_rotation(router_state.clone(), key_settings, params.key_set);
     let router = api::create_router(router_state);
 
     info!("Listening for requests on port {}", params.port);
@@ -55,3 +187,71 @@ async fn run() -> Result<()> {
 
     Ok(())
 }
+
+fn load_key_set(key_set: &str) -> Result<MapKeyRepository> {
+    if !Path::new(key_set).exists() {
+        return Ok(MapKeyRepository::default());
+    }
+
+    let input = fs::read_to_string(key_set)
+        .with_context(|| format!("Could not read signing keys from {key_set}"))?;
+    MapKeyRepository::try_from_toml(&input)
+        .with_context(|| format!("Could not parse signing keys from {key_set}"))
+}
+
+/// Writes the key set, which contains private keys, readable by the owner only
+fn save_key_set(key_set: &str, key_store: &MapKeyRepository) -> Result<()> {
+    let output = key_store.to_toml()?;
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(key_set)
+        .with_context(|| format!("Could not open {key_set} for writing"))?;
+    file.write_all(output.as_bytes())
+        .with_context(|| format!("Could not write signing keys to {key_set}"))
+}
+
+/// Periodically rotates the signing keys. The key set file is reloaded before every check, so
+/// rotations forced with `--rotate-keys` reach the running server.
+fn schedule_key_rotation(
+    router_state: Arc<RouterState>,
+    key_settings: KeySettings,
+    key_set: Option<String>,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(KEY_ROTATION_CHECK_INTERVAL);
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            let key_store = &router_state.key_store;
+            let result = match &key_set {
+                Some(key_set) => load_key_set(key_set)
+                    .and_then(|loaded| {
+                        key::check_id_token_keys(&loaded)?;
+                        key_store.replace_keys(loaded)
+                    })
+                    .and_then(|()| key::initialize_keys(&key_settings, key_store))
+                    .and_then(|()| key::rotate_keys_if_due(&key_settings, key_store))
+                    .and_then(|rotated| {
+                        if rotated {
+                            info!("Rotated signing keys");
+                            save_key_set(key_set, key_store)?;
+                        }
+                        Ok(())
+                    }),
+                None => key::rotate_keys_if_due(&key_settings, key_store).map(|rotated| {
+                    if rotated {
+                        info!("Rotated signing keys");
+                    }
+                }),
+            };
+
+            if let Err(error) = result {
+                error!("Could not rotate signing keys: {error}");
+            }
+        }
+    });
+}
@@ -1,2 +1,10 @@
+pub mod authorization;
+pub mod authorization_code;
 pub mod client;
+pub mod device_code;
+pub mod dpop;
+pub mod key;
 pub mod owner;
+pub mod pushed_request;
+pub mod refresh_token;
+pub mod session;
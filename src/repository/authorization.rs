@@ -0,0 +1,116 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+
+use crate::core::token::{Authorization, AuthorizationRepository};
+
+#[derive(Debug, Default)]
+pub struct MapAuthorizationRepository {
+    pub data: Mutex<HashMap<String, Authorization>>,
+}
+
+impl AuthorizationRepository for MapAuthorizationRepository {
+    fn create_authorization(&self, authorization: Authorization) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Authorization store is poisoned"))?;
+
+        let now = Utc::now();
+        data.retain(|_, stored_authorization| stored_authorization.expires > now);
+        data.insert(authorization.access_token.clone(), authorization);
+
+        Ok(())
+    }
+
+    fn read_authorization(&self, token: &str) -> Option<Authorization> {
+        self.data.lock().ok()?.get(token).cloned()
+    }
+
+    fn delete_authorization(&self, token: &str) -> Option<Authorization> {
+        self.data.lock().ok()?.remove(token)
+    }
+
+    fn delete_authorizations_by_refresh_token(&self, refresh_tokens: &[String]) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Authorization store is poisoned"))?;
+        data.retain(|_, stored_authorization| {
+            stored_authorization
+                .refresh_token
+                .as_ref()
+                .is_none_or(|refresh_token| !refresh_tokens.contains(refresh_token))
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use crate::core::token::{Authorization, AuthorizationRepository};
+
+    use super::MapAuthorizationRepository;
+
+    #[test]
+    fn test_create_authorization() {
+        let authorization_store = MapAuthorizationRepository::default();
+        let created = Utc::now();
+        authorization_store
+            .create_authorization(Authorization {
+                access_token: "2YotnFZFEjr1zCsicMWpAA".to_string(),
+                client_id: "abcd1234".to_string(),
+                scopes: vec!["read".to_string()],
+                owner: Some("alice".to_string()),
+                created,
+                expires: created + Duration::seconds(3600),
+                refresh_token: None,
+                jkt: None,
+            })
+            .unwrap();
+
+        let authorization = authorization_store
+            .read_authorization("2YotnFZFEjr1zCsicMWpAA")
+            .unwrap();
+        assert_eq!(authorization.client_id, "abcd1234");
+        assert_eq!(authorization.owner, Some("alice".to_string()));
+        assert!(authorization_store.read_authorization("unknown").is_none());
+    }
+
+    #[test]
+    fn test_delete_authorizations() {
+        let authorization_store = MapAuthorizationRepository::default();
+        let created = Utc::now();
+        for (access_token, refresh_token) in [
+            ("first", Some("tGzv3JOkF0XG5Qx2TlKWIA")),
+            ("second", Some("other")),
+            ("third", None),
+        ] {
+            authorization_store
+                .create_authorization(Authorization {
+                    access_token: access_token.to_string(),
+                    client_id: "abcd1234".to_string(),
+                    scopes: Vec::new(),
+                    owner: None,
+                    created,
+                    expires: created + Duration::seconds(3600),
+                    refresh_token: refresh_token.map(str::to_string),
+                    jkt: None,
+                })
+                .unwrap();
+        }
+
+        assert!(authorization_store.delete_authorization("third").is_some());
+        assert!(authorization_store.delete_authorization("third").is_none());
+
+        authorization_store
+            .delete_authorizations_by_refresh_token(&["tGzv3JOkF0XG5Qx2TlKWIA".to_string()])
+            .unwrap();
+        assert!(authorization_store.read_authorization("first").is_none());
+        assert!(authorization_store.read_authorization("second").is_some());
+    }
+}
@@ -0,0 +1,85 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+
+use crate::core::authorization::{AuthorizationCode, AuthorizationCodeRepository};
+
+#[derive(Debug, Default)]
+pub struct MapAuthorizationCodeRepository {
+    pub data: Mutex<HashMap<String, AuthorizationCode>>,
+}
+
+impl AuthorizationCodeRepository for MapAuthorizationCodeRepository {
+    fn create_authorization_code(&self, authorization_code: AuthorizationCode) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Authorization code store is poisoned"))?;
+
+        let now = Utc::now();
+        data.retain(|_, stored_code| stored_code.expires > now);
+        data.insert(authorization_code.code.clone(), authorization_code);
+
+        Ok(())
+    }
+
+    fn consume_authorization_code(&self, code: &str) -> Option<AuthorizationCode> {
+        self.data.lock().ok()?.remove(code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use crate::core::authorization::{
+        AuthorizationCode, AuthorizationCodeRepository, CodeChallengeMethod,
+    };
+
+    use super::MapAuthorizationCodeRepository;
+
+    fn authorization_code(code: &str, expires_in: i64) -> AuthorizationCode {
+        let created = Utc::now();
+        AuthorizationCode {
+            code: code.to_string(),
+            client_id: "abcd1234".to_string(),
+            redirect_uri: Some("https://example.com/auth_success".to_string()),
+            scopes: vec!["read".to_string()],
+            owner: "alice".to_string(),
+            code_challenge: None,
+            code_challenge_method: CodeChallengeMethod::Plain,
+            nonce: None,
+            auth_time: created,
+            sid: None,
+            expires: created + Duration::seconds(expires_in),
+        }
+    }
+
+    #[test]
+    fn test_consume_authorization_code_once() {
+        let code_store = MapAuthorizationCodeRepository::default();
+        code_store
+            .create_authorization_code(authorization_code("foobar", 600))
+            .unwrap();
+
+        let stored_code = code_store.consume_authorization_code("foobar").unwrap();
+        assert_eq!(stored_code.client_id, "abcd1234");
+        assert_eq!(stored_code.owner, "alice");
+        assert!(code_store.consume_authorization_code("foobar").is_none());
+    }
+
+    #[test]
+    fn test_create_authorization_code_purges_expired() {
+        let code_store = MapAuthorizationCodeRepository::default();
+        code_store
+            .create_authorization_code(authorization_code("expired", -1))
+            .unwrap();
+        code_store
+            .create_authorization_code(authorization_code("valid", 600))
+            .unwrap();
+
+        assert!(code_store.consume_authorization_code("expired").is_none());
+        assert!(code_store.consume_authorization_code("valid").is_some());
+    }
+}
@@ -1,29 +1,64 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Mutex};
 
+use anyhow::{Result, anyhow, bail};
 use serde::Deserialize;
 
-use crate::core::authorization::{Client, ClientRepository, ClientType};
+use crate::core::{
+    authorization::{
+        AccessTokenFormat, Client, ClientRepository, ClientType, TokenEndpointAuthMethod,
+    },
+    key::JwkSet,
+};
 
-#[derive(Clone, Debug)]
+#[derive(Default, Debug)]
 pub struct MapClientRepository {
-    pub data: HashMap<String, ClientData>,
+    pub data: Mutex<HashMap<String, ClientData>>,
 }
 
 impl MapClientRepository {
     pub fn try_from_toml(input: &str) -> Result<Self, toml::de::Error> {
         let data: HashMap<String, ClientData> = toml::from_str(input)?;
-        Ok(Self { data })
+        Ok(Self {
+            data: Mutex::new(data),
+        })
     }
 }
 
 impl ClientRepository for MapClientRepository {
+    fn create_client(&self, client: Client) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Client store is poisoned"))?;
+        if data.contains_key(&client.id) {
+            bail!("Client {} already exists", client.id);
+        }
+
+        data.insert(client.id.clone(), ClientData::from(client));
+        Ok(())
+    }
+
     fn read_client(&self, id: &str) -> Option<Client> {
-        self.data.get(id).map(|client_data| Client {
-            id: id.to_string(),
-            client_type: client_data.client_type.clone(),
-            redirect_uris: client_data.redirect_uris.clone(),
-            name: client_data.name.clone(),
-        })
+        let data = self.data.lock().ok()?;
+        data.get(id).map(|client_data| client_data.to_client(id))
+    }
+
+    fn update_client(&self, client: Client) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Client store is poisoned"))?;
+        let client_data = data
+            .get_mut(&client.id)
+            .ok_or_else(|| anyhow!("Client {} does not exist", client.id))?;
+
+        *client_data = ClientData::from(client);
+        Ok(())
+    }
+
+    fn delete_client(&self, id: &str) -> Option<Client> {
+        let mut data = self.data.lock().ok()?;
+        data.remove(id).map(|client_data| client_data.to_client(id))
     }
 }
 
@@ -32,31 +67,144 @@ pub struct ClientData {
     pub client_type: ClientType,
     pub redirect_uris: Vec<String>,
     pub name: String,
+    #[serde(default)]
+    pub require_pkce: bool,
+    /// Scopes the client may request, for itself with the client credentials grant or on behalf
+    /// of resource owners
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    /// Argon2 PHC string of the client secret
+    pub client_secret_hash: Option<String>,
+    pub token_endpoint_auth_method: Option<TokenEndpointAuthMethod>,
+    #[serde(default)]
+    pub access_token_format: AccessTokenFormat,
+    #[serde(default)]
+    pub post_logout_redirect_uris: Vec<String>,
+    pub backchannel_logout_uri: Option<String>,
+    #[serde(default)]
+    pub require_pushed_authorization_requests: bool,
+    pub jwks: Option<JwkSet>,
+    pub jwks_uri: Option<String>,
+    #[serde(default)]
+    pub request_uris: Vec<String>,
+    #[serde(default)]
+    pub allow_unsigned_request_objects: bool,
+    #[serde(default)]
+    pub dpop_bound_access_tokens: bool,
+    /// Argon2 PHC string of the registration access token of dynamically registered clients
+    pub registration_access_token_hash: Option<String>,
+    #[serde(default)]
+    pub self_registered: bool,
 }
 
+impl ClientData {
+    fn to_client(&self, id: &str) -> Client {
+        Client {
+            id: id.to_string(),
+            client_type: self.client_type.clone(),
+            redirect_uris: self.redirect_uris.clone(),
+            name: self.name.clone(),
+            require_pkce: self.require_pkce,
+            scopes: self.scopes.clone(),
+            secret_hash: self.client_secret_hash.clone(),
+            token_endpoint_auth_method: self.token_endpoint_auth_method.unwrap_or(
+                match self.client_type {
+                    ClientType::Confidential => TokenEndpointAuthMethod::ClientSecretBasic,
+                    ClientType::Public => TokenEndpointAuthMethod::None,
+                },
+            ),
+            access_token_format: self.access_token_format,
+            post_logout_redirect_uris: self.post_logout_redirect_uris.clone(),
+            backchannel_logout_uri: self.backchannel_logout_uri.clone(),
+            require_pushed_authorization_requests: self.require_pushed_authorization_requests,
+            jwks: self.jwks.clone(),
+            jwks_uri: self.jwks_uri.clone(),
+            request_uris: self.request_uris.clone(),
+            allow_unsigned_request_objects: self.allow_unsigned_request_objects,
+            dpop_bound_access_tokens: self.dpop_bound_access_tokens,
+            registration_access_token_hash: self.registration_access_token_hash.clone(),
+            self_registered: self.self_registered,
+        }
+    }
+}
+
+impl From<Client> for ClientData {
+    fn from(client: Client) -> Self {
+        Self {
+            client_type: client.client_type,
+            redirect_uris: client.redirect_uris,
+            name: client.name,
+            require_pkce: client.require_pkce,
+            scopes: client.scopes,
+            client_secret_hash: client.secret_hash,
+            token_endpoint_auth_method: Some(client.token_endpoint_auth_method),
+            access_token_format: client.access_token_format,
+            post_logout_redirect_uris: client.post_logout_redirect_uris,
+            backchannel_logout_uri: client.backchannel_logout_uri,
+            require_pushed_authorization_requests: client.require_pushed_authorization_requests,
+            jwks: client.jwks,
+            jwks_uri: client.jwks_uri,
+            request_uris: client.request_uris,
+            allow_unsigned_request_objects: client.allow_unsigned_request_objects,
+            dpop_bound_access_tokens: client.dpop_bound_access_tokens,
+            registration_access_token_hash: client.registration_access_token_hash,
+            self_registered: client.self_registered,
+        }
+    }
+}
+
+#[cfg(test)]
 #[derive(Clone, Debug)]
 pub struct TestClientRepository {
     pub client_ids: Vec<String>,
 }
 
+#[cfg(test)]
 impl ClientRepository for TestClientRepository {
     fn read_client(&self, id: &str) -> Option<Client> {
         if self.client_ids.contains(&id.to_string()) {
             Some(Client {
                 id: id.to_string(),
                 client_type: ClientType::Public,
-                redirect_uris: Vec::new(),
+                redirect_uris: vec!["https://client.example.com/cb".to_string()],
                 name: "Example Client".to_string(),
+                require_pkce: false,
+                scopes: Vec::new(),
+                secret_hash: None,
+                token_endpoint_auth_method: TokenEndpointAuthMethod::None,
+                access_token_format: AccessTokenFormat::Opaque,
+                post_logout_redirect_uris: Vec::new(),
+                backchannel_logout_uri: None,
+                require_pushed_authorization_requests: false,
+                jwks: None,
+                jwks_uri: None,
+                request_uris: Vec::new(),
+                allow_unsigned_request_objects: false,
+                dpop_bound_access_tokens: false,
+                registration_access_token_hash: None,
+                self_registered: false,
             })
         } else {
             None
         }
     }
+
+    fn create_client(&self, _client: Client) -> Result<()> {
+        bail!("Test client store is read-only")
+    }
+
+    fn update_client(&self, _client: Client) -> Result<()> {
+        bail!("Test client store is read-only")
+    }
+
+    fn delete_client(&self, _id: &str) -> Option<Client> {
+        None
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::core::authorization::{ClientRepository, ClientType};
+    use crate::core::authorization::{ClientRepository, ClientType, TokenEndpointAuthMethod};
 
     use super::MapClientRepository;
 
@@ -67,19 +215,59 @@ mod test {
             name = "TestClient"
             client_type = "public"
             redirect_uris = ["https://example.com/auth_success"]
+            require_pkce = true
         "#;
 
         let client_store = MapClientRepository::try_from_toml(input).unwrap();
-        assert_eq!(client_store.data.len(), 1);
+        assert_eq!(client_store.data.lock().unwrap().len(), 1);
 
         let test_client = client_store.read_client("abcd1234").unwrap();
         assert_eq!(test_client.id, "abcd1234");
         assert_eq!(test_client.name, "TestClient");
         assert_eq!(test_client.client_type, ClientType::Public);
+        assert!(test_client.require_pkce);
         assert_eq!(test_client.redirect_uris.len(), 1);
         assert_eq!(
             test_client.redirect_uris[0],
             "https://example.com/auth_success"
         );
+        assert_eq!(
+            test_client.token_endpoint_auth_method,
+            TokenEndpointAuthMethod::None
+        );
+    }
+
+    #[test]
+    fn test_try_from_toml_confidential() {
+        let input = r#"
+            [abcd1234]
+            name = "TestClient"
+            client_type = "confidential"
+            redirect_uris = ["https://example.com/auth_success"]
+            client_secret_hash = "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaA"
+            scopes = ["read", "write"]
+
+            [efgh5678]
+            name = "OtherClient"
+            client_type = "confidential"
+            redirect_uris = []
+            token_endpoint_auth_method = "client_secret_post"
+        "#;
+
+        let client_store = MapClientRepository::try_from_toml(input).unwrap();
+
+        let test_client = client_store.read_client("abcd1234").unwrap();
+        assert!(test_client.secret_hash.is_some());
+        assert_eq!(test_client.scopes, vec!["read", "write"]);
+        assert_eq!(
+            test_client.token_endpoint_auth_method,
+            TokenEndpointAuthMethod::ClientSecretBasic
+        );
+
+        let other_client = client_store.read_client("efgh5678").unwrap();
+        assert_eq!(
+            other_client.token_endpoint_auth_method,
+            TokenEndpointAuthMethod::ClientSecretPost
+        );
     }
 }
@@ -0,0 +1,164 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+
+use crate::core::device::{DeviceCode, DeviceCodeRepository, DeviceCodeStatus};
+
+#[derive(Debug, Default)]
+pub struct MapDeviceCodeRepository {
+    pub data: Mutex<HashMap<String, DeviceCode>>,
+}
+
+impl DeviceCodeRepository for MapDeviceCodeRepository {
+    fn create_device_code(&self, device_code: DeviceCode) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Device code store is poisoned"))?;
+
+        let now = Utc::now();
+        data.retain(|_, stored_code| stored_code.expires > now);
+        if data
+            .values()
+            .any(|stored_code| stored_code.user_code == device_code.user_code)
+        {
+            return Err(anyhow!("User code is already in use"));
+        }
+        data.insert(device_code.device_code.clone(), device_code);
+
+        Ok(())
+    }
+
+    fn read_device_code(&self, device_code: &str) -> Option<DeviceCode> {
+        self.data.lock().ok()?.get(device_code).cloned()
+    }
+
+    fn read_device_code_by_user_code(&self, user_code: &str) -> Option<DeviceCode> {
+        self.data
+            .lock()
+            .ok()?
+            .values()
+            .find(|stored_code| stored_code.user_code == user_code)
+            .cloned()
+    }
+
+    fn update_device_code_polling(
+        &self,
+        device_code: &str,
+        interval: i64,
+        last_polled: DateTime<Utc>,
+    ) -> Result<DeviceCode> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Device code store is poisoned"))?;
+
+        let Some(stored_code) = data.get_mut(device_code) else {
+            return Err(anyhow!("Device code does not exist"));
+        };
+        stored_code.interval = interval;
+        stored_code.last_polled = Some(last_polled);
+
+        Ok(stored_code.clone())
+    }
+
+    fn update_device_code_status(
+        &self,
+        device_code: &str,
+        status: DeviceCodeStatus,
+    ) -> Result<Option<DeviceCode>> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Device code store is poisoned"))?;
+
+        let Some(stored_code) = data
+            .get_mut(device_code)
+            .filter(|stored_code| stored_code.status == DeviceCodeStatus::Pending)
+        else {
+            return Ok(None);
+        };
+        stored_code.status = status;
+
+        Ok(Some(stored_code.clone()))
+    }
+
+    fn consume_device_code(&self, device_code: &str) -> Option<DeviceCode> {
+        self.data.lock().ok()?.remove(device_code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use crate::core::device::{DeviceCode, DeviceCodeRepository, DeviceCodeStatus};
+
+    use super::MapDeviceCodeRepository;
+
+    fn device_code(code: &str, user_code: &str) -> DeviceCode {
+        DeviceCode {
+            device_code: code.to_string(),
+            user_code: user_code.to_string(),
+            client_id: "abcd1234".to_string(),
+            scopes: vec!["read".to_string()],
+            status: DeviceCodeStatus::Pending,
+            interval: 5,
+            last_polled: None,
+            expires: Utc::now() + Duration::seconds(600),
+        }
+    }
+
+    #[test]
+    fn test_device_code_lifecycle() {
+        let device_code_store = MapDeviceCodeRepository::default();
+        device_code_store
+            .create_device_code(device_code("foobar", "WDJBMJHT"))
+            .unwrap();
+        assert!(
+            device_code_store
+                .create_device_code(device_code("other", "WDJBMJHT"))
+                .is_err()
+        );
+
+        let stored_code = device_code_store
+            .read_device_code_by_user_code("WDJBMJHT")
+            .unwrap();
+        assert_eq!(stored_code.device_code, "foobar");
+
+        let polled = Utc::now();
+        device_code_store
+            .update_device_code_polling("foobar", 10, polled)
+            .unwrap();
+        let stored_code = device_code_store
+            .update_device_code_status("foobar", DeviceCodeStatus::Denied)
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored_code.interval, 10);
+        assert_eq!(stored_code.last_polled, Some(polled));
+        assert!(
+            device_code_store
+                .update_device_code_status(
+                    "foobar",
+                    DeviceCodeStatus::Approved("alice".to_string())
+                )
+                .unwrap()
+                .is_none()
+        );
+
+        // Polling keeps the decision made in the meantime
+        let stored_code = device_code_store
+            .update_device_code_polling("foobar", 15, Utc::now())
+            .unwrap();
+        assert_eq!(stored_code.status, DeviceCodeStatus::Denied);
+
+        assert!(device_code_store.consume_device_code("foobar").is_some());
+        assert!(device_code_store.consume_device_code("foobar").is_none());
+        assert!(
+            device_code_store
+                .update_device_code_polling("foobar", 5, Utc::now())
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+
+use crate::core::dpop::{DpopNonce, DpopRepository};
+
+#[derive(Debug, Default)]
+pub struct MapDpopRepository {
+    pub proof_jtis: Mutex<HashMap<String, DateTime<Utc>>>,
+    pub nonces: Mutex<Vec<DpopNonce>>,
+}
+
+impl DpopRepository for MapDpopRepository {
+    fn create_proof_jti(&self, jti: &str, expires: DateTime<Utc>) -> Result<bool> {
+        let mut proof_jtis = self
+            .proof_jtis
+            .lock()
+            .map_err(|_| anyhow!("DPoP proof store is poisoned"))?;
+
+        let now = Utc::now();
+        proof_jtis.retain(|_, jti_expires| *jti_expires > now);
+        if proof_jtis.contains_key(jti) {
+            return Ok(false);
+        }
+        proof_jtis.insert(jti.to_string(), expires);
+
+        Ok(true)
+    }
+
+    fn create_nonce(&self, nonce: DpopNonce) -> Result<()> {
+        let mut nonces = self
+            .nonces
+            .lock()
+            .map_err(|_| anyhow!("DPoP nonce store is poisoned"))?;
+
+        let now = Utc::now();
+        nonces.retain(|stored_nonce| stored_nonce.expires > now);
+        nonces.push(nonce);
+
+        Ok(())
+    }
+
+    fn read_nonces(&self) -> Vec<DpopNonce> {
+        self.nonces
+            .lock()
+            .map(|nonces| nonces.clone())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,285 @@
+use std::sync::Mutex;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    jose::SigningKey,
+    key::{KeyRepository, KeyState, StoredKey},
+};
+
+#[derive(Debug, Default)]
+pub struct MapKeyRepository {
+    pub data: Mutex<Vec<StoredKey>>,
+}
+
+impl MapKeyRepository {
+    /// Imports PEM keys in order of preference. The first key becomes active, all others are
+    /// staged to take over at the following rotations.
+    pub fn from_signing_keys(signing_keys: Vec<SigningKey>) -> Self {
+        let now = Utc::now();
+        let keys = signing_keys
+            .into_iter()
+            .enumerate()
+            .map(|(index, signing_key)| StoredKey {
+                signing_key,
+                state: if index == 0 {
+                    KeyState::Active
+                } else {
+                    KeyState::Next
+                },
+                created: now,
+                activated: (index == 0).then_some(now),
+                expires: None,
+            })
+            .collect();
+
+        Self {
+            data: Mutex::new(keys),
+        }
+    }
+
+    pub fn try_from_toml(input: &str) -> Result<Self> {
+        let key_set: KeySetData = toml::from_str(input)?;
+        let keys = key_set
+            .keys
+            .into_iter()
+            .map(|key_data| {
+                Ok(StoredKey {
+                    signing_key: SigningKey::from_pem(&key_data.pem)?,
+                    state: key_data.state,
+                    created: key_data.created,
+                    activated: key_data.activated,
+                    expires: key_data.expires,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            data: Mutex::new(keys),
+        })
+    }
+
+    /// Serializes the key set including private keys, so it can be reloaded by `try_from_toml`
+    pub fn to_toml(&self) -> Result<String> {
+        let keys = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Key store is poisoned"))?
+            .iter()
+            .map(|key| {
+                Ok(KeyData {
+                    pem: key.signing_key.to_pem()?,
+                    state: key.state,
+                    created: key.created,
+                    activated: key.activated,
+                    expires: key.expires,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(toml::to_string(&KeySetData { keys })?)
+    }
+
+    /// Swaps in keys loaded from elsewhere, e.g. after a rotation forced from the command line
+    pub fn replace_keys(&self, other: MapKeyRepository) -> Result<()> {
+        let keys = other
+            .data
+            .into_inner()
+            .map_err(|_| anyhow!("Key store is poisoned"))?;
+        *self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Key store is poisoned"))? = keys;
+
+        Ok(())
+    }
+}
+
+impl KeyRepository for MapKeyRepository {
+    fn create_key(&self, key: StoredKey) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Key store is poisoned"))?;
+
+        if data
+            .iter()
+            .any(|stored_key| stored_key.signing_key.kid == key.signing_key.kid)
+        {
+            return Err(anyhow!("Key {} already exists", key.signing_key.kid));
+        }
+        data.push(key);
+
+        Ok(())
+    }
+
+    fn read_signing_key(&self) -> Option<SigningKey> {
+        self.data
+            .lock()
+            .ok()?
+            .iter()
+            .find(|key| key.state == KeyState::Active)
+            .map(|key| key.signing_key.clone())
+    }
+
+    fn read_keys(&self) -> Vec<StoredKey> {
+        self.data
+            .lock()
+            .map(|data| data.clone())
+            .unwrap_or_default()
+    }
+
+    fn rotate_keys(&self, next: SigningKey, retired_expires: DateTime<Utc>) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Key store is poisoned"))?;
+
+        let now = Utc::now();
+        data.retain(|key| key.expires.is_none_or(|expires| expires > now));
+
+        let mut activated = false;
+        for key in data.iter_mut() {
+            match key.state {
+                KeyState::Active => {
+                    key.state = KeyState::Retired;
+                    key.expires = Some(retired_expires);
+                }
+                KeyState::Next if !activated => {
+                    key.state = KeyState::Active;
+                    key.activated = Some(now);
+                    activated = true;
+                }
+                _ => (),
+            }
+        }
+
+        data.push(StoredKey {
+            signing_key: next,
+            state: if activated {
+                KeyState::Next
+            } else {
+                KeyState::Active
+            },
+            created: now,
+            activated: (!activated).then_some(now),
+            expires: None,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct KeySetData {
+    keys: Vec<KeyData>,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+struct KeyData {
+    /// PKCS#8 PEM of the private key
+    pem: String,
+    state: KeyState,
+    created: DateTime<Utc>,
+    activated: Option<DateTime<Utc>>,
+    expires: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use crate::core::{
+        jose::{Algorithm, SigningKey},
+        key::{KeyRepository, KeyState},
+    };
+
+    use super::MapKeyRepository;
+
+    fn create_key_store() -> MapKeyRepository {
+        MapKeyRepository::from_signing_keys(vec![
+            SigningKey::from_pem(include_str!("../core/testdata/ed25519.pem")).unwrap(),
+            SigningKey::from_pem(include_str!("../core/testdata/p256.pem")).unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_read_signing_key() {
+        assert!(MapKeyRepository::default().read_signing_key().is_none());
+
+        let key_store = create_key_store();
+        let keys = key_store.read_keys();
+        assert_eq!(keys[0].state, KeyState::Active);
+        assert_eq!(keys[1].state, KeyState::Next);
+        assert_eq!(
+            key_store.read_signing_key().unwrap().kid,
+            keys[0].signing_key.kid
+        );
+        assert!(
+            key_store
+                .create_key(keys[0].clone())
+                .is_err_and(|error| error.to_string().contains("already exists"))
+        );
+    }
+
+    #[test]
+    fn test_rotate_keys() {
+        let key_store = create_key_store();
+        let keys = key_store.read_keys();
+        let retired_expires = Utc::now() + Duration::seconds(3600);
+
+        let next = SigningKey::from_pem(include_str!("../core/testdata/rsa.pem")).unwrap();
+        key_store
+            .rotate_keys(next.clone(), retired_expires)
+            .unwrap();
+        let rotated = key_store.read_keys();
+        assert_eq!(rotated[0].state, KeyState::Retired);
+        assert_eq!(rotated[0].expires, Some(retired_expires));
+        assert_eq!(rotated[1].state, KeyState::Active);
+        assert_eq!(rotated[1].signing_key.kid, keys[1].signing_key.kid);
+        assert_eq!(rotated[2].state, KeyState::Next);
+        assert_eq!(rotated[2].signing_key.kid, next.kid);
+
+        key_store
+            .rotate_keys(
+                SigningKey::generate(Algorithm::EdDSA).unwrap(),
+                Utc::now() - Duration::seconds(1),
+            )
+            .unwrap();
+        key_store
+            .rotate_keys(
+                SigningKey::generate(Algorithm::EdDSA).unwrap(),
+                Utc::now() + Duration::seconds(3600),
+            )
+            .unwrap();
+        let kids: Vec<String> = key_store
+            .read_keys()
+            .into_iter()
+            .map(|key| key.signing_key.kid)
+            .collect();
+        assert!(!kids.contains(&keys[1].signing_key.kid));
+        assert!(kids.contains(&next.kid));
+    }
+
+    #[test]
+    fn test_toml_roundtrip() {
+        let key_store = create_key_store();
+        let input = key_store.to_toml().unwrap();
+        assert!(input.contains("state = \"active\""));
+
+        let loaded = MapKeyRepository::try_from_toml(&input).unwrap();
+        let keys = loaded.read_keys();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[1].state, KeyState::Next);
+        assert_eq!(
+            keys[0].signing_key.kid,
+            key_store.read_keys()[0].signing_key.kid
+        );
+
+        let other = MapKeyRepository::default();
+        other.replace_keys(loaded).unwrap();
+        assert_eq!(other.read_keys().len(), 2);
+    }
+}
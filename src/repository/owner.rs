@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::core::token::{Owner, OwnerRepository};
+
+#[derive(Clone, Default, Debug)]
+pub struct MapOwnerRepository {
+    pub data: HashMap<String, OwnerData>,
+}
+
+impl MapOwnerRepository {
+    pub fn try_from_toml(input: &str) -> Result<Self, toml::de::Error> {
+        let data: HashMap<String, OwnerData> = toml::from_str(input)?;
+        Ok(Self { data })
+    }
+}
+
+impl OwnerRepository for MapOwnerRepository {
+    fn read_owner(&self, name: &str) -> Option<Owner> {
+        self.data.get(name).map(|owner_data| Owner {
+            email: owner_data.email.clone(),
+            name: name.to_string(),
+            hash: owner_data.hash.clone(),
+        })
+    }
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct OwnerData {
+    pub email: String,
+    /// Argon2 PHC string of the password
+    pub hash: String,
+}
+
+#[cfg(test)]
+mod test {
+    use crate::core::token::OwnerRepository;
+
+    use super::MapOwnerRepository;
+
+    #[test]
+    fn test_try_from_toml() {
+        let input = r#"
+            [alice]
+            email = "alice@example.com"
+            hash = "$argon2id$v=19$m=19456,t=2,p=1$c2FsdHNhbHQ$aGFzaA"
+        "#;
+
+        let owner_store = MapOwnerRepository::try_from_toml(input).unwrap();
+        assert_eq!(owner_store.data.len(), 1);
+
+        let owner = owner_store.read_owner("alice").unwrap();
+        assert_eq!(owner.name, "alice");
+        assert_eq!(owner.email, "alice@example.com");
+        assert!(owner_store.read_owner("bob").is_none());
+    }
+}
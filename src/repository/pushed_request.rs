@@ -0,0 +1,34 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+
+use crate::core::pushed_authorization::{PushedRequest, PushedRequestRepository};
+
+#[derive(Debug, Default)]
+pub struct MapPushedRequestRepository {
+    pub data: Mutex<HashMap<String, PushedRequest>>,
+}
+
+impl PushedRequestRepository for MapPushedRequestRepository {
+    fn create_pushed_request(&self, pushed_request: PushedRequest) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Pushed request store is poisoned"))?;
+
+        let now = Utc::now();
+        data.retain(|_, stored_request| stored_request.expires > now);
+        data.insert(pushed_request.request_uri.clone(), pushed_request);
+
+        Ok(())
+    }
+
+    fn read_pushed_request(&self, request_uri: &str) -> Option<PushedRequest> {
+        self.data.lock().ok()?.get(request_uri).cloned()
+    }
+
+    fn delete_pushed_request(&self, request_uri: &str) -> Option<PushedRequest> {
+        self.data.lock().ok()?.remove(request_uri)
+    }
+}
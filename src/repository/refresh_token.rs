@@ -0,0 +1,123 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+
+use crate::core::token::{RefreshToken, RefreshTokenRepository};
+
+#[derive(Debug, Default)]
+pub struct MapRefreshTokenRepository {
+    pub data: Mutex<HashMap<String, RefreshToken>>,
+}
+
+impl RefreshTokenRepository for MapRefreshTokenRepository {
+    fn create_refresh_token(&self, refresh_token: RefreshToken) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Refresh token store is poisoned"))?;
+
+        let now = Utc::now();
+        data.retain(|_, stored_token| stored_token.expires > now);
+        data.insert(refresh_token.token.clone(), refresh_token);
+
+        Ok(())
+    }
+
+    fn read_refresh_token(&self, token: &str) -> Option<RefreshToken> {
+        self.data.lock().ok()?.get(token).cloned()
+    }
+
+    fn rotate_refresh_token(&self, token: &str) -> Option<RefreshToken> {
+        let mut data = self.data.lock().ok()?;
+        let refresh_token = data.get_mut(token)?;
+        let previous = refresh_token.clone();
+        refresh_token.rotated = true;
+
+        Some(previous)
+    }
+
+    fn revoke_refresh_token_family(&self, family: &str) -> Result<Vec<String>> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Refresh token store is poisoned"))?;
+
+        let mut revoked = Vec::new();
+        data.retain(|token, stored_token| {
+            let retain = stored_token.family != family;
+            if !retain {
+                revoked.push(token.clone());
+            }
+            retain
+        });
+
+        Ok(revoked)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use crate::core::token::{RefreshToken, RefreshTokenRepository};
+
+    use super::MapRefreshTokenRepository;
+
+    fn refresh_token(token: &str, family: &str) -> RefreshToken {
+        let created = Utc::now();
+        RefreshToken {
+            token: token.to_string(),
+            family: family.to_string(),
+            client_id: "abcd1234".to_string(),
+            scopes: vec!["read".to_string()],
+            owner: "alice".to_string(),
+            expires: created + Duration::seconds(3600),
+            family_expires: created + Duration::seconds(7200),
+            rotated: false,
+            jkt: None,
+        }
+    }
+
+    #[test]
+    fn test_rotate_refresh_token() {
+        let refresh_token_store = MapRefreshTokenRepository::default();
+        refresh_token_store
+            .create_refresh_token(refresh_token("tGzv3JOkF0XG5Qx2TlKWIA", "family"))
+            .unwrap();
+
+        let previous = refresh_token_store
+            .rotate_refresh_token("tGzv3JOkF0XG5Qx2TlKWIA")
+            .unwrap();
+        assert!(!previous.rotated);
+
+        let previous = refresh_token_store
+            .rotate_refresh_token("tGzv3JOkF0XG5Qx2TlKWIA")
+            .unwrap();
+        assert!(previous.rotated);
+    }
+
+    #[test]
+    fn test_revoke_refresh_token_family() {
+        let refresh_token_store = MapRefreshTokenRepository::default();
+        for (token, family) in [
+            ("first", "family"),
+            ("second", "family"),
+            ("other", "other"),
+        ] {
+            refresh_token_store
+                .create_refresh_token(refresh_token(token, family))
+                .unwrap();
+        }
+
+        let mut revoked = refresh_token_store
+            .revoke_refresh_token_family("family")
+            .unwrap();
+        revoked.sort();
+        assert_eq!(revoked, vec!["first", "second"]);
+
+        assert!(refresh_token_store.read_refresh_token("first").is_none());
+        assert!(refresh_token_store.read_refresh_token("second").is_none());
+        assert!(refresh_token_store.read_refresh_token("other").is_some());
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+
+use crate::core::authentication::{Session, SessionRepository};
+
+#[derive(Debug, Default)]
+pub struct MapSessionRepository {
+    pub data: Mutex<HashMap<String, Session>>,
+}
+
+impl SessionRepository for MapSessionRepository {
+    fn create_session(&self, session: Session) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Session store is poisoned"))?;
+
+        let now = Utc::now();
+        data.retain(|_, stored_session| stored_session.expires > now);
+        data.insert(session.id.clone(), session);
+
+        Ok(())
+    }
+
+    fn read_session(&self, id: &str) -> Option<Session> {
+        self.data.lock().ok()?.get(id).cloned()
+    }
+
+    fn delete_session(&self, id: &str) -> Option<Session> {
+        self.data.lock().ok()?.remove(id)
+    }
+
+    fn add_session_client(&self, id: &str, client_id: &str) -> Result<()> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Session store is poisoned"))?;
+        let session = data
+            .get_mut(id)
+            .ok_or_else(|| anyhow!("Session does not exist"))?;
+
+        if !session.clients.iter().any(|client| client == client_id) {
+            session.clients.push(client_id.to_string());
+        }
+
+        Ok(())
+    }
+
+    fn add_failed_user_code(&self, id: &str) -> Result<u32> {
+        let mut data = self
+            .data
+            .lock()
+            .map_err(|_| anyhow!("Session store is poisoned"))?;
+        let session = data
+            .get_mut(id)
+            .ok_or_else(|| anyhow!("Session does not exist"))?;
+
+        session.failed_user_codes += 1;
+
+        Ok(session.failed_user_codes)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{Duration, Utc};
+
+    use crate::core::authentication::{Session, SessionRepository};
+
+    use super::MapSessionRepository;
+
+    #[test]
+    fn test_create_session() {
+        let session_store = MapSessionRepository::default();
+        let authenticated = Utc::now();
+        session_store
+            .create_session(Session {
+                id: "foobar".to_string(),
+                sid: "08a5019c".to_string(),
+                csrf_token: "Ks7mT2pQ".to_string(),
+                failed_user_codes: 0,
+                owner: "alice".to_string(),
+                clients: Vec::new(),
+                authenticated,
+                expires: authenticated + Duration::seconds(3600),
+            })
+            .unwrap();
+
+        assert_eq!(session_store.read_session("foobar").unwrap().owner, "alice");
+        assert!(session_store.read_session("unknown").is_none());
+    }
+
+    #[test]
+    fn test_delete_session() {
+        let session_store = MapSessionRepository::default();
+        let authenticated = Utc::now();
+        session_store
+            .create_session(Session {
+                id: "foobar".to_string(),
+                sid: "08a5019c".to_string(),
+                csrf_token: "Ks7mT2pQ".to_string(),
+                failed_user_codes: 0,
+                owner: "alice".to_string(),
+                clients: Vec::new(),
+                authenticated,
+                expires: authenticated + Duration::seconds(3600),
+            })
+            .unwrap();
+
+        assert_eq!(
+            session_store.delete_session("foobar").unwrap().owner,
+            "alice"
+        );
+        assert!(session_store.read_session("foobar").is_none());
+        assert!(session_store.delete_session("foobar").is_none());
+    }
+
+    #[test]
+    fn test_add_session_client() {
+        let session_store = MapSessionRepository::default();
+        let authenticated = Utc::now();
+        session_store
+            .create_session(Session {
+                id: "foobar".to_string(),
+                sid: "08a5019c".to_string(),
+                csrf_token: "Ks7mT2pQ".to_string(),
+                failed_user_codes: 0,
+                owner: "alice".to_string(),
+                clients: Vec::new(),
+                authenticated,
+                expires: authenticated + Duration::seconds(3600),
+            })
+            .unwrap();
+
+        session_store
+            .add_session_client("foobar", "s6BhdRkqt3")
+            .unwrap();
+        session_store
+            .add_session_client("foobar", "s6BhdRkqt3")
+            .unwrap();
+        assert_eq!(
+            session_store.read_session("foobar").unwrap().clients,
+            vec!["s6BhdRkqt3"]
+        );
+        assert!(
+            session_store
+                .add_session_client("unknown", "s6BhdRkqt3")
+                .is_err()
+        );
+
+        assert_eq!(session_store.add_failed_user_code("foobar").unwrap(), 1);
+        assert_eq!(session_store.add_failed_user_code("foobar").unwrap(), 2);
+        assert!(session_store.add_failed_user_code("unknown").is_err());
+    }
+}